@@ -31,18 +31,22 @@
 //! A flatdata archive is introduced by `define_archive`. It defines two types
 //! `ArchiveName` and `ArchiveNameBuilder` for reading resp. writing data.
 
+use crate::checksum::VerifyingResourceStorage;
+use crate::compatibility::{CompatibleResourceStorage, SchemaAddition};
+use crate::copy::copy_resources;
 use crate::error::ResourceStorageError;
 use crate::storage::ResourceStorage;
 
 use std::fmt::Debug;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 pub use std::marker;
 
 /// A type in flatdata used for reading data.
 ///
-/// Each struct reference in generated code implements this trait.
-pub trait Ref: Clone + Copy + Debug + PartialEq {}
+/// Each struct reference in generated code implements this trait. References
+/// are read-only views on immutable bytes and can be sent to other threads.
+pub trait Ref: Clone + Copy + Debug + PartialEq + Send + Sync {}
 
 /// A mutable type in flatdata used for writing data.
 ///
@@ -96,7 +100,7 @@ pub type TypeIndex = u8;
 /// A type used as element of `MultiArrayView`.
 ///
 /// Implemented by an enum type.
-pub trait VariadicRef: Clone + Debug + PartialEq {
+pub trait VariadicRef: Clone + Debug + PartialEq + Send + Sync {
     /// Returns size in bytes of the current variant type.
     ///
     /// Since a variadic struct can contain types of different sized, this is a
@@ -144,8 +148,8 @@ pub trait Archive: Debug + Clone {
     /// storage for reading.
     ///
     /// When opening the archive, the schema of the archive and the schema
-    /// stored in the storage are compared as strings. If there is a
-    /// difference, an Error [`ResourceStorageError::WrongSignature`](enum.
+    /// stored in the storage are compared up to formatting, comments and the
+    /// order of declarations. If there is a difference, an Error [`ResourceStorageError::WrongSignature`](enum.
     /// ResourceStorageError.html) is returned containing a detailed diff
     /// of both schemata.
     ///
@@ -153,7 +157,163 @@ pub trait Archive: Debug + Clone {
     /// verified. If any non-optional resource is missing or has a wrong
     /// signature (unexpected schema), the operation will fail. Therefore,
     /// it is not possible to open partially written archive.
-    fn open(storage: Rc<ResourceStorage>) -> Result<Self, ResourceStorageError>;
+    ///
+    /// Errors are wrapped in [`ResourceStorageError::InArchive`](enum.
+    /// ResourceStorageError.html#variant.InArchive) with the path of the
+    /// archive or subarchive in which they occurred, e.g. `Graph/data`.
+    ///
+    /// The opened archive is `Send + Sync` and can be shared between threads.
+    fn open(storage: Arc<dyn ResourceStorage>) -> Result<Self, ResourceStorageError>;
+
+    /// Opens the archive with name `NAME` and schema `SCHEMA` in the given
+    /// storage for reading without opening its resources.
+    ///
+    /// Only the signature of the archive is verified when opening. Each
+    /// resource and subarchive is opened, verified and cached on the first
+    /// call of its accessor, which makes opening archives with many resources
+    /// cheap if only a few of them are used. Subarchives are opened lazily as
+    /// well, except that an optional subarchive is opened as a whole by
+    /// [`open`] on the first call of its accessor, since, as for archives
+    /// opened by [`open`], it is only present if it is valid.
+    ///
+    /// Since accessors do not return errors, an accessor of a non-optional
+    /// resource panics if the resource is missing or has a wrong signature;
+    /// an accessor of an optional resource returns `None`. Call
+    /// [`validate_all`] to open and verify all resources upfront.
+    ///
+    /// By default, the archive is opened eagerly by [`open`].
+    ///
+    /// [`open`]: #tymethod.open
+    /// [`validate_all`]: #method.validate_all
+    fn open_lazy(storage: Arc<dyn ResourceStorage>) -> Result<Self, ResourceStorageError> {
+        Self::open(storage)
+    }
+
+    /// Opens and verifies all resources and subarchives which were not opened
+    /// yet.
+    ///
+    /// After a successful call, no accessor of the archive panics. An archive
+    /// opened by [`open`] is already validated.
+    ///
+    /// [`open`]: #tymethod.open
+    fn validate_all(&self) -> Result<(), ResourceStorageError> {
+        Ok(())
+    }
+
+    /// Opens the archive as [`open_lazy`] does, without wrapping errors in
+    /// the archive path. Used for opening subarchives.
+    ///
+    /// [`open_lazy`]: #method.open_lazy
+    #[doc(hidden)]
+    fn open_lazy_without_context(
+        storage: Arc<dyn ResourceStorage>,
+    ) -> Result<Self, ResourceStorageError> {
+        Self::open_lazy(storage)
+    }
+
+    /// Validates the archive as [`validate_all`] does, without wrapping
+    /// errors in the archive path. Used for validating subarchives.
+    ///
+    /// [`validate_all`]: #method.validate_all
+    #[doc(hidden)]
+    fn validate_all_without_context(&self) -> Result<(), ResourceStorageError> {
+        self.validate_all()
+    }
+
+    /// Opens the archive as [`open`] does, and additionally verifies the data
+    /// of all resources against their stored checksums.
+    ///
+    /// Verification reads all data of the archive. If a resource is corrupt,
+    /// an Error [`ResourceStorageError::ChecksumMismatch`](enum.
+    /// ResourceStorageError.html) is returned. Resources without a stored
+    /// checksum are not verified. Cf. [`verify_archive`] for reporting all
+    /// corrupt resources at once.
+    ///
+    /// [`open`]: #tymethod.open
+    /// [`verify_archive`]: fn.verify_archive.html
+    fn open_verified(storage: Arc<dyn ResourceStorage>) -> Result<Self, ResourceStorageError> {
+        Self::open(VerifyingResourceStorage::new(storage, None))
+    }
+
+    /// Opens the archive as [`open`] does, but also accepts an archive
+    /// written with a compatible older schema.
+    ///
+    /// A stored schema is compatible if the expected schema only adds
+    ///
+    /// * optional resources to an archive, which are read as absent,
+    /// * fields to the end of a struct without changing its size in bytes,
+    ///   which are read as zero, since flatdata zeroes unused bits, and
+    /// * types to the end of a multivector.
+    ///
+    /// Returns the opened archive together with the sorted list of additions
+    /// missing in the stored schemas. Any other difference fails as in
+    /// [`open`].
+    ///
+    /// [`open`]: #tymethod.open
+    fn open_compatible(
+        storage: Arc<dyn ResourceStorage>,
+    ) -> Result<(Self, Vec<SchemaAddition>), ResourceStorageError> {
+        let additions = Arc::new(Mutex::new(Vec::new()));
+        let archive = Self::open(CompatibleResourceStorage::new(storage, additions.clone()))?;
+        let mut additions = additions.lock().unwrap().clone();
+        additions.sort();
+        additions.dedup();
+        Ok((archive, additions))
+    }
+
+    /// Returns the storage the archive was opened from.
+    fn storage(&self) -> &Arc<dyn ResourceStorage>;
+
+    /// Returns the paths of the signature and of all resources declared by
+    /// the archive and its subarchives relative to the storage of the
+    /// archive, e.g. `statistics/invariants`. Used for copying archives.
+    #[doc(hidden)]
+    fn resource_paths() -> Vec<String>;
+
+    /// Copies the archive including all of its subarchives byte-exact to
+    /// another storage, commits the storage, and opens the copy as [`open`]
+    /// does.
+    ///
+    /// Only the signature and the resources declared by the archive are
+    /// copied, each together with its schema and checksum; other data in
+    /// the storage of the archive, e.g. further archives in the same
+    /// directory, is not. Absent optional resources are skipped. If a
+    /// resource cannot be copied, the returned error names its path, e.g.
+    /// `Graph/statistics` and `invariants`. Cf. [`copy_archive`] for copying
+    /// everything in a storage.
+    ///
+    /// [`open`]: #tymethod.open
+    /// [`copy_archive`]: fn.copy_archive.html
+    fn copy_to(&self, storage: Arc<dyn ResourceStorage>) -> Result<Self, ResourceStorageError> {
+        copy_resources(&**self.storage(), &*storage, &Self::resource_paths(), false)
+            .map_err(|e| e.in_archive(Self::NAME))?;
+        storage
+            .commit()
+            .map_err(|e| ResourceStorageError::from_io_error(e, Self::NAME.into()))?;
+        Self::open(storage)
+    }
+
+    /// Copies the archive as [`copy_to`] does, verifies that the copy reads
+    /// back byte-exact, and opens the copy as [`open_verified`] does.
+    ///
+    /// The target storage is only committed if the verification succeeds;
+    /// otherwise, an IO error of kind [`InvalidData`] is returned for the
+    /// first differing resource.
+    ///
+    /// [`copy_to`]: #method.copy_to
+    /// [`open_verified`]: #method.open_verified
+    /// [`InvalidData`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidData
+    fn copy_to_verified(
+        &self,
+        storage: Arc<dyn ResourceStorage>,
+    ) -> Result<Self, ResourceStorageError> {
+        copy_resources(&**self.storage(), &*storage, &Self::resource_paths(), true)
+            .map_err(|e| e.in_archive(Self::NAME))?;
+        storage
+            .commit()
+            .map_err(|e| ResourceStorageError::from_io_error(e, Self::NAME.into()))?;
+        Self::open_verified(storage)
+    }
 }
 
 /// A flatdata archive builder for serializing data.
@@ -180,11 +340,14 @@ pub trait ArchiveBuilder: Clone {
     /// * `start_vector`/`finish_vector`
     /// * `start_multivector`/`finish_multivector`.
     ///
+    /// Finally, the generated method `finish` checks that all non-optional
+    /// resources were written and returns the opened archive.
+    ///
     /// For more information about how to write resources, cf. the
     /// [coappearances] example.
     ///
     /// [coappearances]: https://github.com/boxdot/flatdata-rs/blob/master/tests/coappearances_test.rs#L159
-    fn new(storage: Rc<ResourceStorage>) -> Result<Self, ResourceStorageError>;
+    fn new(storage: Arc<dyn ResourceStorage>) -> Result<Self, ResourceStorageError>;
 }
 
 //
@@ -207,8 +370,7 @@ macro_rules! define_struct {
     {
         #[derive(Clone, Copy)]
         pub struct $name<'a> {
-            data: *const u8,
-            _phantom: $crate::marker::PhantomData<&'a u8>,
+            data: &'a [u8],
         }
 
         #[derive(Clone)]
@@ -224,7 +386,7 @@ macro_rules! define_struct {
             #[inline]
             fn create(data : &'a[u8]) -> Self::Item
             {
-                Self::Item{ data : data.as_ptr(), _phantom : $crate::marker::PhantomData }
+                Self::Item{ data }
             }
 
             type ItemMut = $name_mut<'a>;
@@ -232,14 +394,14 @@ macro_rules! define_struct {
             #[inline]
             fn create_mut(data: &'a mut[u8]) -> Self::ItemMut
             {
-                Self::ItemMut{ data : data.as_mut_ptr(), _phantom : $crate::marker::PhantomData }
+                Self::ItemMut{ data }
             }
         }
 
         impl<'a> $name<'a> {
             #[inline]
             $(pub fn $field(&self) -> $type {
-                let value = read_bytes!($primitive_type, self.data, $offset, $bit_size);
+                let value = read_bytes!($primitive_type, self.data.as_ptr(), $offset, $bit_size);
                 unsafe { ::std::mem::transmute::<$primitive_type, $type>(value) }
             })*
         }
@@ -263,22 +425,19 @@ macro_rules! define_struct {
         impl<'a> $crate::Ref for $name<'a> {}
 
         pub struct $name_mut<'a> {
-            data: *mut u8,
-            _phantom: $crate::marker::PhantomData<&'a u8>,
+            data: &'a mut [u8],
         }
 
         impl<'a> $name_mut<'a> {
             #[inline]
             $(pub fn $field(&self) -> $type {
-                let value = read_bytes!($primitive_type, self.data, $offset, $bit_size);
+                let value = read_bytes!($primitive_type, self.data.as_ptr(), $offset, $bit_size);
                 unsafe { ::std::mem::transmute::<$primitive_type, $type>(value) }
             })*
 
             #[inline]
             $(pub fn $field_setter(&mut self, value: $type) {
-                let buffer = unsafe {
-                    ::std::slice::from_raw_parts_mut(self.data, $size_in_bytes)
-                };
+                let buffer = &mut self.data[..$size_in_bytes];
                 write_bytes!($type; value, buffer, $offset, $bit_size)
             })*
 
@@ -289,13 +448,13 @@ macro_rules! define_struct {
 
             #[inline]
             pub fn into_ref(self) -> $name<'a> {
-                $name{ data : self.data, _phantom : $crate::marker::PhantomData }
+                $name{ data : self.data }
             }
         }
 
         impl<'a> ::std::fmt::Debug for $name_mut<'a> {
             fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-                $name{ data : self.data, _phantom : $crate::marker::PhantomData }.fmt( f )
+                $name{ data : &*self.data }.fmt( f )
             }
         }
 
@@ -431,17 +590,44 @@ macro_rules! static_if {
     };
 }
 
-/// Depending on the provided flag returns the result or make it an `Option`.
+/// Loads a resource into a `OnceLock` cache of an archive.
 ///
-/// The flag is `true`, if the resource is optional.
+/// In `lazy` mode the cached resource is returned, and a failure to load a
+/// non-optional resource panics. In `eager` mode a failure to load a
+/// non-optional resource is returned as error. A failure to load an optional
+/// resource results in `None` in both modes.
 #[doc(hidden)]
 #[macro_export]
-macro_rules! check_resource {
-    ($res:expr,false) => {
-        $res?
+macro_rules! load_resource {
+    (lazy, $cell:expr, $res:expr, $name:expr, false) => {
+        $cell.get_or_init(|| match $res {
+            Ok(x) => x,
+            Err(e) => panic!("failed to open resource {}: {}", $name, e),
+        })
+    };
+    (lazy, $cell:expr, $res:expr, $name:expr, true) => {
+        $cell.get_or_init(|| $res.ok())
+    };
+    (eager, $cell:expr, $res:expr, false) => {
+        if $cell.get().is_none() {
+            let _ = $cell.set($res?);
+        }
+    };
+    (eager, $cell:expr, $res:expr, true) => {
+        $cell.get_or_init(|| $res.ok());
     };
-    ($res:expr,true) => {
-        $res.ok()
+}
+
+/// Formats a resource of an archive for debugging if it is already loaded
+/// into its `OnceLock` cache; formatting never loads a resource.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! debug_loaded {
+    ($cell:expr, $value:expr) => {
+        match $cell.get() {
+            Some(_) => format!("{:?}", $value),
+            None => String::from("<not loaded>"),
+        }
     };
 }
 
@@ -473,19 +659,23 @@ macro_rules! define_archive {
 
         #[derive(Clone)]
         pub struct $name {
-            _storage: ::std::rc::Rc<$crate::ResourceStorage>
-            $(,$struct_resource: opt!($crate::MemoryDescriptor, $is_optional_struct))*
-            $(,$vector_resource: opt!($crate::MemoryDescriptor, $is_optional_vector))*
-            $(,$multivector_resource: (
-                opt!($crate::MemoryDescriptor, $is_optional_multivector),
-                opt!($crate::MemoryDescriptor, $is_optional_multivector)))*
-            $(,$raw_data_resource: opt!($crate::MemoryDescriptor, $is_optional_raw_data))*
-            $(,$subarchive_resource: opt!($subarchive_type, $is_optional_subarchive))*
+            _storage: ::std::sync::Arc<dyn $crate::ResourceStorage>
+            $(,$struct_resource: ::std::sync::OnceLock<
+                opt!($crate::MemoryDescriptor, $is_optional_struct)>)*
+            $(,$vector_resource: ::std::sync::OnceLock<
+                opt!($crate::MemoryDescriptor, $is_optional_vector)>)*
+            $(,$multivector_resource: ::std::sync::OnceLock<
+                opt!(($crate::MemoryDescriptor, $crate::MemoryDescriptor),
+                    $is_optional_multivector)>)*
+            $(,$raw_data_resource: ::std::sync::OnceLock<
+                opt!($crate::MemoryDescriptor, $is_optional_raw_data)>)*
+            $(,$subarchive_resource: ::std::sync::OnceLock<
+                opt!($subarchive_type, $is_optional_subarchive)>)*
         }
 
         impl $name {
             fn read_resource<R>(
-                storage: &$crate::ResourceStorage,
+                storage: &dyn $crate::ResourceStorage,
                 name: &str,
                 schema: &str,
             ) -> Result<R, $crate::ResourceStorageError>
@@ -495,57 +685,87 @@ macro_rules! define_archive {
                 storage.read(name, schema).map(|x|R::from($crate::MemoryDescriptor::new(&x)))
             }
 
+            fn read_multivector(
+                storage: &dyn $crate::ResourceStorage,
+                index_name: &str,
+                name: &str,
+                schema: &str,
+            ) -> Result<
+                ($crate::MemoryDescriptor, $crate::MemoryDescriptor),
+                $crate::ResourceStorageError,
+            > {
+                let index = Self::read_resource(storage, index_name, &format!("index({})", schema))?;
+                let data = Self::read_resource(storage, name, schema)?;
+                Ok((index, data))
+            }
+
             $(pub fn $struct_resource(&self) -> opt!(
                 <$struct_type as $crate::Struct>::Item, $is_optional_struct)
             {
+                let mem_desc = load_resource!(lazy, self.$struct_resource,
+                    Self::read_resource(
+                        &*self._storage, stringify!($struct_resource), $struct_schema),
+                    stringify!($struct_resource), $is_optional_struct);
                 static_if!($is_optional_struct, {
-                    self.$struct_resource.as_ref().map(|mem_desc| {
+                    mem_desc.as_ref().map(|mem_desc| {
                         <$struct_type as $crate::Struct>::create(&unsafe{mem_desc.as_bytes()})
                     })
                 }, {
-                    <$struct_type as $crate::Struct>::create(&unsafe{self.$struct_resource.as_bytes()})
+                    <$struct_type as $crate::Struct>::create(&unsafe{mem_desc.as_bytes()})
                 })
             })*
 
             $(pub fn $vector_resource(&self) -> opt!(
                 $crate::ArrayView<$element_type>, $is_optional_vector)
             {
+                let mem_desc = load_resource!(lazy, self.$vector_resource,
+                    Self::read_resource(
+                        &*self._storage, stringify!($vector_resource), $element_schema),
+                    stringify!($vector_resource), $is_optional_vector);
                 static_if!($is_optional_vector, {
-                    self.$vector_resource.as_ref().map(|x|$crate::ArrayView::new(unsafe{x.as_bytes()}))
+                    mem_desc.as_ref().map(|x|$crate::ArrayView::new(unsafe{x.as_bytes()}))
                 }, {
-                    $crate::ArrayView::new(&unsafe{self.$vector_resource.as_bytes()})
+                    $crate::ArrayView::new(&unsafe{mem_desc.as_bytes()})
                 })
             })*
 
             $(pub fn $multivector_resource(&self) -> opt!(
                 $crate::MultiArrayView<$index_type, $variadic_type>, $is_optional_multivector)
             {
+                let mem_descs = load_resource!(lazy, self.$multivector_resource,
+                    Self::read_multivector(
+                        &*self._storage,
+                        stringify!($multivector_resource_index),
+                        stringify!($multivector_resource),
+                        $variadic_type_schema),
+                    stringify!($multivector_resource), $is_optional_multivector);
                 static_if!($is_optional_multivector, {
-                    let index_mem_desc = &self.$multivector_resource.0.as_ref();
-                    let res_mem_desc = &self.$multivector_resource.1.as_ref();
-                    index_mem_desc
-                        .map(|x|$crate::ArrayView::new(unsafe{x.as_bytes()}))
-                        .and_then(move |index| {
-                            res_mem_desc.map(move |mem_desc| {
-                                $crate::MultiArrayView::new(index, unsafe{mem_desc.as_bytes()})
-                            })
-                        })
+                    mem_descs.as_ref().map(|(index, data)| {
+                        $crate::MultiArrayView::new(
+                            $crate::ArrayView::new(unsafe{index.as_bytes()}),
+                            unsafe{data.as_bytes()},
+                        )
+                    })
                 }, {
                     $crate::MultiArrayView::new(
-                        $crate::ArrayView::new(&unsafe{self.$multivector_resource.0.as_bytes()}),
-                        &unsafe{self.$multivector_resource.1.as_bytes()},
+                        $crate::ArrayView::new(&unsafe{mem_descs.0.as_bytes()}),
+                        &unsafe{mem_descs.1.as_bytes()},
                     )
                 })
             })*
 
             $(pub fn $raw_data_resource(&self) -> opt!(&[u8], $is_optional_raw_data) {
+                let mem_desc = load_resource!(lazy, self.$raw_data_resource,
+                    Self::read_resource(
+                        &*self._storage, stringify!($raw_data_resource), $raw_data_schema),
+                    stringify!($raw_data_resource), $is_optional_raw_data);
                 static_if!($is_optional_raw_data, {
-                    self.$raw_data_resource.as_ref().map(|mem_desc| {
+                    mem_desc.as_ref().map(|mem_desc| {
                         unsafe { mem_desc.as_bytes() }
                     })
                 }, {
                     unsafe {
-                        self.$raw_data_resource.as_bytes()
+                        mem_desc.as_bytes()
                     }
                 })
             })*
@@ -553,9 +773,151 @@ macro_rules! define_archive {
             $(pub fn $subarchive_resource(&self) -> &opt!(
                 $subarchive_type, $is_optional_subarchive)
             {
-                &self.$subarchive_resource
+                let storage = self._storage.subdir(stringify!($subarchive_resource));
+                load_resource!(lazy, self.$subarchive_resource,
+                    static_if!($is_optional_subarchive, {
+                        // an optional subarchive is only present if it is valid as a whole
+                        <$subarchive_type as $crate::Archive>::open(storage)
+                    }, {
+                        <$subarchive_type as $crate::Archive>::open_lazy(storage)
+                    }),
+                    stringify!($subarchive_resource), $is_optional_subarchive)
             })*
 
+            /// Hints the expected access pattern of a resource of this
+            /// archive to the operating system.
+            ///
+            /// Hints for a multivector apply to its index as well, hints for a
+            /// subarchive apply to all of its resources. Storages which do not
+            /// memory map resources ignore hints.
+            pub fn advise(&self, resource_name: &str, advice: $crate::Advice)
+                -> ::std::io::Result<()>
+            {
+                match resource_name {
+                    $(stringify!($struct_resource) => self._storage.advise(resource_name, advice),)*
+                    $(stringify!($vector_resource) => self._storage.advise(resource_name, advice),)*
+                    $(stringify!($multivector_resource) => {
+                        self._storage.advise(stringify!($multivector_resource_index), advice)?;
+                        self._storage.advise(resource_name, advice)
+                    })*
+                    $(stringify!($raw_data_resource) => self._storage.advise(resource_name, advice),)*
+                    $(stringify!($subarchive_resource) => static_if!($is_optional_subarchive, {
+                        match self.$subarchive_resource() {
+                            Some(subarchive) => subarchive.advise_all(advice),
+                            None => Ok(()),
+                        }
+                    }, {
+                        self.$subarchive_resource().advise_all(advice)
+                    }),)*
+                    _ => Err(::std::io::Error::new(
+                        ::std::io::ErrorKind::InvalidInput,
+                        format!("{} has no resource {}", stringify!($name), resource_name),
+                    )),
+                }
+            }
+
+            /// Hints the expected access pattern of all present resources of
+            /// this archive and its subarchives to the operating system.
+            pub fn advise_all(&self, advice: $crate::Advice) -> ::std::io::Result<()> {
+                let names: &[&str] = &[
+                    $(stringify!($struct_resource),)*
+                    $(stringify!($vector_resource),)*
+                    $(stringify!($multivector_resource),)*
+                    $(stringify!($raw_data_resource),)*
+                ];
+                for name in names {
+                    if self._storage.exists(name) {
+                        self.advise(name, advice)?;
+                    }
+                }
+                $(self.advise(stringify!($subarchive_resource), advice)?;)*
+                Ok(())
+            }
+
+            /// Starts warming up all present resources of this archive and its
+            /// subarchives in a background thread, reading at most
+            /// `rate_limit` bytes per second if set.
+            ///
+            /// Subarchives of a lazily opened archive are only warmed up once
+            /// they were accessed.
+            ///
+            /// Cf. [`WarmUp`](struct.WarmUp.html).
+            pub fn warm_up(&self, rate_limit: Option<u64>) -> $crate::WarmUp {
+                $crate::WarmUp::new(self.warm_up_resources(), rate_limit)
+            }
+
+            #[doc(hidden)]
+            pub fn warm_up_resources(&self)
+                -> Vec<(::std::sync::Arc<dyn $crate::ResourceStorage>, String)>
+            {
+                let names: &[&str] = &[
+                    $(stringify!($struct_resource),)*
+                    $(stringify!($vector_resource),)*
+                    $(stringify!($multivector_resource_index),
+                        stringify!($multivector_resource),)*
+                    $(stringify!($raw_data_resource),)*
+                ];
+                let mut resources: Vec<_> = names
+                    .iter()
+                    .filter(|name| self._storage.exists(name))
+                    .map(|name| (self._storage.clone(), name.to_string()))
+                    .collect();
+                $(
+                    // subarchives are not opened here, if they were not yet
+                    let subarchive = static_if!($is_optional_subarchive, {
+                        self.$subarchive_resource.get().and_then(|x| x.as_ref())
+                    }, {
+                        self.$subarchive_resource.get()
+                    });
+                    if let Some(subarchive) = subarchive {
+                        resources.extend(subarchive.warm_up_resources());
+                    }
+                )*
+                resources
+            }
+
+            /// Reports how many pages of each memory mapped resource of this
+            /// archive and its subarchives are resident in memory.
+            ///
+            /// Subarchives of a lazily opened archive are only reported once
+            /// they were accessed.
+            #[cfg(target_os = "linux")]
+            pub fn residency(&self) -> ::std::io::Result<$crate::ResidencyReport> {
+                let mut report = $crate::ResidencyReport::default();
+                let names: &[&str] = &[
+                    $(stringify!($struct_resource),)*
+                    $(stringify!($vector_resource),)*
+                    $(stringify!($multivector_resource_index),
+                        stringify!($multivector_resource),)*
+                    $(stringify!($raw_data_resource),)*
+                ];
+                for name in names {
+                    if !self._storage.exists(name) {
+                        continue;
+                    }
+                    if let Some(residency) = self._storage.residency(name)? {
+                        report.resources.insert(name.to_string(), residency);
+                    }
+                }
+                $(
+                    // subarchives are not opened here, if they were not yet
+                    let subarchive = static_if!($is_optional_subarchive, {
+                        self.$subarchive_resource.get().and_then(|x| x.as_ref())
+                    }, {
+                        self.$subarchive_resource.get()
+                    });
+                    if let Some(subarchive) = subarchive {
+                        for (name, residency) in subarchive.residency()?.resources {
+                            report.resources.insert(
+                                format!("{}/{}", stringify!($subarchive_resource), name),
+                                residency,
+                            );
+                        }
+                    }
+                )*
+                Ok(report)
+            }
+
             fn signature_name(archive_name: &str) -> String {
                 format!("{}.archive", archive_name)
             }
@@ -566,18 +928,21 @@ macro_rules! define_archive {
                 write!(f,
                     concat!(stringify!($name), " {{ ",
                         intersperse!(""
-                            $(, concat!(stringify!($struct_resource), ": {:?}"))*
-                            $(, concat!(stringify!($vector_resource), ": {:?}"))*
-                            $(, concat!(stringify!($multivector_resource), ": {:?}"))*
-                            $(, concat!(stringify!($raw_data_resource), ": {:?}"))*
-                            $(, concat!(stringify!($subarchive_resource), ": {:?}"))*
+                            $(, concat!(stringify!($struct_resource), ": {}"))*
+                            $(, concat!(stringify!($vector_resource), ": {}"))*
+                            $(, concat!(stringify!($multivector_resource), ": {}"))*
+                            $(, concat!(stringify!($raw_data_resource), ": {}"))*
+                            $(, concat!(stringify!($subarchive_resource), ": {}"))*
                         ),
                     " }}"),
-                    $(self.$struct_resource(), )*
-                    $(self.$vector_resource(), )*
-                    $(self.$multivector_resource(), )*
-                    $(self.$raw_data_resource, )*
-                    $(self.$subarchive_resource, )*
+                    $(debug_loaded!(self.$struct_resource, self.$struct_resource()), )*
+                    $(debug_loaded!(self.$vector_resource, self.$vector_resource()), )*
+                    $(debug_loaded!(
+                        self.$multivector_resource, self.$multivector_resource()), )*
+                    $(debug_loaded!(
+                        self.$raw_data_resource, self.$raw_data_resource.get().unwrap()), )*
+                    $(debug_loaded!(
+                        self.$subarchive_resource, self.$subarchive_resource.get().unwrap()), )*
                 )
             }
         }
@@ -586,74 +951,115 @@ macro_rules! define_archive {
             const NAME: &'static str = stringify!($name);
             const SCHEMA: &'static str = $archive_schema;
 
-            fn open(storage: ::std::rc::Rc<$crate::ResourceStorage>)
+            fn storage(&self) -> &::std::sync::Arc<dyn $crate::ResourceStorage> {
+                &self._storage
+            }
+
+            fn resource_paths() -> Vec<String> {
+                let mut paths = vec![Self::signature_name(Self::NAME)];
+                paths.extend([
+                    $(stringify!($struct_resource),)*
+                    $(stringify!($vector_resource),)*
+                    $(stringify!($multivector_resource_index),
+                        stringify!($multivector_resource),)*
+                    $(stringify!($raw_data_resource),)*
+                ].iter().map(|name: &&str| name.to_string()));
+                $(
+                    paths.extend(
+                        <$subarchive_type as $crate::Archive>::resource_paths()
+                            .into_iter()
+                            .map(|path| format!("{}/{}", stringify!($subarchive_resource), path)),
+                    );
+                )*
+                paths
+            }
+
+            fn open(storage: ::std::sync::Arc<dyn $crate::ResourceStorage>)
                 -> ::std::result::Result<Self, $crate::ResourceStorageError>
             {
-                $(let $struct_resource;)*
-                $(let $vector_resource;)*
-                $(let $multivector_resource_index;
-                  let $multivector_resource;)*
-                $(let $raw_data_resource;)*
-                {
-                    storage.read(&Self::signature_name(Self::NAME), Self::SCHEMA)?;
-
-                    $($struct_resource = check_resource!(
-                        Self::read_resource(
-                            &*storage,
-                            stringify!($struct_resource),
-                            $struct_schema
-                        ), $is_optional_struct);
-                    )*
-
-                    $($vector_resource = check_resource!(
-                        Self::read_resource(
-                            &*storage,
-                            stringify!($vector_resource),
-                            $element_schema), $is_optional_vector);
-                    )*
-
-                    $($multivector_resource_index = check_resource!(
-                        Self::read_resource(
-                            &*storage,
-                            stringify!($multivector_resource_index),
-                            &format!("index({})", $variadic_type_schema)),
-                        $is_optional_multivector);
-                    $multivector_resource = check_resource!(
-                        Self::read_resource(
-                            &*storage,
-                            stringify!($multivector_resource),
-                            $variadic_type_schema), $is_optional_multivector);
-                    )*
-
-                    $($raw_data_resource = check_resource!(
-                        Self::read_resource(
-                            &*storage,
-                            stringify!($raw_data_resource),
-                            $raw_data_schema), $is_optional_raw_data);
-                    )*
-                }
-                $(
-                let $subarchive_resource = check_resource!(
-                    $subarchive_type::open(
-                        storage.subdir(&stringify!($subarchive_resource))),
-                    $is_optional_subarchive
-                );)*
+                let archive = Self::open_lazy_without_context(storage)
+                    .map_err(|e| e.in_archive(Self::NAME))?;
+                archive.validate_all()?;
+                Ok(archive)
+            }
+
+            fn open_lazy(storage: ::std::sync::Arc<dyn $crate::ResourceStorage>)
+                -> ::std::result::Result<Self, $crate::ResourceStorageError>
+            {
+                Self::open_lazy_without_context(storage).map_err(|e| e.in_archive(Self::NAME))
+            }
+
+            fn validate_all(&self) -> ::std::result::Result<(), $crate::ResourceStorageError> {
+                self.validate_all_without_context().map_err(|e| e.in_archive(Self::NAME))
+            }
+
+            fn open_lazy_without_context(
+                storage: ::std::sync::Arc<dyn $crate::ResourceStorage>,
+            ) -> ::std::result::Result<Self, $crate::ResourceStorageError> {
+                storage.read(
+                    &Self::signature_name(Self::NAME),
+                    Self::SCHEMA,
+                )?;
                 Ok(Self {
                     _storage: storage
-                    $(,$struct_resource)*
-                    $(,$vector_resource)*
-                    $(,$multivector_resource: (
-                        $multivector_resource_index,
-                        $multivector_resource))*
-                    $(,$raw_data_resource)*
-                    $(,$subarchive_resource)*
+                    $(,$struct_resource: ::std::sync::OnceLock::new())*
+                    $(,$vector_resource: ::std::sync::OnceLock::new())*
+                    $(,$multivector_resource: ::std::sync::OnceLock::new())*
+                    $(,$raw_data_resource: ::std::sync::OnceLock::new())*
+                    $(,$subarchive_resource: ::std::sync::OnceLock::new())*
                 })
             }
+
+            fn validate_all_without_context(&self)
+                -> ::std::result::Result<(), $crate::ResourceStorageError>
+            {
+                $(load_resource!(eager, self.$struct_resource,
+                    Self::read_resource(
+                        &*self._storage, stringify!($struct_resource), $struct_schema),
+                    $is_optional_struct);)*
+                $(load_resource!(eager, self.$vector_resource,
+                    Self::read_resource(
+                        &*self._storage, stringify!($vector_resource), $element_schema),
+                    $is_optional_vector);)*
+                $(load_resource!(eager, self.$multivector_resource,
+                    Self::read_multivector(
+                        &*self._storage,
+                        stringify!($multivector_resource_index),
+                        stringify!($multivector_resource),
+                        $variadic_type_schema),
+                    $is_optional_multivector);)*
+                $(load_resource!(eager, self.$raw_data_resource,
+                    Self::read_resource(
+                        &*self._storage, stringify!($raw_data_resource), $raw_data_schema),
+                    $is_optional_raw_data);)*
+                $(static_if!($is_optional_subarchive, {
+                    // an optional subarchive is only present if it is valid as a whole
+                    let subarchive = self.$subarchive_resource.get_or_init(|| {
+                        <$subarchive_type as $crate::Archive>::open(
+                            self._storage.subdir(stringify!($subarchive_resource))).ok()
+                    });
+                    if let Some(subarchive) = subarchive {
+                        $crate::Archive::validate_all_without_context(subarchive)
+                            .map_err(|e| e.in_archive(stringify!($subarchive_resource)))?;
+                    }
+                }, {
+                    load_resource!(eager, self.$subarchive_resource,
+                        <$subarchive_type as $crate::Archive>::open_lazy_without_context(
+                            self._storage.subdir(stringify!($subarchive_resource)))
+                            .map_err(|e| e.in_archive(stringify!($subarchive_resource))),
+                        false);
+                    if let Some(subarchive) = self.$subarchive_resource.get() {
+                        $crate::Archive::validate_all_without_context(subarchive)
+                            .map_err(|e| e.in_archive(stringify!($subarchive_resource)))?;
+                    }
+                });)*
+                Ok(())
+            }
         }
 
         #[derive(Clone)]
         pub struct $builder_name {
-            storage: ::std::rc::Rc<$crate::ResourceStorage>
+            storage: ::std::sync::Arc<dyn $crate::ResourceStorage>
         }
 
         impl $builder_name {
@@ -661,9 +1067,7 @@ macro_rules! define_archive {
                 &self,
                 resource: <$struct_type as $crate::Struct>::Item,
             ) -> ::std::io::Result<()> {
-                let data = unsafe {
-                    ::std::slice::from_raw_parts(resource.data, <$struct_type as $crate::Struct>::SIZE_IN_BYTES)
-                };
+                let data = &resource.data[..<$struct_type as $crate::Struct>::SIZE_IN_BYTES];
                 self.storage
                     .write(stringify!($struct_resource), $struct_schema, data)
             })*
@@ -714,6 +1118,77 @@ macro_rules! define_archive {
                 $subarchive_builder_type::new(storage)
             }
             )*
+
+            /// Finishes writing the archive and opens it for reading.
+            ///
+            /// Checks that all non-optional resources and subarchives were
+            /// written, and fails with `ResourceStorageError::MissingResource`
+            /// otherwise. Checks that all resources started by `start_*`
+            /// methods were closed, and fails with
+            /// `ResourceStorageError::ResourceNotClosed` otherwise; a resource
+            /// is closed once its checksum is written. Then the archive is
+            /// opened, and finally the underlying storage is committed, which
+            /// e.g. atomically moves an archive written to a staged
+            /// `FileResourceStorage` into place.
+            ///
+            /// Subarchive builders can be finished as well; committing a
+            /// subdirectory of a storage has no effect.
+            pub fn finish(self) -> Result<$name, $crate::ResourceStorageError> {
+                use $crate::Archive;
+                $(Self::check_written(
+                    &*self.storage, stringify!($struct_resource), $is_optional_struct)?;)*
+                $(Self::check_written(
+                    &*self.storage, stringify!($vector_resource), $is_optional_vector)?;)*
+                $(Self::check_written(
+                    &*self.storage,
+                    stringify!($multivector_resource_index),
+                    $is_optional_multivector)?;
+                Self::check_written(
+                    &*self.storage,
+                    stringify!($multivector_resource),
+                    $is_optional_multivector)?;)*
+                $(Self::check_written(
+                    &*self.storage, stringify!($raw_data_resource), $is_optional_raw_data)?;)*
+                $(static_if!($is_optional_subarchive, {}, {
+                    let signature_name = format!(
+                        "{}.archive", <$subarchive_type as $crate::Archive>::NAME);
+                    if !self.storage.subdir(stringify!($subarchive_resource))
+                        .exists(&signature_name)
+                    {
+                        return Err($crate::ResourceStorageError::MissingResource(
+                            stringify!($subarchive_resource).into()));
+                    }
+                });)*
+
+                let archive = $name::open(self.storage.clone())?;
+                self.storage.commit().map_err(|e| {
+                    $crate::ResourceStorageError::from_io_error(e, $name::NAME.into())
+                })?;
+                Ok(archive)
+            }
+
+            /// Checks that a resource was written and closed; an optional
+            /// resource may be absent.
+            #[allow(dead_code)]
+            fn check_written(
+                storage: &dyn $crate::ResourceStorage,
+                resource_name: &str,
+                is_optional: bool,
+            ) -> Result<(), $crate::ResourceStorageError> {
+                if !storage.exists(resource_name) {
+                    if is_optional {
+                        return Ok(());
+                    }
+                    return Err(
+                        $crate::ResourceStorageError::MissingResource(resource_name.into()));
+                }
+                // the checksum is written when a resource is closed
+                if !storage.exists(&format!("{}.checksum", resource_name)) {
+                    return Err(
+                        $crate::ResourceStorageError::ResourceNotClosed(resource_name.into()));
+                }
+                Ok(())
+            }
         }
 
         impl $crate::ArchiveBuilder for $builder_name {
@@ -721,7 +1196,7 @@ macro_rules! define_archive {
             const SCHEMA: &'static str = $archive_schema;
 
             fn new(
-                storage: ::std::rc::Rc<$crate::ResourceStorage>,
+                storage: ::std::sync::Arc<dyn $crate::ResourceStorage>,
             ) -> Result<Self, $crate::ResourceStorageError> {
                 $crate::create_archive::<Self>(&storage)?;
                 Ok(Self { storage })
@@ -874,5 +1349,10 @@ mod test {
             (arch, SubArch, SubArchBuilder, "arch schema", false),
             (opt_arch, SubArch, SubArchBuilder, "opt_arch schema", true)
         );
+
+        // archives and builders can be shared between threads
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Arch>();
+        assert_send_sync::<ArchBuilder>();
     }
 }
@@ -386,6 +386,17 @@ mod test {
         assert_eq!(view.slice(2..8).iter().next().unwrap().value(), 2);
     }
 
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let v = create_values(10);
+        let view = v.as_view();
+        assert_send_sync(&view);
+        assert_send_sync(&view.iter());
+        assert_send_sync(&view.at(0));
+    }
+
     #[test]
     fn debug() {
         let v = create_values(100);
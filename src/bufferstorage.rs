@@ -0,0 +1,207 @@
+use crate::storage::{list_paths, ResourceStorage, Stream};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+type Buffer = Box<dyn AsRef<[u8]> + Send + Sync>;
+
+/// Read-only resource storage backed by byte buffers provided by the caller.
+///
+/// The storage is constructed from a list of resource names and their
+/// contents. A buffer can be of any type which can be viewed as a slice of
+/// bytes, e.g. `&'static [u8]` (in particular obtained by
+/// `&include_bytes!(..)[..]`), `Vec<u8>` or `Arc<[u8]>`. The contents are
+/// expected to be exactly the same as the corresponding files of an archive
+/// directory, including the schema resources `{resource_name}.schema` and the
+/// archive signature.
+/// Resources of subarchives are named by their path, e.g.
+/// `statistics/vertex_degrees`.
+///
+/// The schema of resources is checked when they are read, as for any other
+/// resource storage.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{BufferResourceStorage, ResourceStorage};
+///
+/// let data: Vec<u8> = vec![1, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 0, 0, 0, 0, 0];
+/// let storage = BufferResourceStorage::new(vec![
+///     ("resource", data),
+///     ("resource.schema", b"schema".to_vec()),
+/// ]);
+/// assert_eq!(storage.read("resource", "schema").unwrap(), &[42]);
+/// # }
+/// ```
+pub struct BufferResourceStorage {
+    buffers: Arc<BTreeMap<String, Buffer>>,
+    prefix: String,
+}
+
+impl BufferResourceStorage {
+    /// Creates a read-only resource storage from a list of resource names and
+    /// their contents.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new<I, K, V>(resources: I) -> Arc<Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        let buffers = resources
+            .into_iter()
+            .map(|(name, data)| (name.into(), Box::new(data) as Buffer))
+            .collect();
+        Arc::new(Self {
+            buffers: Arc::new(buffers),
+            prefix: String::new(),
+        })
+    }
+
+    fn key(&self, resource_name: &str) -> String {
+        if self.prefix.is_empty() {
+            resource_name.into()
+        } else {
+            format!("{}/{}", self.prefix, resource_name)
+        }
+    }
+}
+
+impl fmt::Debug for BufferResourceStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "BufferResourceStorage {{ prefix: {:?}, num_buffers: {} }}",
+            self.prefix,
+            self.buffers.len()
+        )
+    }
+}
+
+impl ResourceStorage for BufferResourceStorage {
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            buffers: self.buffers.clone(),
+            prefix: self.key(dir),
+        })
+    }
+
+    fn exists(&self, resource_name: &str) -> bool {
+        self.buffers.contains_key(&self.key(resource_name))
+    }
+
+    fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
+        let key = self.key(resource_name);
+        match self.buffers.get(&key) {
+            Some(buffer) => Ok((**buffer).as_ref()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, key)),
+        }
+    }
+
+    fn create_output_stream(
+        &self,
+        resource_name: &str,
+    ) -> Result<Arc<Mutex<dyn Stream>>, io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "cannot write {}: buffer storage is read-only",
+                self.key(resource_name)
+            ),
+        ))
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        Ok(list_paths(self.buffers.keys(), &self.prefix, false))
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        Ok(list_paths(self.buffers.keys(), &self.prefix, true))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::ResourceStorageError;
+
+    // size header, data and padding
+    const RESOURCE: &[u8] = &[2, 0, 0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0];
+
+    fn check_storage(storage: Arc<BufferResourceStorage>) {
+        assert!(storage.exists("a"));
+        assert!(!storage.exists("b"));
+        assert_eq!(storage.read("a", "schema a").unwrap(), &[1, 2]);
+        let sub = storage.subdir("sub");
+        assert!(sub.exists("b"));
+        assert_eq!(sub.read("b", "schema b").unwrap(), &[1, 2]);
+        assert!(sub.create_output_stream("c").is_err());
+    }
+
+    #[test]
+    fn read_static_buffers() {
+        check_storage(BufferResourceStorage::new(vec![
+            ("a", RESOURCE),
+            ("a.schema", &b"schema a"[..]),
+            ("sub/b", RESOURCE),
+            ("sub/b.schema", &b"schema b"[..]),
+        ]));
+    }
+
+    #[test]
+    fn read_owned_buffers() {
+        check_storage(BufferResourceStorage::new(vec![
+            ("a", RESOURCE.to_vec()),
+            ("a.schema", b"schema a".to_vec()),
+            ("sub/b", RESOURCE.to_vec()),
+            ("sub/b.schema", b"schema b".to_vec()),
+        ]));
+    }
+
+    #[test]
+    fn read_shared_buffers() {
+        let resource: Arc<[u8]> = Arc::from(RESOURCE);
+        check_storage(BufferResourceStorage::new(vec![
+            ("a", resource.clone()),
+            ("a.schema", Arc::from(&b"schema a"[..])),
+            ("sub/b", resource),
+            ("sub/b.schema", Arc::from(&b"schema b"[..])),
+        ]));
+    }
+
+    #[test]
+    fn check_schema_and_size() {
+        let storage = BufferResourceStorage::new(vec![
+            ("a", RESOURCE),
+            ("a.schema", &b"schema a"[..]),
+            ("b", &RESOURCE[..10]),
+            ("b.schema", &b"schema b"[..]),
+            ("d", RESOURCE),
+        ]);
+
+        match storage.read("a", "other schema") {
+            Err(ResourceStorageError::WrongSignature { .. }) => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+        match storage.read("b", "schema b") {
+            Err(ResourceStorageError::UnexpectedDataSize {
+                ref resource_name,
+                expected,
+                actual: 10,
+            }) if resource_name == "b" && expected > 10 => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+        match storage.read("c", "schema c") {
+            Err(ResourceStorageError::Io(ref e, _)) if e.kind() == io::ErrorKind::NotFound => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+        match storage.read("d", "schema d") {
+            Err(ResourceStorageError::MissingSchema(ref name)) if name == "d" => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+}
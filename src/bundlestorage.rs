@@ -0,0 +1,689 @@
+use crate::memory::{SizeType, PADDING_SIZE};
+#[cfg(target_os = "linux")]
+use crate::paging::Residency;
+use crate::paging::{self, Advice};
+use crate::storage::{list_paths, ResourceStorage, Stream};
+
+use memmap::{Mmap, MmapOptions};
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Cursor, Read, Write};
+use std::mem;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::slice;
+use std::sync::{Arc, Mutex};
+
+/// Magic bytes at the beginning and at the end of a bundle file.
+const BUNDLE_MAGIC: &[u8; 8] = b"FDBUNDLE";
+/// Version of the bundle file format. Version 1 has no flags in the table of
+/// contents.
+const BUNDLE_VERSION: u64 = 2;
+/// Size of the header: magic and version.
+const HEADER_SIZE: usize = 16;
+/// Size of the footer: offset of the table of contents, number of entries and
+/// magic.
+const FOOTER_SIZE: usize = 24;
+/// Alignment of resources in a bundle file.
+const ALIGNMENT: u64 = 8;
+/// Flag of a resource in the table of contents whose size header is a
+/// placeholder, since the resource was streamed to a non-seekable output. The
+/// size is derived from the size of the resource in the table of contents
+/// instead, and the header is restored when the bundle is opened.
+pub(crate) const SIZE_IN_TOC: u64 = 1;
+
+type BundleStream = Arc<Mutex<Cursor<Vec<u8>>>>;
+/// Location of each resource in a bundle file.
+type Toc = BTreeMap<String, Range<usize>>;
+
+/// Content of a bundle.
+enum Content {
+    /// Bundle file opened for reading.
+    Mapped { mmap: Mmap, toc: Toc },
+    /// Bundle which is being written and kept in memory until it is finished.
+    Buffered {
+        // Streams of resources that were written.
+        streams: Mutex<BTreeMap<String, BundleStream>>,
+        // Data of resources that were opened for reading.
+        resources: Mutex<BTreeMap<String, Arc<Vec<u8>>>>,
+    },
+}
+
+/// Internal storage of a bundle shared by all subdirectories.
+struct Bundle {
+    path: PathBuf,
+    content: Content,
+}
+
+impl fmt::Debug for Bundle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.content {
+            Content::Mapped { ref toc, .. } => write!(
+                f,
+                "Bundle {{ path: {:?}, num_resources: {} }}",
+                self.path,
+                toc.len()
+            ),
+            Content::Buffered { ref streams, .. } => write!(
+                f,
+                "Bundle {{ path: {:?}, num_streams: {} }}",
+                self.path,
+                streams.lock().unwrap().len()
+            ),
+        }
+    }
+}
+
+/// Resource storage keeping a whole archive in a single file.
+///
+/// A bundle contains all resources of an archive including schemas,
+/// signatures and subarchives, and a table of contents mapping resource paths
+/// to their location in the file. When a bundle is opened for reading, the
+/// file is memory mapped once and resources are returned as slices of the
+/// mapping without copying.
+///
+/// A bundle created for writing keeps the written resources in memory, until
+/// it is written to disk by [`finish`]. For large archives, it is usually
+/// better to write the archive to a [`FileResourceStorage`] and to convert it
+/// by [`convert_directory_to_bundle`].
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{BundleResourceStorage, ResourceStorage};
+///
+/// let path = std::env::temp_dir().join("bundle_doc_example.bundle");
+/// {
+///     let storage = BundleResourceStorage::create(&path);
+///     storage.write("resource", "schema", &[1, 2, 3]).expect("write failed");
+///     storage.finish().expect("finish failed");
+/// }
+///
+/// let storage = BundleResourceStorage::open(&path).expect("open failed");
+/// let data = storage.read("resource", "schema").expect("read failed");
+/// assert_eq!(data, &[1, 2, 3]);
+/// # }
+/// ```
+///
+/// [`finish`]: #method.finish
+/// [`FileResourceStorage`]: struct.FileResourceStorage.html
+/// [`convert_directory_to_bundle`]: fn.convert_directory_to_bundle.html
+#[derive(Debug)]
+pub struct BundleResourceStorage {
+    bundle: Arc<Bundle>,
+    prefix: String,
+}
+
+impl BundleResourceStorage {
+    /// Opens an existing bundle file for reading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an IO error if the file cannot be mapped, or an error of kind
+    /// [`InvalidData`] if the file is not a valid bundle.
+    ///
+    /// [`InvalidData`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#InvalidData.v
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Arc<Self>> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        let (toc, size_in_toc) = read_toc(&mmap)?;
+        let mmap = if size_in_toc.is_empty() {
+            mmap
+        } else {
+            // restore size headers in a private copy-on-write mapping, which
+            // only copies the pages of the headers
+            let mut mmap = unsafe { MmapOptions::new().map_copy(&file)? };
+            for range in size_in_toc {
+                mmap[range.start..range.start + mem::size_of::<SizeType>()]
+                    .copy_from_slice(&size_header(&range));
+            }
+            mmap.make_read_only()?
+        };
+        Ok(Arc::new(Self {
+            bundle: Arc::new(Bundle {
+                path,
+                content: Content::Mapped { mmap, toc },
+            }),
+            prefix: String::new(),
+        }))
+    }
+
+    /// Creates an empty bundle for writing at a given path.
+    ///
+    /// Nothing is written to disk until [`finish`] is called.
+    ///
+    /// [`finish`]: #method.finish
+    #[allow(clippy::new_ret_no_self)]
+    pub fn create<P: Into<PathBuf>>(path: P) -> Arc<Self> {
+        Arc::new(Self {
+            bundle: Arc::new(Bundle {
+                path: path.into(),
+                content: Content::Buffered {
+                    streams: Mutex::new(BTreeMap::new()),
+                    resources: Mutex::new(BTreeMap::new()),
+                },
+            }),
+            prefix: String::new(),
+        })
+    }
+
+    /// Writes all resources of a bundle created for writing to its file.
+    ///
+    /// Resources written to any subdirectory of this storage are included.
+    /// Existing file at the bundle path is overwritten.
+    pub fn finish(&self) -> io::Result<()> {
+        match self.bundle.content {
+            Content::Mapped { .. } => Err(read_only_error(&self.bundle.path)),
+            Content::Buffered { ref streams, .. } => {
+                let mut writer = BundleWriter::create(&self.bundle.path)?;
+                for (name, stream) in streams.lock().unwrap().iter() {
+                    writer.add(name, &mut &stream.lock().unwrap().get_ref()[..])?;
+                }
+                writer.finish()
+            }
+        }
+    }
+
+    fn list(&self, subdirs: bool) -> Vec<String> {
+        match self.bundle.content {
+            Content::Mapped { ref toc, .. } => list_paths(toc.keys(), &self.prefix, subdirs),
+            Content::Buffered {
+                ref streams,
+                ref resources,
+            } => {
+                let streams = streams.lock().unwrap();
+                let resources = resources.lock().unwrap();
+                list_paths(
+                    streams.keys().chain(resources.keys()),
+                    &self.prefix,
+                    subdirs,
+                )
+            }
+        }
+    }
+
+    fn key(&self, resource_name: &str) -> String {
+        if self.prefix.is_empty() {
+            resource_name.into()
+        } else {
+            format!("{}/{}", self.prefix, resource_name)
+        }
+    }
+}
+
+impl ResourceStorage for BundleResourceStorage {
+    /// Writes the bundle to its file, cf. [`finish`].
+    ///
+    /// Has no effect for bundles opened for reading and for subdirectories.
+    ///
+    /// [`finish`]: #method.finish
+    fn commit(&self) -> io::Result<()> {
+        match self.bundle.content {
+            Content::Buffered { .. } if self.prefix.is_empty() => self.finish(),
+            _ => Ok(()),
+        }
+    }
+
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            bundle: self.bundle.clone(),
+            prefix: self.key(dir),
+        })
+    }
+
+    fn exists(&self, resource_name: &str) -> bool {
+        let key = self.key(resource_name);
+        match self.bundle.content {
+            Content::Mapped { ref toc, .. } => toc.contains_key(&key),
+            Content::Buffered {
+                ref streams,
+                ref resources,
+            } => {
+                resources.lock().unwrap().contains_key(&key)
+                    || streams.lock().unwrap().contains_key(&key)
+            }
+        }
+    }
+
+    fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
+        let key = self.key(resource_name);
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, key.clone());
+        match self.bundle.content {
+            Content::Mapped { ref mmap, ref toc } => {
+                let range = toc.get(&key).ok_or_else(not_found)?;
+                Ok(&mmap[range.clone()])
+            }
+            Content::Buffered {
+                ref streams,
+                ref resources,
+            } => {
+                let mut resources = resources.lock().unwrap();
+                if !resources.contains_key(&key) {
+                    // Resource is not yet opened, but there might be a stream it was written to
+                    // => copy the stream as resource data.
+                    let streams = streams.lock().unwrap();
+                    let stream = streams.get(&key).ok_or_else(not_found)?;
+                    let data = Arc::new(stream.lock().unwrap().get_ref().clone());
+                    resources.insert(key.clone(), data);
+                }
+                let data = &resources[&key];
+                // We cannot prove to Rust that the buffer will live as long as the storage
+                // (we never delete resources), so we need to manually extend lifetime
+                let extended_lifetime_data =
+                    unsafe { slice::from_raw_parts(data.as_ptr(), data.len()) };
+                Ok(extended_lifetime_data)
+            }
+        }
+    }
+
+    fn create_output_stream(
+        &self,
+        resource_name: &str,
+    ) -> Result<Arc<Mutex<dyn Stream>>, io::Error> {
+        match self.bundle.content {
+            Content::Mapped { .. } => Err(read_only_error(&self.bundle.path)),
+            Content::Buffered { ref streams, .. } => {
+                let stream = streams
+                    .lock()
+                    .unwrap()
+                    .entry(self.key(resource_name))
+                    .or_insert_with(|| Arc::new(Mutex::new(Cursor::new(Vec::new()))))
+                    .clone();
+                Ok(stream)
+            }
+        }
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        Ok(self.list(false))
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        Ok(self.list(true))
+    }
+
+    fn advise(&self, resource_name: &str, advice: Advice) -> io::Result<()> {
+        match self.bundle.content {
+            Content::Mapped { .. } => paging::advise(self.read_resource(resource_name)?, advice),
+            // buffered resources are not memory mapped
+            Content::Buffered { .. } => Ok(()),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn residency(&self, resource_name: &str) -> io::Result<Option<Residency>> {
+        match self.bundle.content {
+            Content::Mapped { .. } => {
+                paging::residency(self.read_resource(resource_name)?).map(Some)
+            }
+            Content::Buffered { .. } => Ok(None),
+        }
+    }
+}
+
+/// Converts an archive stored in a directory to a single bundle file.
+///
+/// All files in the directory and its subdirectories are copied to the
+/// bundle, in particular, resources, schemas, signatures and subarchives.
+pub fn convert_directory_to_bundle<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    bundle: Q,
+) -> io::Result<()> {
+    let mut files = Vec::new();
+    collect_files(dir.as_ref(), "", &mut files)?;
+
+    let mut writer = BundleWriter::create(bundle.as_ref())?;
+    for (name, path) in files {
+        writer.add(&name, &mut File::open(path)?)?;
+    }
+    writer.finish()
+}
+
+/// Converts a bundle file to an archive stored in a directory.
+///
+/// The directory is created if it does not exist. Each resource of the
+/// bundle is written to a file, subarchives to subdirectories.
+pub fn convert_bundle_to_directory<P: AsRef<Path>, Q: AsRef<Path>>(
+    bundle: P,
+    dir: Q,
+) -> io::Result<()> {
+    let file = File::open(bundle.as_ref())?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    let (toc, size_in_toc) = read_toc(&mmap)?;
+    for (name, range) in toc {
+        let path = resource_path(dir.as_ref(), &name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(path)?;
+        if size_in_toc.contains(&range) {
+            let header_end = range.start + mem::size_of::<SizeType>();
+            file.write_all(&size_header(&range))?;
+            file.write_all(&mmap[header_end..range.end])?;
+        } else {
+            file.write_all(&mmap[range])?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the path of a resource of a bundle in the given directory.
+///
+/// Fails for names which would escape the directory, i.e. names with empty,
+/// `.` or `..` parts, or with parts which are not plain file names.
+fn resource_path(dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for part in name.split('/') {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) => path.push(part),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid resource name in bundle: {:?}", name),
+                ))
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Writes a bundle sequentially: header, resources, table of contents and
+/// footer.
+///
+/// The writer is never seeked, therefore, a bundle can be written to any
+/// output, e.g. a pipe.
+pub(crate) struct BundleWriter<W: Write> {
+    writer: W,
+    offset: u64,
+    // name, offset, size and flags of each resource
+    toc: Vec<(String, u64, u64, u64)>,
+}
+
+impl BundleWriter<BufWriter<File>> {
+    fn create(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> BundleWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(BUNDLE_MAGIC)?;
+        writer.write_all(&BUNDLE_VERSION.to_le_bytes())?;
+        Ok(Self {
+            writer,
+            offset: HEADER_SIZE as u64,
+            toc: Vec::new(),
+        })
+    }
+
+    pub fn add(&mut self, name: &str, data: &mut dyn Read) -> io::Result<()> {
+        let start = self.offset;
+        self.offset += io::copy(data, &mut self.writer)?;
+        self.end_resource(name, start, 0)
+    }
+
+    /// Returns the offset at which the next resource starts.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Writes data of a resource, which is added by [`end_resource`] after
+    /// all of its data is written.
+    ///
+    /// [`end_resource`]: #method.end_resource
+    pub fn write_data(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(data)?;
+        self.offset += data.len() as u64;
+        Ok(())
+    }
+
+    /// Adds the data written since offset `start` as resource with the given
+    /// flags to the table of contents.
+    pub fn end_resource(&mut self, name: &str, start: u64, flags: u64) -> io::Result<()> {
+        self.toc
+            .push((name.into(), start, self.offset - start, flags));
+        self.align()
+    }
+
+    fn align(&mut self) -> io::Result<()> {
+        let padding = (ALIGNMENT - self.offset % ALIGNMENT) % ALIGNMENT;
+        self.writer
+            .write_all(&[0; ALIGNMENT as usize][..padding as usize])?;
+        self.offset += padding;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        let toc_offset = self.offset;
+        for (name, offset, size, flags) in &self.toc {
+            self.writer.write_all(&(name.len() as u64).to_le_bytes())?;
+            self.writer.write_all(name.as_bytes())?;
+            self.writer.write_all(&offset.to_le_bytes())?;
+            self.writer.write_all(&size.to_le_bytes())?;
+            self.writer.write_all(&flags.to_le_bytes())?;
+        }
+        self.writer.write_all(&toc_offset.to_le_bytes())?;
+        self.writer
+            .write_all(&(self.toc.len() as u64).to_le_bytes())?;
+        self.writer.write_all(BUNDLE_MAGIC)?;
+        self.writer.flush()
+    }
+}
+
+/// Reads the table of contents of a bundle.
+///
+/// Returns the location of each resource in the bundle, and the locations of
+/// resources flagged by [`SIZE_IN_TOC`].
+///
+/// [`SIZE_IN_TOC`]: constant.SIZE_IN_TOC.html
+fn read_toc(data: &[u8]) -> io::Result<(Toc, Vec<Range<usize>>)> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    if data.len() < HEADER_SIZE + FOOTER_SIZE
+        || &data[..BUNDLE_MAGIC.len()] != BUNDLE_MAGIC
+        || &data[data.len() - BUNDLE_MAGIC.len()..] != BUNDLE_MAGIC
+    {
+        return Err(invalid("not a flatdata bundle"));
+    }
+    let version = read_u64(data, BUNDLE_MAGIC.len());
+    if version != 1 && version != BUNDLE_VERSION {
+        return Err(invalid("unsupported bundle version"));
+    }
+    let entry_size = if version == 1 { 16 } else { 24 };
+
+    let footer = data.len() - FOOTER_SIZE;
+    let read_usize =
+        |pos| usize::try_from(read_u64(data, pos)).map_err(|_| invalid("value out of range"));
+    let truncated = || invalid("truncated table of contents");
+    let toc_offset = read_usize(footer)?;
+    let num_entries = read_u64(data, footer + 8);
+    if toc_offset > footer {
+        return Err(truncated());
+    }
+
+    let mut toc = BTreeMap::new();
+    let mut size_in_toc = Vec::new();
+    let mut pos = toc_offset;
+    for _ in 0..num_entries {
+        if footer - pos < 8 {
+            return Err(truncated());
+        }
+        let name_len = read_usize(pos)?;
+        pos += 8;
+        let name_end = pos
+            .checked_add(name_len)
+            .filter(|&end| end <= footer && footer - end >= entry_size)
+            .ok_or_else(truncated)?;
+        let name = String::from_utf8(data[pos..name_end].to_vec())
+            .map_err(|_| invalid("resource name is not valid utf8"))?;
+        pos = name_end;
+        let offset = read_usize(pos)?;
+        let size = read_usize(pos + 8)?;
+        let flags = if version == 1 {
+            0
+        } else {
+            read_u64(data, pos + 16)
+        };
+        pos += entry_size;
+        let end = offset
+            .checked_add(size)
+            .filter(|&end| offset >= HEADER_SIZE && end <= toc_offset)
+            .ok_or_else(|| invalid("resource out of bounds"))?;
+        match flags {
+            0 => (),
+            SIZE_IN_TOC if size >= mem::size_of::<SizeType>() + PADDING_SIZE => {
+                size_in_toc.push(offset..end)
+            }
+            _ => return Err(invalid("invalid resource flags")),
+        }
+        toc.insert(name, offset..end);
+    }
+    Ok((toc, size_in_toc))
+}
+
+/// Returns the size header of a resource flagged by [`SIZE_IN_TOC`] at the
+/// given location.
+///
+/// [`SIZE_IN_TOC`]: constant.SIZE_IN_TOC.html
+fn size_header(range: &Range<usize>) -> [u8; 8] {
+    let size = range.len() - mem::size_of::<SizeType>() - PADDING_SIZE;
+    (size as SizeType).to_le_bytes()
+}
+
+fn read_u64(data: &[u8], pos: usize) -> u64 {
+    let mut buffer = [0; 8];
+    buffer.copy_from_slice(&data[pos..pos + 8]);
+    u64::from_le_bytes(buffer)
+}
+
+fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_str().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{:?} is not valid utf8", entry.path()),
+            )
+        })?;
+        let name = if prefix.is_empty() {
+            file_name.to_string()
+        } else {
+            format!("{}/{}", prefix, file_name)
+        };
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &name, files)?;
+        } else {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(())
+}
+
+fn read_only_error(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("bundle {:?} is opened read-only", path),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn write_and_read_bundle() {
+        let path = env::temp_dir().join("flatdata_bundle_test/write_and_read.bundle");
+        {
+            let storage = BundleResourceStorage::create(&path);
+            storage.write("a", "schema_a", &[1, 2, 3]).unwrap();
+            let subdir = storage.subdir("sub");
+            subdir.write("b", "schema_b", &[4, 5]).unwrap();
+            assert!(subdir.exists("b"));
+            assert_eq!(subdir.read("b", "schema_b").unwrap(), &[4, 5]);
+            storage.finish().unwrap();
+        }
+
+        let storage = BundleResourceStorage::open(&path).unwrap();
+        assert!(storage.exists("a"));
+        assert!(storage.exists("a.schema"));
+        assert!(storage.exists("sub/b"));
+        assert!(!storage.exists("b"));
+        assert_eq!(storage.read("a", "schema_a").unwrap(), &[1, 2, 3]);
+        assert_eq!(
+            storage.subdir("sub").read("b", "schema_b").unwrap(),
+            &[4, 5]
+        );
+        assert!(storage.create_output_stream("c").is_err());
+    }
+
+    #[test]
+    fn open_invalid_bundle() {
+        let path = env::temp_dir().join("flatdata_bundle_test/invalid.bundle");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(&path)
+            .unwrap()
+            .write_all(b"definitely not a bundle file")
+            .unwrap();
+        let err = BundleResourceStorage::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn open_bundle_with_corrupt_toc() {
+        let mut bundle = Vec::new();
+        let mut writer = BundleWriter::new(&mut bundle).unwrap();
+        writer.add("a", &mut &[1, 2, 3][..]).unwrap();
+        writer.finish().unwrap();
+        assert!(read_toc(&bundle).is_ok());
+
+        // name length, offset, size and flags of the only entry, and the toc
+        // offset
+        let toc_offset = bundle.len() - FOOTER_SIZE - (8 + 1 + 24);
+        for &pos in &[
+            toc_offset,
+            toc_offset + 9,
+            toc_offset + 17,
+            toc_offset + 25,
+            bundle.len() - FOOTER_SIZE,
+        ] {
+            for &value in &[u64::MAX, u64::MAX - 7] {
+                let mut corrupt = bundle.clone();
+                corrupt[pos..pos + 8].copy_from_slice(&value.to_le_bytes());
+                let err = read_toc(&corrupt).unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            }
+        }
+    }
+
+    #[test]
+    fn convert_bundle_with_malicious_names() {
+        let root = env::temp_dir().join("flatdata_bundle_test/malicious");
+        let dir = root.join("archive");
+        for name in &["../escaped", "a//b", "/absolute", "a/./b", ""] {
+            let _ = fs::remove_dir_all(&root);
+            let path = root.join("malicious.bundle");
+            let mut writer = BundleWriter::create(&path).unwrap();
+            writer.add(name, &mut &[1, 2, 3][..]).unwrap();
+            writer.finish().unwrap();
+
+            let err = convert_bundle_to_directory(&path, &dir).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", name);
+            assert!(!root.join("escaped").exists());
+        }
+        assert!(resource_path(&dir, "sub/a.schema").is_ok());
+    }
+}
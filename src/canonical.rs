@@ -0,0 +1,381 @@
+//! Canonical form of flatdata schemas.
+//!
+//! Schemas are stored as text next to each resource. Different generator
+//! versions may render the same schema differently, e.g. with other
+//! whitespace, comments, declaration order, or with several declarations
+//! sharing a single namespace block. Comparing the canonical forms instead of
+//! the text accepts such schemas, while still rejecting any structural
+//! difference.
+
+use crate::compatibility::SchemaAddition;
+use crate::schema::{
+    self, Annotation, Archive, Declaration, Position, Resource, ResourceType, Schema, Struct,
+};
+
+/// Returns whether two schemas have the same canonical form.
+///
+/// The canonical form is independent of
+///
+/// * whitespace and comments,
+/// * the order of declarations in a namespace and of resources in an
+///   archive, and
+/// * how declarations are grouped in namespace blocks.
+///
+/// The order of fields in structs and of values in enums is significant, and
+/// therefore, kept. Schemas which cannot be parsed are only equivalent if
+/// they are equal.
+pub(crate) fn is_equivalent(left: &str, right: &str) -> bool {
+    if left == right {
+        return true;
+    }
+    let (left, right) = match (strip_index(left), strip_index(right)) {
+        (Some(left), Some(right)) => (left, right),
+        (None, None) => (left, right),
+        _ => return false,
+    };
+    match (schema::parse(left), schema::parse(right)) {
+        (Ok(left), Ok(right)) => declarations(&left) == declarations(&right),
+        _ => false,
+    }
+}
+
+/// Checks whether data written with the `stored` schema can be read with the
+/// `expected` schema, and returns what the stored schema is missing.
+///
+/// Returns `None` if the schemas are incompatible. Besides equivalent
+/// declarations, the following additions are compatible:
+///
+/// * optional resources added to an archive,
+/// * fields appended to a struct without changing its size in bytes, which
+///   read as zero from data written by flatdata, and
+/// * types appended to a multivector.
+pub(crate) fn compatible_additions(stored: &str, expected: &str) -> Option<Vec<SchemaAddition>> {
+    let (stored, expected) = match (strip_index(stored), strip_index(expected)) {
+        (Some(stored), Some(expected)) => (stored, expected),
+        _ => (stored, expected),
+    };
+    let stored = schema::parse(stored).ok()?;
+    let expected = schema::parse(expected).ok()?;
+    let expected_declarations = declarations(&expected);
+
+    let mut additions = Vec::new();
+    for (name, stored_declaration) in declarations(&stored) {
+        let (_, expected_declaration) = expected_declarations
+            .iter()
+            .find(|(expected_name, _)| *expected_name == name)?;
+        if stored_declaration == *expected_declaration {
+            continue;
+        }
+        match (&stored_declaration, expected_declaration) {
+            (Declaration::Struct(stored_struct), Declaration::Struct(expected_struct)) => {
+                compare_structs(
+                    &name,
+                    (&stored, stored_struct),
+                    (&expected, expected_struct),
+                    &mut additions,
+                )?
+            }
+            (Declaration::Archive(stored_archive), Declaration::Archive(expected_archive)) => {
+                compare_archives(&name, stored_archive, expected_archive, &mut additions)?
+            }
+            _ => return None,
+        }
+    }
+    Some(additions)
+}
+
+/// Strips the `index(...)` wrapper of multivector index schemas.
+pub(crate) fn strip_index(schema: &str) -> Option<&str> {
+    schema.strip_prefix("index(")?.strip_suffix(')')
+}
+
+/// Returns the canonical forms of all declarations together with their
+/// qualified names, sorted by name and without duplicates.
+fn declarations(schema: &Schema) -> Vec<(String, Declaration)> {
+    let mut result: Vec<(String, Declaration)> = schema
+        .declarations()
+        .map(|(name, declaration)| (name, canonical(declaration)))
+        .collect();
+    result.sort_by_cached_key(|(name, declaration)| (name.clone(), format!("{:?}", declaration)));
+    result.dedup();
+    result
+}
+
+/// Returns the canonical form of a declaration, i.e. without positions and
+/// with the resources of archives sorted by name.
+fn canonical(declaration: &Declaration) -> Declaration {
+    let mut declaration = declaration.clone();
+    match declaration {
+        Declaration::Const(ref mut constant) => {
+            constant.position = UNKNOWN_POSITION;
+            clear_positions(&mut constant.annotations);
+        }
+        Declaration::Enum(ref mut enumeration) => {
+            enumeration.position = UNKNOWN_POSITION;
+            clear_positions(&mut enumeration.annotations);
+            for value in &mut enumeration.values {
+                value.position = UNKNOWN_POSITION;
+            }
+        }
+        Declaration::Struct(ref mut structure) => {
+            structure.position = UNKNOWN_POSITION;
+            clear_positions(&mut structure.annotations);
+            for field in &mut structure.fields {
+                field.position = UNKNOWN_POSITION;
+                clear_positions(&mut field.annotations);
+            }
+        }
+        Declaration::Archive(ref mut archive) => {
+            archive.position = UNKNOWN_POSITION;
+            clear_positions(&mut archive.annotations);
+            for resource in &mut archive.resources {
+                resource.position = UNKNOWN_POSITION;
+                clear_positions(&mut resource.annotations);
+            }
+            archive
+                .resources
+                .sort_by(|left, right| left.name.cmp(&right.name));
+        }
+    }
+    declaration
+}
+
+const UNKNOWN_POSITION: Position = Position { line: 0, column: 0 };
+
+fn clear_positions(annotations: &mut [Annotation]) {
+    for annotation in annotations {
+        annotation.position = UNKNOWN_POSITION;
+    }
+}
+
+/// Returns the size in bytes of a struct, or `None` if a field is of an
+/// unknown enum type.
+fn struct_size(schema: &Schema, structure: &Struct) -> Option<u32> {
+    let mut bits = 0;
+    for field in &structure.fields {
+        bits += schema::field_width(schema, field)?;
+    }
+    Some(bits.div_ceil(8))
+}
+
+fn compare_structs(
+    name: &str,
+    (stored_schema, stored): (&Schema, &Struct),
+    (expected_schema, expected): (&Schema, &Struct),
+    additions: &mut Vec<SchemaAddition>,
+) -> Option<()> {
+    if stored.annotations != expected.annotations
+        || stored.fields.len() > expected.fields.len()
+        || struct_size(stored_schema, stored)? != struct_size(expected_schema, expected)?
+    {
+        return None;
+    }
+    let (common, appended) = expected.fields.split_at(stored.fields.len());
+    if stored.fields[..] != *common {
+        return None;
+    }
+    for field in appended {
+        additions.push(SchemaAddition::StructField(format!(
+            "{}.{}",
+            name, field.name
+        )));
+    }
+    Some(())
+}
+
+fn compare_archives(
+    name: &str,
+    stored: &Archive,
+    expected: &Archive,
+    additions: &mut Vec<SchemaAddition>,
+) -> Option<()> {
+    // annotations of the archive do not affect how its resources are read
+    if stored.resources.iter().any(|resource| {
+        !expected
+            .resources
+            .iter()
+            .any(|expected_resource| expected_resource.name == resource.name)
+    }) {
+        return None;
+    }
+    for resource in &expected.resources {
+        let qualified_name = format!("{}.{}", name, resource.name);
+        match stored.resources.iter().find(|x| x.name == resource.name) {
+            Some(stored) => compare_resources(&qualified_name, stored, resource, additions)?,
+            None if resource.is_optional() => {
+                additions.push(SchemaAddition::Resource(qualified_name));
+            }
+            None => return None,
+        }
+    }
+    Some(())
+}
+
+fn compare_resources(
+    name: &str,
+    stored: &Resource,
+    expected: &Resource,
+    additions: &mut Vec<SchemaAddition>,
+) -> Option<()> {
+    if stored.is_optional() && !expected.is_optional() {
+        return None;
+    }
+    match (&stored.ty, &expected.ty) {
+        (stored_type, expected_type) if stored_type == expected_type => Some(()),
+        (
+            ResourceType::Multivector {
+                index_width: stored_index_width,
+                types: stored_types,
+            },
+            ResourceType::Multivector { index_width, types },
+        ) if stored_index_width == index_width && types.starts_with(stored_types) => {
+            // types appended to a multivector
+            for type_name in &types[stored_types.len()..] {
+                additions.push(SchemaAddition::VariadicType {
+                    resource: name.into(),
+                    type_name: type_name.clone(),
+                });
+            }
+            Some(())
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SCHEMA: &str = r#"namespace n {
+struct S
+{
+    a : u32 : 16;
+    b : u32 : 16;
+}
+}
+
+namespace n {
+@bound_implicitly( b : .n.A.x, .n.A.y )
+archive A
+{
+    @explicit_reference( .n.S.a, .n.A.y )
+    x : vector< .n.S >;
+    @optional
+    y : raw_data;
+}
+}
+
+"#;
+
+    #[test]
+    fn ignore_formatting_and_comments() {
+        let reformatted = r#"
+// schema of n
+namespace n { struct S { a : u32 : 16; /* first */ b : u32 : 16; } }
+namespace n {
+    @bound_implicitly(b: .n.A.x, .n.A.y)
+    archive A {
+        @explicit_reference(.n.S.a, .n.A.y) x: vector<.n.S>;
+        @optional y: raw_data;
+    }
+}"#;
+        assert!(is_equivalent(SCHEMA, reformatted));
+        assert!(is_equivalent(
+            &format!("index({})", SCHEMA),
+            &format!("index( {} )", reformatted)
+        ));
+        assert!(!is_equivalent(&format!("index({})", SCHEMA), reformatted));
+    }
+
+    #[test]
+    fn ignore_declaration_order() {
+        let reordered = r#"namespace n {
+@bound_implicitly( b : .n.A.x, .n.A.y )
+archive A
+{
+    @optional
+    y : raw_data;
+    @explicit_reference( .n.S.a, .n.A.y )
+    x : vector< .n.S >;
+}
+struct S
+{
+    a : u32 : 16;
+    b : u32 : 16;
+}
+}
+"#;
+        assert!(is_equivalent(SCHEMA, reordered));
+    }
+
+    #[test]
+    fn detect_structural_differences() {
+        let swapped_fields = SCHEMA.replace("a : u32 : 16;\n    b", "b : u32 : 16;\n    a");
+        assert!(!is_equivalent(SCHEMA, &swapped_fields));
+        let resized_field = SCHEMA.replace("b : u32 : 16", "b : u32 : 17");
+        assert!(!is_equivalent(SCHEMA, &resized_field));
+        let required = SCHEMA.replace("@optional", "");
+        assert!(!is_equivalent(SCHEMA, &required));
+        let other_namespace = SCHEMA.replace("namespace n {\nstruct", "namespace m {\nstruct");
+        assert!(!is_equivalent(SCHEMA, &other_namespace));
+    }
+
+    #[test]
+    fn accept_added_optional_resources() {
+        let added = SCHEMA.replace(
+            "    y : raw_data;",
+            "    y : raw_data;\n    @optional\n    z : raw_data;",
+        );
+        assert_eq!(
+            compatible_additions(SCHEMA, &added),
+            Some(vec![SchemaAddition::Resource(".n.A.z".into())])
+        );
+        assert_eq!(compatible_additions(SCHEMA, SCHEMA), Some(vec![]));
+
+        let required = SCHEMA.replace("    y : raw_data;", "    y : raw_data;\n    z : raw_data;");
+        assert_eq!(compatible_additions(SCHEMA, &required), None);
+        // removed resources are not compatible
+        assert_eq!(compatible_additions(&added, SCHEMA), None);
+    }
+
+    #[test]
+    fn accept_appended_struct_fields() {
+        let old = "namespace n { struct S { a : u8 : 3; } }";
+        let new = "namespace n { struct S { a : u8 : 3; b : u8 : 5; } }";
+        assert_eq!(
+            compatible_additions(old, new),
+            Some(vec![SchemaAddition::StructField(".n.S.b".into())])
+        );
+        // additional fields must neither change the size nor the layout
+        let resized = "namespace n { struct S { a : u8 : 3; b : u8 : 6; } }";
+        assert_eq!(compatible_additions(old, resized), None);
+        let prepended = "namespace n { struct S { b : u8 : 5; a : u8 : 3; } }";
+        assert_eq!(compatible_additions(old, prepended), None);
+
+        let old = "namespace n { struct S { @range(r) a : u8 : 3; } }";
+        let new = "namespace n { struct S { @range(r) a : u8 : 3; b : u8 : 5; } }";
+        assert_eq!(
+            compatible_additions(old, new),
+            Some(vec![SchemaAddition::StructField(".n.S.b".into())])
+        );
+    }
+
+    #[test]
+    fn accept_appended_variadic_types() {
+        let old = "namespace n { struct S { a : u8 : 8; } }\n\
+                   namespace n { archive A { m : multivector< 8, .n.S >; } }";
+        let new = "namespace n { struct S { a : u8 : 8; } }\n\
+                   namespace n { struct T { b : u8 : 8; } }\n\
+                   namespace n { archive A { m : multivector< 8, .n.S, .n.T >; } }";
+        let added = vec![SchemaAddition::VariadicType {
+            resource: ".n.A.m".into(),
+            type_name: ".n.T".into(),
+        }];
+        assert_eq!(compatible_additions(old, new), Some(added.clone()));
+        assert_eq!(
+            compatible_additions(&format!("index({})", old), &format!("index({})", new)),
+            Some(added)
+        );
+        assert_eq!(compatible_additions(new, old), None);
+    }
+}
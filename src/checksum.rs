@@ -0,0 +1,310 @@
+use crate::archive::Archive;
+use crate::error::ResourceStorageError;
+use crate::paging::Advice;
+#[cfg(target_os = "linux")]
+use crate::paging::Residency;
+use crate::storage::{ResourceStorage, Stream};
+
+use std::fmt;
+use std::io;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+const CRC32C_POLYNOMIAL: u32 = 0x82F6_3B78;
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32C_POLYNOMIAL
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Incremental CRC-32C (Castagnoli) checksum of resource data.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Crc32c(u32);
+
+impl Crc32c {
+    pub fn new() -> Self {
+        Crc32c(!0)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 = CRC32C_TABLE[((self.0 ^ u32::from(byte)) & 0xff) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    /// Returns the checksum in the format stored in `{resource_name}.checksum`.
+    pub fn finish(self) -> String {
+        format!("crc32c:{:08x}", !self.0)
+    }
+}
+
+/// Returns the checksum of data in the format stored in
+/// `{resource_name}.checksum`.
+pub(crate) fn checksum(data: &[u8]) -> String {
+    let mut crc = Crc32c::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Checks data of a resource against the checksum stored in the storage.
+///
+/// Returns `Ok(false)` on mismatch. Resources without a stored checksum, e.g.
+/// written by older versions of flatdata, are not verified.
+fn verify_checksum(
+    storage: &dyn ResourceStorage,
+    resource_name: &str,
+    data: &[u8],
+) -> Result<bool, ResourceStorageError> {
+    let checksum_name = format!("{}.checksum", resource_name);
+    if !storage.exists(&checksum_name) {
+        return Ok(true);
+    }
+    let stored = storage
+        .read_resource(&checksum_name)
+        .map_err(|e| ResourceStorageError::from_io_error(e, checksum_name.clone()))?;
+    let stored = str::from_utf8(stored)
+        .map_err(|e| ResourceStorageError::Utf8Error(e, checksum_name.clone()))?;
+    if !stored.starts_with("crc32c:") {
+        return Err(ResourceStorageError::from_io_error(
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported checksum: {}", stored),
+            ),
+            checksum_name,
+        ));
+    }
+    Ok(stored.trim_end() == checksum(data))
+}
+
+/// Resource storage verifying checksums of resources on read.
+///
+/// If corrupt resources are collected, reading a corrupt resource succeeds and
+/// its path relative to the root is recorded; otherwise, reading fails with
+/// `ResourceStorageError::ChecksumMismatch` naming the resource, which is
+/// put into the context of its subarchives by the archive reading it.
+pub(crate) struct VerifyingResourceStorage {
+    inner: Arc<dyn ResourceStorage>,
+    path: String,
+    corrupt: Option<Arc<Mutex<Vec<String>>>>,
+}
+
+impl VerifyingResourceStorage {
+    pub fn new(
+        inner: Arc<dyn ResourceStorage>,
+        corrupt: Option<Arc<Mutex<Vec<String>>>>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            path: String::new(),
+            corrupt,
+        })
+    }
+
+    fn path_of(&self, resource_name: &str) -> String {
+        if self.path.is_empty() {
+            resource_name.into()
+        } else {
+            format!("{}/{}", self.path, resource_name)
+        }
+    }
+}
+
+impl fmt::Debug for VerifyingResourceStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "VerifyingResourceStorage {{ path: {:?} }}", self.path)
+    }
+}
+
+impl ResourceStorage for VerifyingResourceStorage {
+    fn read(&self, resource_name: &str, schema: &str) -> Result<&[u8], ResourceStorageError> {
+        let data = self.inner.read(resource_name, schema)?;
+        if !verify_checksum(&*self.inner, resource_name, data)? {
+            match self.corrupt {
+                Some(ref corrupt) => corrupt.lock().unwrap().push(self.path_of(resource_name)),
+                None => return Err(ResourceStorageError::ChecksumMismatch(resource_name.into())),
+            }
+        }
+        Ok(data)
+    }
+
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            inner: self.inner.subdir(dir),
+            path: self.path_of(dir),
+            corrupt: self.corrupt.clone(),
+        })
+    }
+
+    fn exists(&self, resource_name: &str) -> bool {
+        self.inner.exists(resource_name)
+    }
+
+    fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
+        self.inner.read_resource(resource_name)
+    }
+
+    fn create_output_stream(&self, resource_name: &str) -> io::Result<Arc<Mutex<dyn Stream>>> {
+        self.inner.create_output_stream(resource_name)
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        self.inner.list_resources()
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        self.inner.list_subdirs()
+    }
+
+    fn commit(&self) -> io::Result<()> {
+        self.inner.commit()
+    }
+
+    fn advise(&self, resource_name: &str, advice: Advice) -> io::Result<()> {
+        self.inner.advise(resource_name, advice)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn residency(&self, resource_name: &str) -> io::Result<Option<Residency>> {
+        self.inner.residency(resource_name)
+    }
+}
+
+/// Verifies checksums of all resources of an archive including its
+/// subarchives.
+///
+/// Checksums are written to `{resource_name}.checksum` whenever a resource is
+/// written. Resources without a stored checksum are not verified.
+///
+/// Returns the paths of all resources whose data does not match the stored
+/// checksum, relative to the archive, e.g. `statistics/vertex_degrees`. An
+/// empty list means that the archive is intact. If the archive cannot be
+/// opened at all, e.g. since a resource is missing or truncated, the
+/// corresponding error is returned.
+///
+/// Cf. [`Archive::open_verified`] for opening an archive with verification.
+///
+/// [`Archive::open_verified`]: trait.Archive.html#method.open_verified
+pub fn verify_archive<A: Archive>(
+    storage: Arc<dyn ResourceStorage>,
+) -> Result<Vec<String>, ResourceStorageError> {
+    let corrupt = Arc::new(Mutex::new(Vec::new()));
+    A::open(VerifyingResourceStorage::new(
+        storage,
+        Some(corrupt.clone()),
+    ))?;
+    let corrupt = corrupt.lock().unwrap().clone();
+    Ok(corrupt)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memstorage::MemoryResourceStorage;
+
+    #[test]
+    fn crc32c_check_value() {
+        assert_eq!(checksum(b"123456789"), "crc32c:e3069283");
+        assert_eq!(checksum(b""), "crc32c:00000000");
+
+        let mut crc = Crc32c::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finish(), "crc32c:e3069283");
+    }
+
+    fn write_resource(storage: &dyn ResourceStorage, name: &str, data: &[u8], checksum: &str) {
+        let stream = storage.create_output_stream(name).unwrap();
+        let mut stream = stream.lock().unwrap();
+        stream
+            .write_all(&[data.len() as u8, 0, 0, 0, 0, 0, 0, 0])
+            .unwrap();
+        stream.write_all(data).unwrap();
+        stream.write_all(&[0; 8]).unwrap();
+        let stream = storage
+            .create_output_stream(&format!("{}.schema", name))
+            .unwrap();
+        stream.lock().unwrap().write_all(b"schema").unwrap();
+        let stream = storage
+            .create_output_stream(&format!("{}.checksum", name))
+            .unwrap();
+        stream
+            .lock()
+            .unwrap()
+            .write_all(checksum.as_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn write_checksums() {
+        let storage = MemoryResourceStorage::new("/root/checksum");
+        storage.write("a", "schema", &[1, 2, 3]).unwrap();
+        assert_eq!(
+            storage.read_resource("a.checksum").unwrap(),
+            checksum(&[1, 2, 3]).as_bytes()
+        );
+    }
+
+    #[test]
+    fn detect_corrupt_resources() {
+        let inner = MemoryResourceStorage::new("/root/checksum");
+        write_resource(&*inner, "a", &[1, 2, 3], &checksum(&[1, 2, 3]));
+        write_resource(&*inner, "b", &[1, 2, 3], &checksum(&[1, 2, 4]));
+        write_resource(&*inner.subdir("sub"), "c", &[1], &checksum(&[2]));
+
+        let corrupt = Arc::new(Mutex::new(Vec::new()));
+        let storage = VerifyingResourceStorage::new(inner.clone(), Some(corrupt.clone()));
+        assert_eq!(storage.read("a", "schema").unwrap(), &[1, 2, 3]);
+        assert!(corrupt.lock().unwrap().is_empty());
+        assert_eq!(storage.read("b", "schema").unwrap(), &[1, 2, 3]);
+        assert_eq!(*corrupt.lock().unwrap(), vec!["b".to_string()]);
+        assert!(storage.subdir("sub").read("c", "schema").is_ok());
+        assert_eq!(
+            *corrupt.lock().unwrap(),
+            vec!["b".to_string(), "sub/c".to_string()]
+        );
+
+        let storage = VerifyingResourceStorage::new(inner, None);
+        assert!(storage.read("a", "schema").is_ok());
+        match storage.read("b", "schema") {
+            Err(ResourceStorageError::ChecksumMismatch(ref name)) if name == "b" => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+        // the path of subdirectories is added by the archives reading them
+        match storage.subdir("sub").read("c", "schema") {
+            Err(ResourceStorageError::ChecksumMismatch(ref name)) if name == "c" => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn skip_resources_without_checksum() {
+        let inner = MemoryResourceStorage::new("/root/no_checksum");
+        let stream = inner.create_output_stream("a").unwrap();
+        stream
+            .lock()
+            .unwrap()
+            .write_all(&[1, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 0, 0, 0, 0, 0])
+            .unwrap();
+        let stream = inner.create_output_stream("a.schema").unwrap();
+        stream.lock().unwrap().write_all(b"schema").unwrap();
+
+        let storage = VerifyingResourceStorage::new(inner, None);
+        assert_eq!(storage.read("a", "schema").unwrap(), &[42]);
+    }
+}
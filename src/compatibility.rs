@@ -0,0 +1,180 @@
+use crate::canonical;
+use crate::error::ResourceStorageError;
+use crate::paging::Advice;
+#[cfg(target_os = "linux")]
+use crate::paging::Residency;
+use crate::storage::{ResourceStorage, Stream};
+
+use std::fmt;
+use std::io;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+/// Part of an expected schema which is missing in the compatible older
+/// schema an archive was written with.
+///
+/// Names are fully qualified, e.g. `.coappearances.Graph.statistics`.
+///
+/// Cf. [`Archive::open_compatible`].
+///
+/// [`Archive::open_compatible`]: trait.Archive.html#method.open_compatible
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SchemaAddition {
+    /// Optional resource added to an archive; it is read as absent.
+    Resource(String),
+    /// Field appended to a struct; it is read as zero.
+    StructField(String),
+    /// Type appended to a multivector; the data contains no items of it.
+    VariadicType {
+        /// Name of the multivector resource.
+        resource: String,
+        /// Name of the appended type.
+        type_name: String,
+    },
+}
+
+impl fmt::Display for SchemaAddition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SchemaAddition::Resource(ref name) => write!(f, "optional resource {}", name),
+            SchemaAddition::StructField(ref name) => write!(f, "struct field {}", name),
+            SchemaAddition::VariadicType {
+                ref resource,
+                ref type_name,
+            } => write!(f, "type {} of multivector {}", type_name, resource),
+        }
+    }
+}
+
+/// Resource storage accepting resources whose stored schema is compatible
+/// with the expected schema.
+///
+/// Resources are read with their stored schema; what it is missing compared
+/// to the expected schema is recorded.
+pub(crate) struct CompatibleResourceStorage {
+    inner: Arc<dyn ResourceStorage>,
+    additions: Arc<Mutex<Vec<SchemaAddition>>>,
+}
+
+impl CompatibleResourceStorage {
+    pub fn new(
+        inner: Arc<dyn ResourceStorage>,
+        additions: Arc<Mutex<Vec<SchemaAddition>>>,
+    ) -> Arc<Self> {
+        Arc::new(Self { inner, additions })
+    }
+
+    fn stored_schema(&self, resource_name: &str) -> Option<&str> {
+        let schema = self
+            .inner
+            .read_resource(&format!("{}.schema", resource_name))
+            .ok()?;
+        str::from_utf8(schema).ok()
+    }
+}
+
+impl fmt::Debug for CompatibleResourceStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CompatibleResourceStorage {{ num_additions: {} }}",
+            self.additions.lock().unwrap().len()
+        )
+    }
+}
+
+impl ResourceStorage for CompatibleResourceStorage {
+    fn read(&self, resource_name: &str, schema: &str) -> Result<&[u8], ResourceStorageError> {
+        match self.inner.read(resource_name, schema) {
+            Err(ResourceStorageError::WrongSignature {
+                resource_name: name,
+                changes,
+                diff,
+            }) => {
+                let compatible = self.stored_schema(resource_name).and_then(|stored| {
+                    canonical::compatible_additions(stored, schema).map(|a| (stored, a))
+                });
+                match compatible {
+                    Some((stored, additions)) => {
+                        self.additions.lock().unwrap().extend(additions);
+                        self.inner.read(resource_name, stored)
+                    }
+                    None => Err(ResourceStorageError::WrongSignature {
+                        resource_name: name,
+                        changes,
+                        diff,
+                    }),
+                }
+            }
+            result => result,
+        }
+    }
+
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            inner: self.inner.subdir(dir),
+            additions: self.additions.clone(),
+        })
+    }
+
+    fn exists(&self, resource_name: &str) -> bool {
+        self.inner.exists(resource_name)
+    }
+
+    fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
+        self.inner.read_resource(resource_name)
+    }
+
+    fn create_output_stream(&self, resource_name: &str) -> io::Result<Arc<Mutex<dyn Stream>>> {
+        self.inner.create_output_stream(resource_name)
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        self.inner.list_resources()
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        self.inner.list_subdirs()
+    }
+
+    fn commit(&self) -> io::Result<()> {
+        self.inner.commit()
+    }
+
+    fn advise(&self, resource_name: &str, advice: Advice) -> io::Result<()> {
+        self.inner.advise(resource_name, advice)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn residency(&self, resource_name: &str) -> io::Result<Option<Residency>> {
+        self.inner.residency(resource_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memstorage::MemoryResourceStorage;
+
+    const OLD: &str = "namespace n { struct S { a : u8 : 4; } }";
+    const NEW: &str = "namespace n { struct S { a : u8 : 4; b : u8 : 4; } }";
+
+    #[test]
+    fn read_resource_with_compatible_schema() {
+        let inner = MemoryResourceStorage::new("/root/compatible");
+        inner.write("resource", OLD, &[1]).unwrap();
+        let additions = Arc::new(Mutex::new(Vec::new()));
+        let storage = CompatibleResourceStorage::new(inner.clone(), additions.clone());
+
+        assert!(inner.read("resource", NEW).is_err());
+        assert_eq!(storage.read("resource", NEW).unwrap(), &[1]);
+        assert_eq!(
+            *additions.lock().unwrap(),
+            vec![SchemaAddition::StructField(".n.S.b".into())]
+        );
+        match storage.read("resource", "namespace n { struct S { a : u8 : 5; } }") {
+            Err(ResourceStorageError::WrongSignature { .. }) => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use crate::paging::Advice;
+#[cfg(target_os = "linux")]
+use crate::paging::Residency;
+use crate::storage::{list_paths, ResourceStorage, Stream};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io::{self, Cursor};
+use std::slice;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+type PendingStream = Arc<Mutex<Cursor<Vec<u8>>>>;
+
+/// Resource which is written, but not yet compressed.
+struct PendingResource {
+    // Storage the resource is written to, i.e. the underlying storage of the
+    // subdirectory it was created in.
+    storage: Arc<dyn ResourceStorage>,
+    // Name of the resource relative to `storage`.
+    name: String,
+    stream: PendingStream,
+}
+
+/// Internal state of a compressed storage shared by all subdirectories.
+struct CompressedState {
+    compression: Compression,
+    // Resources which are written, but not yet compressed, by path.
+    pending: Mutex<BTreeMap<String, PendingResource>>,
+    // Decompressed data of resources which were opened for reading, by path.
+    resources: Mutex<BTreeMap<String, Arc<Vec<u8>>>>,
+}
+
+impl CompressedState {
+    fn flush(&self) -> io::Result<()> {
+        let paths: Vec<String> = self.pending.lock().unwrap().keys().cloned().collect();
+        for path in paths {
+            self.flush_resource(&path)?;
+        }
+        Ok(())
+    }
+
+    fn flush_resource(&self, path: &str) -> io::Result<()> {
+        let resource = match self.pending.lock().unwrap().remove(path) {
+            Some(resource) => resource,
+            None => return Ok(()),
+        };
+        let stream = resource.stream.lock().unwrap();
+        self.write_compressed(&*resource.storage, &resource.name, stream.get_ref())
+    }
+
+    fn write_compressed(
+        &self,
+        storage: &dyn ResourceStorage,
+        resource_name: &str,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let codec = match self.compression.name() {
+            Some(codec) => codec,
+            None => return write_all(storage, resource_name, data),
+        };
+        let compressed = self.compression.compress(data)?;
+        write_all(storage, resource_name, &compressed)?;
+        write_all(
+            storage,
+            &format!("{}.codec", resource_name),
+            codec.as_bytes(),
+        )
+    }
+}
+
+impl Drop for CompressedState {
+    fn drop(&mut self) {
+        // Errors cannot be reported here; call `flush` explicitly to handle them.
+        let _ = self.flush();
+    }
+}
+
+/// Compression codec of resources written to a [`CompressedResourceStorage`].
+///
+/// [`CompressedResourceStorage`]: struct.CompressedResourceStorage.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Resources are written uncompressed.
+    None,
+    /// Resources are compressed with zstd at the given level (requires the
+    /// `zstd` feature).
+    #[cfg(feature = "zstd")]
+    Zstd {
+        /// Compression level, cf. `zstd::compression_level_range`; `0` means
+        /// the default level.
+        level: i32,
+    },
+    /// Resources are compressed with lz4 (requires the `lz4` feature).
+    #[cfg(feature = "lz4")]
+    Lz4,
+}
+
+impl Compression {
+    fn name(self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { .. } => Some("zstd"),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Some("lz4"),
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd { level } => zstd::encode_all(data, level),
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+        }
+    }
+}
+
+/// Decompresses data of a resource with the codec of the given name.
+#[cfg_attr(not(any(feature = "zstd", feature = "lz4")), allow(unused_variables))]
+fn decompress(codec: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    match codec {
+        #[cfg(feature = "zstd")]
+        "zstd" => zstd::decode_all(data),
+        #[cfg(feature = "lz4")]
+        "lz4" => lz4_flex::decompress_size_prepended(data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported compression codec: {}", codec),
+        )),
+    }
+}
+
+/// Resource storage transparently compressing resources of another resource
+/// storage.
+///
+/// Resources are written compressed with the configured [`Compression`] to
+/// the underlying storage. The codec is recorded alongside the schema as
+/// another resource with name `{resource_name}.codec`. Schemas and checksums
+/// are never compressed.
+///
+/// On read, a resource with a recorded codec is decompressed into memory once
+/// and cached for the lifetime of the storage; therefore, it cannot be written
+/// again by the same storage afterwards. Resources without a recorded
+/// codec are passed through from the underlying storage, therefore, archives
+/// written without compression remain readable, and mixing compressed and
+/// uncompressed resources is possible. Note that compressed resources are not
+/// memory mapped anymore, i.e. they are fully loaded into memory when opened.
+///
+/// Since a resource is compressed as a whole, data written to an output stream
+/// is buffered in memory until the resource is read back, which the builders
+/// do when closing a resource, or [`flush`] is called, or the storage and all
+/// of its subdirectories are dropped. Subdirectories share pending and
+/// decompressed resources with the storage they were created from.
+///
+/// Supported codecs are zstd (`zstd` feature) and lz4 (`lz4` feature).
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{CompressedResourceStorage, Compression, MemoryResourceStorage, ResourceStorage};
+///
+/// let inner = MemoryResourceStorage::new("/root/compressed");
+/// let storage = CompressedResourceStorage::new(inner.clone(), Compression::None);
+/// storage.write("resource", "schema", &[42; 100]).unwrap();
+/// assert_eq!(storage.read("resource", "schema").unwrap(), &[42; 100][..]);
+/// # }
+/// ```
+///
+/// [`Compression`]: enum.Compression.html
+/// [`flush`]: #method.flush
+pub struct CompressedResourceStorage {
+    inner: Arc<dyn ResourceStorage>,
+    state: Arc<CompressedState>,
+    prefix: String,
+}
+
+impl CompressedResourceStorage {
+    /// Creates a storage compressing resources written to `inner` with the
+    /// given compression.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(inner: Arc<dyn ResourceStorage>, compression: Compression) -> Arc<Self> {
+        Arc::new(Self {
+            inner,
+            state: Arc::new(CompressedState {
+                compression,
+                pending: Mutex::new(BTreeMap::new()),
+                resources: Mutex::new(BTreeMap::new()),
+            }),
+            prefix: String::new(),
+        })
+    }
+
+    /// Compresses and writes all pending resources to the underlying
+    /// storage, including those of subdirectories.
+    pub fn flush(&self) -> io::Result<()> {
+        self.state.flush()
+    }
+
+    fn key(&self, resource_name: &str) -> String {
+        if self.prefix.is_empty() {
+            resource_name.into()
+        } else {
+            format!("{}/{}", self.prefix, resource_name)
+        }
+    }
+}
+
+fn write_all(storage: &dyn ResourceStorage, resource_name: &str, data: &[u8]) -> io::Result<()> {
+    let stream = storage.create_output_stream(resource_name)?;
+    let mut stream = stream.lock().unwrap();
+    stream.write_all(data)?;
+    stream.flush()
+}
+
+/// Schemas and checksums are stored uncompressed.
+fn is_metadata(resource_name: &str) -> bool {
+    resource_name.ends_with(".schema") || resource_name.ends_with(".checksum")
+}
+
+impl fmt::Debug for CompressedResourceStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CompressedResourceStorage {{ prefix: {:?}, compression: {:?}, num_pending: {}, \
+             num_resources: {} }}",
+            self.prefix,
+            self.state.compression,
+            self.state.pending.lock().unwrap().len(),
+            self.state.resources.lock().unwrap().len(),
+        )
+    }
+}
+
+impl ResourceStorage for CompressedResourceStorage {
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            inner: self.inner.subdir(dir),
+            state: self.state.clone(),
+            prefix: self.key(dir),
+        })
+    }
+
+    fn exists(&self, resource_name: &str) -> bool {
+        self.state
+            .pending
+            .lock()
+            .unwrap()
+            .contains_key(&self.key(resource_name))
+            || self.inner.exists(resource_name)
+    }
+
+    fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
+        if is_metadata(resource_name) {
+            return self.inner.read_resource(resource_name);
+        }
+        let key = self.key(resource_name);
+        self.state.flush_resource(&key)?;
+
+        let codec_name = format!("{}.codec", resource_name);
+        if !self.inner.exists(&codec_name) {
+            return self.inner.read_resource(resource_name);
+        }
+
+        let mut resources = self.state.resources.lock().unwrap();
+        if !resources.contains_key(&key) {
+            let codec = str::from_utf8(self.inner.read_resource(&codec_name)?).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "codec is not valid utf8")
+            })?;
+            let data = decompress(codec, self.inner.read_resource(resource_name)?)?;
+            resources.insert(key.clone(), Arc::new(data));
+        }
+        let data = &resources[&key];
+        // We cannot prove to Rust that the buffer will live as long as the storage
+        // (we never delete decompressed resources), so we need to manually extend
+        // lifetime
+        let extended_lifetime_data = unsafe { slice::from_raw_parts(data.as_ptr(), data.len()) };
+        Ok(extended_lifetime_data)
+    }
+
+    fn create_output_stream(
+        &self,
+        resource_name: &str,
+    ) -> Result<Arc<Mutex<dyn Stream>>, io::Error> {
+        if is_metadata(resource_name) {
+            return self.inner.create_output_stream(resource_name);
+        }
+        // Decompressed data may still be referenced by readers, so it cannot be
+        // replaced by new data.
+        if self
+            .state
+            .resources
+            .lock()
+            .unwrap()
+            .contains_key(&self.key(resource_name))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("resource {} is already decompressed", resource_name),
+            ));
+        }
+        if self.state.compression == Compression::None {
+            return self.inner.create_output_stream(resource_name);
+        }
+        let stream = Arc::new(Mutex::new(Cursor::new(Vec::new())));
+        self.state.pending.lock().unwrap().insert(
+            self.key(resource_name),
+            PendingResource {
+                storage: self.inner.clone(),
+                name: resource_name.into(),
+                stream: stream.clone(),
+            },
+        );
+        Ok(stream)
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        let mut names: BTreeSet<String> = self
+            .inner
+            .list_resources()?
+            .into_iter()
+            .filter(|name| !name.ends_with(".codec"))
+            .collect();
+        names.extend(list_paths(
+            self.state.pending.lock().unwrap().keys(),
+            &self.prefix,
+            false,
+        ));
+        Ok(names.into_iter().collect())
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        let mut names: BTreeSet<String> = self.inner.list_subdirs()?.into_iter().collect();
+        names.extend(list_paths(
+            self.state.pending.lock().unwrap().keys(),
+            &self.prefix,
+            true,
+        ));
+        Ok(names.into_iter().collect())
+    }
+
+    fn commit(&self) -> io::Result<()> {
+        self.flush()?;
+        self.inner.commit()
+    }
+
+    fn advise(&self, resource_name: &str, advice: Advice) -> io::Result<()> {
+        // decompressed resources are not memory mapped
+        if self.inner.exists(&format!("{}.codec", resource_name)) {
+            return Ok(());
+        }
+        self.inner.advise(resource_name, advice)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn residency(&self, resource_name: &str) -> io::Result<Option<Residency>> {
+        if self.inner.exists(&format!("{}.codec", resource_name)) {
+            return Ok(None);
+        }
+        self.inner.residency(resource_name)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filestorage::FileResourceStorage;
+    use crate::memstorage::MemoryResourceStorage;
+    use std::env;
+    use std::fs;
+
+    fn check_roundtrip(compression: Compression) {
+        let dir = env::temp_dir()
+            .join("flatdata_compressed_test")
+            .join(compression.name().unwrap_or("none"));
+        let _ = fs::remove_dir_all(&dir);
+        let data = vec![7; 4096];
+        {
+            let storage =
+                CompressedResourceStorage::new(FileResourceStorage::new(&dir), compression);
+            storage.write("a", "schema", &data).unwrap();
+            storage.subdir("sub").write("b", "schema", &data).unwrap();
+            assert_eq!(storage.read("a", "schema").unwrap(), &data[..]);
+        }
+
+        let raw_size = fs::metadata(dir.join("a")).unwrap().len() as usize;
+        match compression.name() {
+            Some(codec) => {
+                assert!(raw_size < data.len());
+                assert_eq!(fs::read(dir.join("a.codec")).unwrap(), codec.as_bytes());
+                assert_eq!(fs::read(dir.join("sub/b.codec")).unwrap(), codec.as_bytes());
+            }
+            None => assert!(!dir.join("a.codec").exists()),
+        }
+        assert_eq!(fs::read(dir.join("a.schema")).unwrap(), b"schema");
+
+        let storage = CompressedResourceStorage::new(FileResourceStorage::new(&dir), compression);
+        assert_eq!(storage.read("a", "schema").unwrap(), &data[..]);
+        assert_eq!(
+            storage.subdir("sub").read("b", "schema").unwrap(),
+            &data[..]
+        );
+    }
+
+    #[test]
+    fn roundtrip_uncompressed() {
+        check_roundtrip(Compression::None);
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn roundtrip_zstd() {
+        check_roundtrip(Compression::Zstd { level: 0 });
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn roundtrip_lz4() {
+        check_roundtrip(Compression::Lz4);
+    }
+
+    #[test]
+    #[cfg(feature = "lz4")]
+    fn share_state_between_subdirs() {
+        let inner = MemoryResourceStorage::new("/root/compressed_subdirs");
+        let storage = CompressedResourceStorage::new(inner.clone(), Compression::Lz4);
+        let stream = storage.subdir("sub").create_output_stream("a").unwrap();
+        stream.lock().unwrap().write_all(&[1; 64]).unwrap();
+
+        // pending writes are visible through other handles of the same subdir
+        let other = storage.subdir("sub");
+        assert!(other.exists("a"));
+        assert_eq!(other.list_resources().unwrap(), vec!["a"]);
+        assert_eq!(storage.list_subdirs().unwrap(), vec!["sub"]);
+        assert!(!inner.subdir("sub").exists("a"));
+
+        // dropping a handle does not flush resources shared with other handles
+        drop(other);
+        assert!(!inner.subdir("sub").exists("a"));
+
+        // data is decompressed once per storage
+        let (sub, other) = (storage.subdir("sub"), storage.subdir("sub"));
+        let data = sub.read_resource("a").unwrap();
+        assert_eq!(data, &[1; 64][..]);
+        assert!(inner.subdir("sub").exists("a.codec"));
+        assert_eq!(other.read_resource("a").unwrap().as_ptr(), data.as_ptr());
+
+        // decompressed data is not replaced while it may be referenced
+        assert_eq!(
+            other.create_output_stream("a").err().map(|e| e.kind()),
+            Some(io::ErrorKind::AlreadyExists)
+        );
+        assert_eq!(sub.read_resource("a").unwrap(), &[1; 64][..]);
+    }
+
+    #[test]
+    fn read_uncompressed_resources() {
+        let inner = MemoryResourceStorage::new("/root/uncompressed");
+        inner.write("a", "schema", &[1, 2, 3]).unwrap();
+        let storage = CompressedResourceStorage::new(inner, Compression::None);
+        assert!(storage.exists("a"));
+        assert_eq!(storage.read("a", "schema").unwrap(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn unsupported_codec() {
+        let inner = MemoryResourceStorage::new("/root/unsupported");
+        inner.write("a", "schema", &[1, 2, 3]).unwrap();
+        write_all(&*inner, "a.codec", b"unknown").unwrap();
+        let storage = CompressedResourceStorage::new(inner, Compression::None);
+        assert_eq!(
+            storage.read_resource("a").unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
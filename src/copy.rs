@@ -0,0 +1,201 @@
+use crate::error::ResourceStorageError;
+use crate::storage::ResourceStorage;
+
+use std::io;
+
+/// Copies an archive including all of its subarchives from one storage to
+/// another.
+///
+/// Every resource, schema, checksum and archive signature found by
+/// [`list_resources`] and [`list_subdirs`] is copied byte-exact, therefore,
+/// the source storage has to support listing. Finally, the target storage is
+/// committed, which e.g. publishes a staged [`FileResourceStorage`].
+///
+/// Cf. [`copy_archive_verified`] for verifying the copy, and
+/// [`Archive::copy_to`] for copying an opened archive.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{copy_archive, MemoryResourceStorage, ResourceStorage};
+///
+/// let from = MemoryResourceStorage::new("/root/from");
+/// from.write("resource", "schema", &[42]).unwrap();
+/// from.subdir("subarchive").write("resource", "schema", &[43]).unwrap();
+///
+/// let to = MemoryResourceStorage::new("/root/to");
+/// copy_archive(&*from, &*to).unwrap();
+/// assert_eq!(to.read("resource", "schema").unwrap(), &[42]);
+/// assert_eq!(to.subdir("subarchive").read("resource", "schema").unwrap(), &[43]);
+/// # }
+/// ```
+///
+/// [`list_resources`]: trait.ResourceStorage.html#method.list_resources
+/// [`list_subdirs`]: trait.ResourceStorage.html#method.list_subdirs
+/// [`FileResourceStorage`]: struct.FileResourceStorage.html#method.create_staged
+/// [`copy_archive_verified`]: fn.copy_archive_verified.html
+/// [`Archive::copy_to`]: trait.Archive.html#method.copy_to
+pub fn copy_archive(from: &dyn ResourceStorage, to: &dyn ResourceStorage) -> io::Result<()> {
+    copy_dir(from, to)?;
+    to.commit()
+}
+
+/// Copies an archive as [`copy_archive`] does, and verifies afterwards that
+/// every resource reads back from the target storage exactly as from the
+/// source storage.
+///
+/// The target storage is only committed if the verification succeeds;
+/// otherwise, an IO error of kind [`InvalidData`] is returned, which names
+/// the path of the first differing resource relative to the archive, e.g.
+/// `statistics/vertex_degrees`.
+///
+/// [`copy_archive`]: fn.copy_archive.html
+/// [`InvalidData`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#variant.InvalidData
+pub fn copy_archive_verified(
+    from: &dyn ResourceStorage,
+    to: &dyn ResourceStorage,
+) -> io::Result<()> {
+    copy_dir(from, to)?;
+    compare_dir(from, to, "")?;
+    to.commit()
+}
+
+/// Copies the resources with the given paths relative to an archive, each
+/// together with its schema and checksum, from one storage to another, and
+/// optionally verifies that they read back byte-exact. Absent resources are
+/// skipped. Errors name the failing resource and its directory.
+pub(crate) fn copy_resources(
+    from: &dyn ResourceStorage,
+    to: &dyn ResourceStorage,
+    paths: &[String],
+    verify: bool,
+) -> Result<(), ResourceStorageError> {
+    for path in paths {
+        let (dir, name) = match path.rfind('/') {
+            Some(pos) => (&path[..pos], &path[pos + 1..]),
+            None => ("", &path[..]),
+        };
+        let result = if dir.is_empty() {
+            copy_resource(from, to, name, verify)
+        } else {
+            copy_resource(&*from.subdir(dir), &*to.subdir(dir), name, verify)
+                .map_err(|e| e.in_archive(dir))
+        };
+        result?;
+    }
+    Ok(())
+}
+
+fn copy_resource(
+    from: &dyn ResourceStorage,
+    to: &dyn ResourceStorage,
+    name: &str,
+    verify: bool,
+) -> Result<(), ResourceStorageError> {
+    for resource_name in &[
+        name.to_string(),
+        format!("{}.schema", name),
+        format!("{}.checksum", name),
+    ] {
+        if !from.exists(resource_name) {
+            continue;
+        }
+        let copy = || -> io::Result<()> {
+            let data = from.read_resource(resource_name)?;
+            {
+                let stream = to.create_output_stream(resource_name)?;
+                let mut stream = stream.lock().unwrap();
+                stream.write_all(data)?;
+                stream.flush()?;
+            }
+            if verify && data != to.read_resource(resource_name)? {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("copy of {} differs", resource_name),
+                ));
+            }
+            Ok(())
+        };
+        copy().map_err(|e| ResourceStorageError::from_io_error(e, resource_name.clone()))?;
+    }
+    Ok(())
+}
+
+fn copy_dir(from: &dyn ResourceStorage, to: &dyn ResourceStorage) -> io::Result<()> {
+    for resource_name in from.list_resources()? {
+        let data = from.read_resource(&resource_name)?;
+        let stream = to.create_output_stream(&resource_name)?;
+        let mut stream = stream.lock().unwrap();
+        stream.write_all(data)?;
+        stream.flush()?;
+    }
+    for dir in from.list_subdirs()? {
+        copy_dir(&*from.subdir(&dir), &*to.subdir(&dir))?;
+    }
+    Ok(())
+}
+
+fn compare_dir(from: &dyn ResourceStorage, to: &dyn ResourceStorage, path: &str) -> io::Result<()> {
+    for resource_name in from.list_resources()? {
+        if from.read_resource(&resource_name)? != to.read_resource(&resource_name)? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("copy of {}{} differs", path, resource_name),
+            ));
+        }
+    }
+    for dir in from.list_subdirs()? {
+        compare_dir(
+            &*from.subdir(&dir),
+            &*to.subdir(&dir),
+            &format!("{}{}/", path, dir),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filestorage::FileResourceStorage;
+    use crate::memstorage::MemoryResourceStorage;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn copy_between_memory_and_file_storage() {
+        let from = MemoryResourceStorage::new("/root/copy");
+        from.write("a", "schema", &[1, 2, 3]).unwrap();
+        from.subdir("sub").write("b", "schema", &[4]).unwrap();
+
+        let dir = env::temp_dir().join("flatdata_copy_test/copy_between_storages");
+        let _ = fs::remove_dir_all(&dir);
+        let to = FileResourceStorage::create_staged(&dir).unwrap();
+        copy_archive_verified(&*from, &*to).unwrap();
+        assert!(dir.join("sub/b.schema").exists());
+
+        let back = MemoryResourceStorage::new("/root/copy_back");
+        copy_archive(&*FileResourceStorage::new(&dir), &*back).unwrap();
+        assert_eq!(back.read("a", "schema").unwrap(), &[1, 2, 3]);
+        assert_eq!(back.subdir("sub").read("b", "schema").unwrap(), &[4]);
+        assert_eq!(
+            back.list_resources().unwrap(),
+            from.list_resources().unwrap()
+        );
+    }
+
+    #[test]
+    fn detect_differing_copy() {
+        let from = MemoryResourceStorage::new("/root/copy_from");
+        from.subdir("sub").write("a", "schema", &[1]).unwrap();
+
+        // memory storages append to existing resources
+        let to = MemoryResourceStorage::new("/root/copy_to");
+        to.subdir("sub").write("a", "schema", &[1]).unwrap();
+        let err = copy_archive_verified(&*from, &*to).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("sub/a"));
+    }
+}
@@ -0,0 +1,904 @@
+use crate::error::ResourceStorageError;
+use crate::schema::{self, BasicType, Declaration, FieldType, ResourceType, Schema};
+use crate::storage::{MemoryDescriptor, ResourceStorage};
+
+use std::cmp;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io;
+use std::iter;
+use std::str;
+use std::sync::Arc;
+
+/// Value of a field of a [`DynamicStruct`].
+///
+/// [`DynamicStruct`]: struct.DynamicStruct.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Value {
+    /// Value of a `bool` field.
+    Bool(bool),
+    /// Value of a field of signed type.
+    Signed(i64),
+    /// Value of a field of unsigned type.
+    Unsigned(u64),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Signed(value) => write!(f, "{}", value),
+            Value::Unsigned(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Layout of a field in a struct.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldLayout {
+    /// Name of the field.
+    pub name: String,
+    /// Type the field is decoded as; for enum fields, the underlying type of
+    /// the enum.
+    pub ty: BasicType,
+    /// Offset of the field in bits.
+    pub offset: usize,
+    /// Size of the field in bits.
+    pub width: usize,
+}
+
+/// Layout of a struct as computed from its schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructLayout {
+    /// Qualified name of the struct, e.g. `.coappearances.Character`.
+    pub name: String,
+    /// Fields in order of their layout.
+    pub fields: Vec<FieldLayout>,
+    /// Size of the struct in bytes.
+    pub size_in_bytes: usize,
+}
+
+impl StructLayout {
+    /// Computes the layout of the struct with the given qualified name in a
+    /// schema.
+    ///
+    /// Fields are packed without gaps in order of their declaration; enum
+    /// fields are resolved to the underlying type of the enum.
+    pub fn new(schema: &Schema, name: &str) -> Result<Self, String> {
+        let declaration = match schema.find(name) {
+            Some(Declaration::Struct(declaration)) => declaration,
+            _ => return Err(format!("struct {} not found in schema", name)),
+        };
+        let mut fields = Vec::new();
+        let mut offset = 0;
+        for field in &declaration.fields {
+            let ty = match field.ty {
+                FieldType::Basic(ty) => ty,
+                FieldType::Enum(ref enum_name) => match schema.find(enum_name) {
+                    Some(Declaration::Enum(declaration)) => declaration.ty,
+                    _ => return Err(format!("enum {} not found in schema", enum_name)),
+                },
+            };
+            let width = field.width.unwrap_or_else(|| ty.bits()) as usize;
+            fields.push(FieldLayout {
+                name: field.name.clone(),
+                ty,
+                offset,
+                width,
+            });
+            offset += width;
+        }
+        Ok(Self {
+            name: name.into(),
+            fields,
+            size_in_bytes: offset.div_ceil(8),
+        })
+    }
+
+    /// Layout of multivector indexes with the given size in bits.
+    fn index(index_width: u32) -> Self {
+        Self {
+            name: format!("IndexType{}", index_width),
+            fields: vec![FieldLayout {
+                name: "value".into(),
+                ty: BasicType::U64,
+                offset: 0,
+                width: index_width as usize,
+            }],
+            size_in_bytes: (index_width as usize).div_ceil(8),
+        }
+    }
+}
+
+/// Read-only handle to a struct whose layout is only known at runtime.
+///
+/// Field values are decoded by the same rules as [`read_bytes`], i.e. as
+/// generated code decodes them.
+///
+/// [`read_bytes`]: macro.read_bytes.html
+#[derive(Clone, Copy)]
+pub struct DynamicStruct<'a> {
+    layout: &'a StructLayout,
+    data: &'a [u8],
+}
+
+impl<'a> DynamicStruct<'a> {
+    /// Returns the layout of the struct.
+    pub fn layout(&self) -> &'a StructLayout {
+        self.layout
+    }
+
+    /// Returns the qualified name of the struct.
+    pub fn name(&self) -> &'a str {
+        &self.layout.name
+    }
+
+    /// Returns the value of the field with the given name, or `None` if the
+    /// struct has no such field.
+    pub fn get(&self, field_name: &str) -> Option<Value> {
+        self.layout
+            .fields
+            .iter()
+            .find(|field| field.name == field_name)
+            .map(|field| self.decode(field))
+    }
+
+    /// Returns the names and values of all fields in order of their layout.
+    pub fn fields(&self) -> impl Iterator<Item = (&'a str, Value)> + 'a {
+        let this = *self;
+        self.layout
+            .fields
+            .iter()
+            .map(move |field| (field.name.as_str(), this.decode(field)))
+    }
+
+    /// Returns the raw bytes of the struct.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        &self.data[..self.layout.size_in_bytes]
+    }
+
+    // With widths known only at runtime, clippy suggests `div_ceil` in the
+    // expansion of `read_bytes`, which is meant for constant widths.
+    #[allow(clippy::manual_div_ceil)]
+    fn decode(&self, field: &FieldLayout) -> Value {
+        let data = self.data.as_ptr();
+        match field.ty {
+            BasicType::Bool => Value::Bool(read_bytes!(bool, data, field.offset, field.width)),
+            ty if ty.is_signed() => {
+                Value::Signed(read_bytes!(i64, data, field.offset, field.width))
+            }
+            _ => Value::Unsigned(read_bytes!(u64, data, field.offset, field.width)),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for DynamicStruct<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct(&self.layout.name);
+        for (name, value) in self.fields() {
+            s.field(name, &value);
+        }
+        s.finish()
+    }
+}
+
+/// Read-only view on a vector of structs whose layout is only known at
+/// runtime; the dynamic counterpart of [`ArrayView`].
+///
+/// [`ArrayView`]: struct.ArrayView.html
+#[derive(Clone, Copy)]
+pub struct DynamicArrayView<'a> {
+    layout: &'a StructLayout,
+    data: &'a [u8],
+}
+
+impl<'a> DynamicArrayView<'a> {
+    /// Returns the layout of the elements.
+    pub fn layout(&self) -> &'a StructLayout {
+        self.layout
+    }
+
+    /// Number of elements in the array.
+    pub fn len(&self) -> usize {
+        match self.layout.size_in_bytes {
+            0 => 0,
+            size => self.data.len() / size,
+        }
+    }
+
+    /// Return `true` if the array is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a read-only handle to the element in the array at position
+    /// `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if index is greater than or equal to `DynamicArrayView::len()`.
+    pub fn at(&self, index: usize) -> DynamicStruct<'a> {
+        assert!(index < self.len());
+        DynamicStruct {
+            layout: self.layout,
+            data: &self.data[index * self.layout.size_in_bytes..],
+        }
+    }
+
+    /// Returns an iterator to the elements of the array.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = DynamicStruct<'a>> + 'a {
+        let view = *self;
+        (0..self.len()).map(move |index| view.at(index))
+    }
+
+    /// Returns a raw bytes representation of the underlying array data.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> fmt::Debug for DynamicArrayView<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let preview: Vec<_> = self.iter().take(super::DEBUG_PREVIEW_LEN).collect();
+        write!(
+            f,
+            "DynamicArrayView {{ len: {}, data: {:?}{} }}",
+            self.len(),
+            preview,
+            if self.len() <= super::DEBUG_PREVIEW_LEN {
+                ""
+            } else {
+                "..."
+            }
+        )
+    }
+}
+
+/// Read-only view on a multivector whose types are only known at runtime;
+/// the dynamic counterpart of [`MultiArrayView`].
+///
+/// [`MultiArrayView`]: struct.MultiArrayView.html
+#[derive(Clone, Copy)]
+pub struct DynamicMultiArrayView<'a> {
+    index: DynamicArrayView<'a>,
+    types: &'a [StructLayout],
+    data: &'a [u8],
+}
+
+impl<'a> DynamicMultiArrayView<'a> {
+    /// Returns the layouts of the variadic types in order of their type
+    /// index.
+    pub fn types(&self) -> &'a [StructLayout] {
+        self.types
+    }
+
+    /// Number of items in the multivector.
+    pub fn len(&self) -> usize {
+        self.index.len().saturating_sub(1)
+    }
+
+    /// Return `true` if the multivector is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the elements of the item at position `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if index is greater than or equal to
+    /// `DynamicMultiArrayView::len()`.
+    pub fn at(&self, index: usize) -> DynamicMultiArrayViewItemIter<'a> {
+        assert!(index < self.len());
+        let offset = |index| match self.index.at(index).get("value") {
+            Some(Value::Unsigned(value)) => value as usize,
+            _ => unreachable!("index has an unsigned value"),
+        };
+        DynamicMultiArrayViewItemIter {
+            types: self.types,
+            data: &self.data[offset(index)..offset(index + 1)],
+        }
+    }
+
+    /// Returns an iterator over the items of the multivector.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = DynamicMultiArrayViewItemIter<'a>> + 'a {
+        let view = *self;
+        (0..self.len()).map(move |index| view.at(index))
+    }
+}
+
+impl<'a> fmt::Debug for DynamicMultiArrayView<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let preview: Vec<(usize, Vec<_>)> = self
+            .iter()
+            .take(super::DEBUG_PREVIEW_LEN)
+            .enumerate()
+            .map(|(index, item)| (index, item.collect()))
+            .collect();
+        write!(
+            f,
+            "DynamicMultiArrayView {{ len: {}, data: {:?}{} }}",
+            self.len(),
+            preview,
+            if self.len() <= super::DEBUG_PREVIEW_LEN {
+                ""
+            } else {
+                "..."
+            }
+        )
+    }
+}
+
+/// Iterator over the elements of an item of a [`DynamicMultiArrayView`].
+///
+/// Iteration stops early at an element with a type index without a type, or
+/// at an element truncated by the end of the item. Then, the remaining bytes
+/// of the item are not empty.
+///
+/// [`DynamicMultiArrayView`]: struct.DynamicMultiArrayView.html
+#[derive(Debug, Clone)]
+pub struct DynamicMultiArrayViewItemIter<'a> {
+    types: &'a [StructLayout],
+    data: &'a [u8],
+}
+
+impl<'a> DynamicMultiArrayViewItemIter<'a> {
+    /// Returns the bytes of the elements which were not iterated yet.
+    pub fn remaining_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> iter::Iterator for DynamicMultiArrayViewItemIter<'a> {
+    type Item = DynamicStruct<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&type_index, data) = self.data.split_first()?;
+        let layout = self.types.get(type_index as usize)?;
+        if data.len() < layout.size_in_bytes {
+            return None;
+        }
+        self.data = &data[layout.size_in_bytes..];
+        Some(DynamicStruct { layout, data })
+    }
+}
+
+impl<'a> iter::FusedIterator for DynamicMultiArrayViewItemIter<'a> {}
+
+/// Resource of a [`DynamicArchive`].
+///
+/// [`DynamicArchive`]: struct.DynamicArchive.html
+#[derive(Debug, Clone, Copy)]
+pub enum DynamicResource<'a> {
+    /// Single struct.
+    Instance(DynamicStruct<'a>),
+    /// Vector of structs.
+    Vector(DynamicArrayView<'a>),
+    /// Multivector.
+    Multivector(DynamicMultiArrayView<'a>),
+    /// Raw bytes.
+    RawData(&'a [u8]),
+    /// Subarchive.
+    Archive(&'a DynamicArchive),
+}
+
+#[derive(Debug)]
+enum ResourceData {
+    Instance(StructLayout, MemoryDescriptor),
+    Vector(StructLayout, MemoryDescriptor),
+    Multivector {
+        index: StructLayout,
+        index_data: MemoryDescriptor,
+        types: Vec<StructLayout>,
+        data: MemoryDescriptor,
+    },
+    RawData(MemoryDescriptor),
+    Archive(Box<DynamicArchive>),
+}
+
+/// Archive opened without generated code.
+///
+/// The archive schema is read from the stored `{name}.archive.schema`, and
+/// the layout of each resource is computed from its stored schema. Thus, any
+/// archive can be read, e.g. by tools which inspect archives of different
+/// types. Schemas are verified as [`Archive::open`] does, with the stored
+/// schemas as expected schemas.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{DynamicArchive, DynamicResource, FileResourceStorage, Value};
+///
+/// let storage = FileResourceStorage::new("tests/coappearances/karenina.archive");
+/// let archive = DynamicArchive::open(storage).unwrap();
+/// assert_eq!(archive.name(), "Graph");
+/// match archive.resource("vertices") {
+///     Some(DynamicResource::Vector(vertices)) => {
+///         assert_eq!(vertices.len(), 138);
+///         assert_eq!(vertices.at(0).get("name_ref"), Some(Value::Unsigned(99)));
+///     }
+///     otherwise => panic!("unexpected resource: {:?}", otherwise),
+/// }
+/// # }
+/// ```
+///
+/// [`Archive::open`]: trait.Archive.html#tymethod.open
+pub struct DynamicArchive {
+    name: String,
+    schema: schema::Archive,
+    resources: Vec<(String, Option<ResourceData>)>,
+    storage: Arc<dyn ResourceStorage>,
+}
+
+impl DynamicArchive {
+    /// Opens the only archive in the given storage, i.e. the archive whose
+    /// signature `{name}.archive` is stored in it.
+    pub fn open(storage: Arc<dyn ResourceStorage>) -> Result<Self, ResourceStorageError> {
+        let signatures: Vec<String> = storage
+            .list_resources()
+            .map_err(|e| ResourceStorageError::from_io_error(e, "*.archive".into()))?
+            .into_iter()
+            .filter(|name| name.ends_with(".archive"))
+            .collect();
+        match signatures.as_slice() {
+            [signature] => {
+                let name = signature.trim_end_matches(".archive").to_string();
+                Self::open_named(storage, &name)
+            }
+            _ => Err(invalid_data(
+                "*.archive",
+                format!("expected one archive, found {}", signatures.len()),
+            )),
+        }
+    }
+
+    /// Opens the archive with the given name, e.g. `Graph`, in the given
+    /// storage.
+    pub fn open_named(
+        storage: Arc<dyn ResourceStorage>,
+        name: &str,
+    ) -> Result<Self, ResourceStorageError> {
+        let signature_name = format!("{}.archive", name);
+        let schema = read_schema(&*storage, &signature_name)?;
+        let archive_schema = schema
+            .declarations()
+            .find_map(|(_, declaration)| match declaration {
+                Declaration::Archive(archive) if archive.name == name => Some(archive.clone()),
+                _ => None,
+            })
+            .ok_or_else(|| invalid_data(&signature_name, format!("archive {} not found", name)))?;
+
+        let mut resources = Vec::new();
+        for resource in &archive_schema.resources {
+            let data = match resource.ty {
+                ResourceType::Archive(ref archive_name) => {
+                    let archive_name = archive_name.rsplit('.').next().unwrap_or(archive_name);
+                    let subdir = storage.subdir(&resource.name);
+                    if resource.is_optional()
+                        && !subdir.exists(&format!("{}.archive", archive_name))
+                    {
+                        None
+                    } else {
+                        Some(ResourceData::Archive(Box::new(Self::open_named(
+                            subdir,
+                            archive_name,
+                        )?)))
+                    }
+                }
+                _ if resource.is_optional() && !storage.exists(&resource.name) => None,
+                _ => Some(read_resource(&*storage, &resource.name)?),
+            };
+            resources.push((resource.name.clone(), data));
+        }
+
+        Ok(Self {
+            name: name.into(),
+            schema: archive_schema,
+            resources,
+            storage,
+        })
+    }
+
+    /// Returns the name of the archive, e.g. `Graph`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the schema of the archive.
+    pub fn schema(&self) -> &schema::Archive {
+        &self.schema
+    }
+
+    /// Returns the storage the archive was opened from.
+    pub fn storage(&self) -> &Arc<dyn ResourceStorage> {
+        &self.storage
+    }
+
+    /// Returns the names of all resources in order of the schema.
+    pub fn resource_names(&self) -> impl Iterator<Item = &str> {
+        self.resources.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the resource with the given name, or `None` if the archive has
+    /// no such resource or an optional resource is missing.
+    pub fn resource(&self, name: &str) -> Option<DynamicResource<'_>> {
+        let (_, data) = self.resources.iter().find(|(n, _)| n == name)?;
+        Some(match data.as_ref()? {
+            ResourceData::Instance(layout, data) => DynamicResource::Instance(DynamicStruct {
+                layout,
+                data: bytes(data),
+            }),
+            ResourceData::Vector(layout, data) => DynamicResource::Vector(DynamicArrayView {
+                layout,
+                data: bytes(data),
+            }),
+            ResourceData::Multivector {
+                index,
+                index_data,
+                types,
+                data,
+            } => DynamicResource::Multivector(DynamicMultiArrayView {
+                index: DynamicArrayView {
+                    layout: index,
+                    data: bytes(index_data),
+                },
+                types,
+                data: bytes(data),
+            }),
+            ResourceData::RawData(data) => DynamicResource::RawData(bytes(data)),
+            ResourceData::Archive(archive) => DynamicResource::Archive(archive),
+        })
+    }
+}
+
+/// Returns the bytes of a resource of an archive, which are owned by its
+/// storage, and thus, live as long as the archive.
+fn bytes(data: &MemoryDescriptor) -> &[u8] {
+    unsafe { data.as_bytes() }
+}
+
+impl fmt::Debug for DynamicArchive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut s = f.debug_struct("DynamicArchive");
+        s.field("name", &self.name);
+        for name in self.resource_names() {
+            s.field(name, &self.resource(name));
+        }
+        s.finish()
+    }
+}
+
+fn invalid_data(resource_name: &str, message: String) -> ResourceStorageError {
+    ResourceStorageError::from_io_error(
+        io::Error::new(io::ErrorKind::InvalidData, message),
+        resource_name.into(),
+    )
+}
+
+/// Reads and parses the stored schema of a resource.
+fn read_schema(
+    storage: &dyn ResourceStorage,
+    resource_name: &str,
+) -> Result<Schema, ResourceStorageError> {
+    let stored = stored_schema(storage, resource_name)?;
+    let stored = stored
+        .strip_prefix("index(")
+        .and_then(|schema| schema.strip_suffix(')'))
+        .unwrap_or(stored);
+    schema::parse(stored).map_err(|e| {
+        invalid_data(
+            resource_name,
+            format!("invalid schema {}.schema: {}", resource_name, e),
+        )
+    })
+}
+
+fn stored_schema<'a>(
+    storage: &'a dyn ResourceStorage,
+    resource_name: &str,
+) -> Result<&'a str, ResourceStorageError> {
+    let schema = storage
+        .read_resource(&format!("{}.schema", resource_name))
+        .map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                ResourceStorageError::MissingSchema(resource_name.into())
+            } else {
+                ResourceStorageError::from_io_error(e, resource_name.into())
+            }
+        })?;
+    str::from_utf8(schema).map_err(|e| ResourceStorageError::Utf8Error(e, resource_name.into()))
+}
+
+/// Reads a resource with its stored schema, and computes its layout.
+fn read_resource(
+    storage: &dyn ResourceStorage,
+    resource_name: &str,
+) -> Result<ResourceData, ResourceStorageError> {
+    let schema = read_schema(storage, resource_name)?;
+    let resource_schema = schema
+        .declarations()
+        .find_map(|(_, declaration)| match declaration {
+            Declaration::Archive(archive) => archive
+                .resources
+                .iter()
+                .find(|resource| resource.name == resource_name),
+            _ => None,
+        })
+        .ok_or_else(|| {
+            invalid_data(
+                resource_name,
+                format!("resource {} not found in its schema", resource_name),
+            )
+        })?;
+    let layout = |name: &str| {
+        StructLayout::new(&schema, name).map_err(|message| invalid_data(resource_name, message))
+    };
+    let read = |name: &str| -> Result<MemoryDescriptor, ResourceStorageError> {
+        let data = storage.read(name, stored_schema(storage, name)?)?;
+        Ok(MemoryDescriptor::new(data))
+    };
+    let read_structs = |layout: &StructLayout, is_vector: bool| {
+        let data = storage.read(resource_name, stored_schema(storage, resource_name)?)?;
+        check_size(resource_name, data.len(), layout, is_vector)?;
+        Ok(MemoryDescriptor::new(data))
+    };
+    Ok(match resource_schema.ty {
+        ResourceType::Instance(ref name) => {
+            let layout = layout(name)?;
+            let data = read_structs(&layout, false)?;
+            ResourceData::Instance(layout, data)
+        }
+        ResourceType::Vector(ref name) => {
+            let layout = layout(name)?;
+            let data = read_structs(&layout, true)?;
+            ResourceData::Vector(layout, data)
+        }
+        ResourceType::Multivector {
+            index_width,
+            ref types,
+        } => {
+            let index_name = format!("{}_index", resource_name);
+            let index = StructLayout::index(index_width);
+            let index_data = read(&index_name)?;
+            check_size(&index_name, bytes(&index_data).len(), &index, true)?;
+            let data = read(resource_name)?;
+            check_index(&index_name, &index, &index_data, bytes(&data).len())?;
+            ResourceData::Multivector {
+                index,
+                index_data,
+                types: types
+                    .iter()
+                    .map(|name| layout(name))
+                    .collect::<Result<_, _>>()?,
+                data,
+            }
+        }
+        ResourceType::RawData => ResourceData::RawData(read(resource_name)?),
+        ResourceType::Archive(_) => {
+            return Err(invalid_data(
+                resource_name,
+                "subarchive is not a resource".into(),
+            ))
+        }
+    })
+}
+
+/// Checks that data contains a whole instance, or a whole number of vector
+/// elements, of a struct, such that no struct is read past the end of data.
+fn check_size(
+    resource_name: &str,
+    actual: usize,
+    layout: &StructLayout,
+    is_vector: bool,
+) -> Result<(), ResourceStorageError> {
+    let expected = match layout.size_in_bytes {
+        0 => actual,
+        size if is_vector => actual.next_multiple_of(size),
+        size => cmp::max(actual, size),
+    };
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(ResourceStorageError::UnexpectedDataSize {
+            resource_name: resource_name.into(),
+            expected,
+            actual,
+        })
+    }
+}
+
+/// Checks that the offsets in the index of a multivector are monotonic and
+/// within its data, such that no item is read past the end of data.
+fn check_index(
+    index_name: &str,
+    layout: &StructLayout,
+    index_data: &MemoryDescriptor,
+    data_len: usize,
+) -> Result<(), ResourceStorageError> {
+    let index = DynamicArrayView {
+        layout,
+        data: bytes(index_data),
+    };
+    let mut end = 0;
+    for (pos, element) in index.iter().enumerate() {
+        let offset = match element.get("value") {
+            Some(Value::Unsigned(value)) => value,
+            _ => unreachable!("index has an unsigned value"),
+        };
+        if offset < end {
+            return Err(invalid_data(
+                index_name,
+                format!(
+                    "offset {} at position {} is smaller than the previous offset {}",
+                    offset, pos, end
+                ),
+            ));
+        }
+        end = offset;
+    }
+    let end = usize::try_from(end).unwrap_or(usize::MAX);
+    if end <= data_len {
+        Ok(())
+    } else {
+        Err(ResourceStorageError::UnexpectedDataSize {
+            resource_name: index_name.into(),
+            expected: end,
+            actual: data_len,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memstorage::MemoryResourceStorage;
+
+    const SCHEMA: &str = "namespace n {\n\
+                          enum Kind : i8 { A = -1, B }\n\
+                          struct S { flag : bool; kind : .n.Kind : 3; value : u32 : 20; }\n\
+                          }\n\
+                          namespace n { archive A { s : vector< .n.S >; } }";
+
+    #[test]
+    fn compute_struct_layout() {
+        let schema = schema::parse(SCHEMA).unwrap();
+        let layout = StructLayout::new(&schema, ".n.S").unwrap();
+        let fields: Vec<_> = layout
+            .fields
+            .iter()
+            .map(|field| (&field.name[..], field.ty, field.offset, field.width))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("flag", BasicType::Bool, 0, 1),
+                ("kind", BasicType::I8, 1, 3),
+                ("value", BasicType::U32, 4, 20),
+            ]
+        );
+        assert_eq!(layout.size_in_bytes, 3);
+        assert!(StructLayout::new(&schema, ".n.T").is_err());
+    }
+
+    #[test]
+    fn read_vector_resource() {
+        let mut data = vec![0u8; 2 * 3 + crate::memory::PADDING_SIZE];
+        let element = &mut data[3..];
+        write_bytes!(bool; true, element, 0, 1);
+        write_bytes!(i8; -2i8, element, 1, 3);
+        write_bytes!(u32; 0xfffff, element, 4, 20);
+
+        let storage = MemoryResourceStorage::new("/root/dynamic");
+        storage.write("s", SCHEMA, &data[..6]).unwrap();
+        match read_resource(&*storage, "s").unwrap() {
+            ResourceData::Vector(layout, data) => {
+                let view = DynamicArrayView {
+                    layout: &layout,
+                    data: unsafe { data.as_bytes() },
+                };
+                assert_eq!(view.len(), 2);
+                let values: Vec<_> = view.at(1).fields().collect();
+                assert_eq!(
+                    values,
+                    vec![
+                        ("flag", Value::Bool(true)),
+                        ("kind", Value::Signed(-2)),
+                        ("value", Value::Unsigned(0xfffff)),
+                    ]
+                );
+                assert_eq!(view.at(0).get("value"), Some(Value::Unsigned(0)));
+                assert_eq!(view.at(0).get("missing"), None);
+            }
+            otherwise => panic!("unexpected resource: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn reject_truncated_structs() {
+        let storage = MemoryResourceStorage::new("/root/dynamic");
+        storage.write("s", SCHEMA, &[0; 4]).unwrap();
+        match read_resource(&*storage, "s") {
+            Err(ResourceStorageError::UnexpectedDataSize {
+                expected: 6,
+                actual: 4,
+                ..
+            }) => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+
+        let schema = "namespace n { struct S { value : u32 : 20; } }\n\
+                      namespace n { archive A { s : .n.S; } }";
+        let storage = MemoryResourceStorage::new("/root/dynamic");
+        storage.write("s", schema, &[0; 2]).unwrap();
+        match read_resource(&*storage, "s") {
+            Err(ResourceStorageError::UnexpectedDataSize {
+                expected: 3,
+                actual: 2,
+                ..
+            }) => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn reject_corrupt_multivector_index() {
+        let schema = "namespace n { struct S { value : u32 : 20; } }\n\
+                      namespace n { archive A { m : multivector< 32, .n.S >; } }";
+        let index_schema = format!("index({})", schema);
+        let read = |offsets: &[u32]| {
+            let index: Vec<u8> = offsets.iter().flat_map(|x| x.to_le_bytes()).collect();
+            let storage = MemoryResourceStorage::new("/root/dynamic");
+            storage.write("m_index", &index_schema, &index).unwrap();
+            // an item with one element, a truncated item, and an item with an
+            // invalid type index
+            storage
+                .write("m", schema, &[0, 1, 0, 0, 0, 5, 7, 0, 0, 0])
+                .unwrap();
+            read_resource(&*storage, "m")
+        };
+
+        match read(&[0, 4, 6, 10]).unwrap() {
+            ResourceData::Multivector {
+                index,
+                index_data,
+                types,
+                data,
+            } => {
+                let view = DynamicMultiArrayView {
+                    index: DynamicArrayView {
+                        layout: &index,
+                        data: bytes(&index_data),
+                    },
+                    types: &types,
+                    data: bytes(&data),
+                };
+                assert_eq!(view.len(), 3);
+                let mut item = view.at(0);
+                assert_eq!(item.next().unwrap().get("value"), Some(Value::Unsigned(1)));
+                assert!(item.next().is_none());
+                for (index, remaining) in [(1, &[0, 5][..]), (2, &[7, 0, 0, 0][..])] {
+                    let mut item = view.at(index);
+                    assert!(item.next().is_none());
+                    assert_eq!(item.remaining_bytes(), remaining);
+                }
+            }
+            otherwise => panic!("unexpected resource: {:?}", otherwise),
+        }
+        match read(&[0, 6, 4, 10]) {
+            Err(ResourceStorageError::Io(ref e, ref name)) if name == "m_index" => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData)
+            }
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+        match read(&[0, 4, 4294967280]) {
+            Err(ResourceStorageError::UnexpectedDataSize {
+                expected: 4294967280,
+                actual: 10,
+                ..
+            }) => (),
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
+}
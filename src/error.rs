@@ -1,3 +1,5 @@
+use crate::schema::SchemaChange;
+
 use std::error;
 use std::fmt;
 use std::io;
@@ -6,7 +8,13 @@ use std::str::Utf8Error;
 /// Error indicating failures when reading and writing data from/to a
 /// [`Storage`].
 ///
+/// Resource names are relative to the archive in which the error occurred;
+/// errors of [`Archive::open`] are wrapped in [`InArchive`] with the path of
+/// the archive or subarchive.
+///
 /// [`Storage`]: trait.Storage.html
+/// [`Archive::open`]: trait.Archive.html#tymethod.open
+/// [`InArchive`]: #variant.InArchive
 #[derive(Debug)]
 pub enum ResourceStorageError {
     /// Wrapper of [`io::Error`] with resource name for which the error
@@ -14,10 +22,11 @@ pub enum ResourceStorageError {
     ///
     /// [`io::Error`]: https://doc.rust-lang.org/std/io/struct.Error.html
     Io(io::Error, String),
-    /// Wrapper of [`Utf8Error`].
+    /// Wrapper of [`Utf8Error`] with resource name whose schema is not valid
+    /// UTF-8.
     ///
     /// [`Utf8Error`]: https://doc.rust-lang.org/std/str/struct.Utf8Error.html
-    Utf8Error(Utf8Error),
+    Utf8Error(Utf8Error, String),
     /// Indicates that schema for the resource with stored name is missing in
     /// resource storage.
     MissingSchema(String),
@@ -26,7 +35,10 @@ pub enum ResourceStorageError {
     WrongSignature {
         /// Resource name for which the error occurred.
         resource_name: String,
-        /// Diff from the stored schema to the expected schema.
+        /// Semantic changes from the stored schema to the expected schema;
+        /// empty if one of the schemas cannot be parsed.
+        changes: Vec<SchemaChange>,
+        /// Line-based diff from the stored schema to the expected schema.
         diff: String,
     },
     /// Indicates that the size of the data does not fit to the serialized
@@ -35,7 +47,33 @@ pub enum ResourceStorageError {
     /// When data is serialized to resource storage, a control header is
     /// written which, in particular, contains the final size of the whole
     /// resource.
-    UnexpectedDataSize,
+    UnexpectedDataSize {
+        /// Resource name for which the error occurred.
+        resource_name: String,
+        /// Size in bytes according to the control header, the minimal size
+        /// of a resource if the data is too small to contain a header, or the
+        /// size required by the struct layout of a dynamically read resource.
+        expected: usize,
+        /// Actual size of the data in bytes.
+        actual: usize,
+    },
+    /// Indicates that a non-optional resource with stored name was not written
+    /// when finishing an archive builder.
+    MissingResource(String),
+    /// Indicates that a resource with stored name was started, but not closed
+    /// when finishing an archive builder.
+    ResourceNotClosed(String),
+    /// Indicates that the data of the resource with stored name does not match
+    /// the checksum stored in resource storage.
+    ChecksumMismatch(String),
+    /// Error which occurred in an archive.
+    InArchive {
+        /// Path of the archive, i.e. the name of the opened archive followed
+        /// by the names of the subarchive resources, e.g. `Graph/data`.
+        path: String,
+        /// Underlying error.
+        source: Box<ResourceStorageError>,
+    },
 }
 
 impl ResourceStorageError {
@@ -45,11 +83,88 @@ impl ResourceStorageError {
     pub fn from_io_error(err: io::Error, resource_name: String) -> Self {
         ResourceStorageError::Io(err, resource_name)
     }
+
+    /// Wraps the error in [`InArchive`] with the given archive name, which is
+    /// prepended to the path if the error is already wrapped.
+    ///
+    /// [`InArchive`]: #variant.InArchive
+    pub fn in_archive(self, name: &str) -> Self {
+        match self {
+            ResourceStorageError::InArchive { path, source } => ResourceStorageError::InArchive {
+                path: format!("{}/{}", name, path),
+                source,
+            },
+            err => ResourceStorageError::InArchive {
+                path: name.into(),
+                source: Box::new(err),
+            },
+        }
+    }
+
+    /// Returns the error without archive context, i.e. the innermost source
+    /// of [`InArchive`] errors.
+    ///
+    /// [`InArchive`]: #variant.InArchive
+    pub fn root_cause(&self) -> &Self {
+        match *self {
+            ResourceStorageError::InArchive { ref source, .. } => source.root_cause(),
+            ref err => err,
+        }
+    }
 }
 
 impl fmt::Display for ResourceStorageError {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{:?}", self)
+        match *self {
+            ResourceStorageError::Io(ref err, ref name) => {
+                write!(f, "failed to access resource {}: {}", name, err)
+            }
+            ResourceStorageError::Utf8Error(ref err, ref name) => {
+                write!(f, "schema of resource {} is not valid UTF-8: {}", name, err)
+            }
+            ResourceStorageError::MissingSchema(ref name) => {
+                write!(f, "schema of resource {} is missing", name)
+            }
+            ResourceStorageError::WrongSignature {
+                ref resource_name,
+                ref changes,
+                ref diff,
+            } => {
+                write!(
+                    f,
+                    "schema of resource {} does not match the expected schema",
+                    resource_name
+                )?;
+                if changes.is_empty() {
+                    write!(f, ":\n{}", diff)
+                } else {
+                    let changes: Vec<String> = changes.iter().map(ToString::to_string).collect();
+                    write!(f, ": {}", changes.join("; "))
+                }
+            }
+            ResourceStorageError::UnexpectedDataSize {
+                ref resource_name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "resource {} has unexpected size: expected {} bytes, found {} bytes",
+                resource_name, expected, actual
+            ),
+            ResourceStorageError::MissingResource(ref name) => {
+                write!(f, "resource {} is missing", name)
+            }
+            ResourceStorageError::ResourceNotClosed(ref name) => {
+                write!(f, "resource {} was not closed", name)
+            }
+            ResourceStorageError::ChecksumMismatch(ref name) => {
+                write!(f, "data of resource {} does not match its checksum", name)
+            }
+            ResourceStorageError::InArchive {
+                ref path,
+                ref source,
+            } => write!(f, "in archive {}: {}", path, source),
+        }
     }
 }
 
@@ -58,9 +173,71 @@ impl error::Error for ResourceStorageError {
         match *self {
             ResourceStorageError::Io(_, _) => "resource io error",
             ResourceStorageError::MissingSchema(_) => "schema of resource is missing",
-            ResourceStorageError::UnexpectedDataSize => "resource has unexpected size",
-            ResourceStorageError::Utf8Error(_) => "utf8 error in schema",
+            ResourceStorageError::UnexpectedDataSize { .. } => "resource has unexpected size",
+            ResourceStorageError::Utf8Error(_, _) => "utf8 error in schema",
             ResourceStorageError::WrongSignature { .. } => "schema is not matching expected schema",
+            ResourceStorageError::MissingResource(_) => "resource is missing",
+            ResourceStorageError::ResourceNotClosed(_) => "resource was not closed",
+            ResourceStorageError::ChecksumMismatch(_) => "resource data does not match checksum",
+            ResourceStorageError::InArchive { .. } => "error in archive",
+        }
+    }
+
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ResourceStorageError::Io(ref err, _) => Some(err),
+            ResourceStorageError::Utf8Error(ref err, _) => Some(err),
+            ResourceStorageError::InArchive { ref source, .. } => Some(&**source),
+            _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn wrap_errors_in_archive_path() {
+        let err = ResourceStorageError::UnexpectedDataSize {
+            resource_name: "vertices".into(),
+            expected: 24,
+            actual: 16,
+        }
+        .in_archive("data")
+        .in_archive("Graph");
+
+        match err {
+            ResourceStorageError::InArchive { ref path, .. } => assert_eq!(path, "Graph/data"),
+            ref otherwise => panic!("unexpected error: {:?}", otherwise),
+        }
+        assert_eq!(
+            err.to_string(),
+            "in archive Graph/data: resource vertices has unexpected size: \
+             expected 24 bytes, found 16 bytes"
+        );
+        match *err.root_cause() {
+            ResourceStorageError::UnexpectedDataSize { actual: 16, .. } => (),
+            ref otherwise => panic!("unexpected error: {:?}", otherwise),
+        }
+        let source = err.source().unwrap().to_string();
+        assert!(source.starts_with("resource vertices"), "{}", source);
+    }
+
+    #[test]
+    fn display_underlying_errors() {
+        let err = ResourceStorageError::from_io_error(
+            io::Error::new(io::ErrorKind::NotFound, "no such file"),
+            "edges".into(),
+        );
+        assert_eq!(
+            err.to_string(),
+            "failed to access resource edges: no such file"
+        );
+        assert_eq!(err.source().unwrap().to_string(), "no such file");
+        assert!(ResourceStorageError::MissingSchema("edges".into())
+            .source()
+            .is_none());
+    }
+}
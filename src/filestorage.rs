@@ -1,29 +1,64 @@
-use crate::storage::{ResourceStorage, Stream};
+use crate::memory::SizeType;
+#[cfg(target_os = "linux")]
+use crate::paging::Residency;
+use crate::paging::{self, Advice};
+use crate::storage::{seek_and_write_size, ResourceStorage, Stream};
 
 use memmap::Mmap;
 
-use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io;
-use std::path::PathBuf;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How pages of memory mapped resources are loaded into memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoadMode {
+    /// Pages are read on first access (default).
+    #[default]
+    OnDemand,
+    /// All pages of a resource are read when it is mapped, similar to
+    /// `MAP_POPULATE`.
+    Populate,
+    /// All pages of a resource are read and locked in memory when it is
+    /// mapped, cf. `mlock(2)`. Mapping fails if the pages cannot be locked,
+    /// e.g. due to `RLIMIT_MEMLOCK`.
+    Lock,
+}
 
 /// Internal storage of data as files.
 #[derive(Debug, Default)]
 struct MemoryMappedFileStorage {
-    maps: RefCell<BTreeMap<String, Mmap>>,
+    maps: Mutex<BTreeMap<String, Mmap>>,
+    load_mode: LoadMode,
 }
 
 impl MemoryMappedFileStorage {
+    fn new(load_mode: LoadMode) -> Self {
+        Self {
+            maps: Mutex::new(BTreeMap::new()),
+            load_mode,
+        }
+    }
+
     pub fn read(&self, path: &str) -> Result<&[u8], io::Error> {
-        if !self.maps.borrow().contains_key(path) {
+        let mut maps = self.maps.lock().unwrap();
+        if !maps.contains_key(path) {
             let file = File::open(path)?;
             let file_mmap = unsafe { Mmap::map(&file)? };
-            self.maps.borrow_mut().insert(path.into(), file_mmap);
+            match self.load_mode {
+                LoadMode::OnDemand => (),
+                LoadMode::Populate => paging::populate(&file_mmap),
+                LoadMode::Lock => paging::lock(&file_mmap)?,
+            }
+            maps.insert(path.into(), file_mmap);
         }
-        let data = &self.maps.borrow()[path];
+        let data = &maps[path];
         // We cannot prove to Rust that the buffer will live as long as the storage
         // (we never delete mappings), so we need to manually extend lifetime
         let extended_lifetime_data = unsafe { slice::from_raw_parts(data.as_ptr(), data.len()) };
@@ -31,37 +66,169 @@ impl MemoryMappedFileStorage {
     }
 }
 
-impl Stream for File {}
+impl Stream for File {
+    fn write_size_header(&mut self, size: SizeType) -> io::Result<()> {
+        seek_and_write_size(self, size)
+    }
+}
+
+/// Staging directory of a transactional file storage.
+#[derive(Debug)]
+struct Staging {
+    staging_path: PathBuf,
+    target_path: PathBuf,
+    committed: AtomicBool,
+}
 
 /// Resource storage on disk using memory mapped files.
 #[derive(Debug)]
 pub struct FileResourceStorage {
     storage: MemoryMappedFileStorage,
+    // For staged storages the path is relative to the staging directory.
     path: PathBuf,
+    staging: Option<Arc<Staging>>,
 }
 
 impl FileResourceStorage {
     /// Create an empty memory mapped file storage at a given path.
     #[allow(clippy::new_ret_no_self)]
-    pub fn new<P: Into<PathBuf>>(path: P) -> Rc<Self> {
-        Rc::new(Self {
-            storage: MemoryMappedFileStorage::default(),
+    pub fn new<P: Into<PathBuf>>(path: P) -> Arc<Self> {
+        Self::with_load_mode(path, LoadMode::OnDemand)
+    }
+
+    /// Create an empty memory mapped file storage at a given path, which
+    /// loads pages of resources as specified by `load_mode` when they are
+    /// mapped.
+    ///
+    /// Loading all pages upfront avoids page faults on first access, which is
+    /// useful for latency-critical services. Subdirectories use the same load
+    /// mode.
+    pub fn with_load_mode<P: Into<PathBuf>>(path: P, load_mode: LoadMode) -> Arc<Self> {
+        Arc::new(Self {
+            storage: MemoryMappedFileStorage::new(load_mode),
             path: path.into(),
+            staging: None,
         })
     }
+
+    /// Hints the expected access pattern of a resource to the operating
+    /// system, cf. `madvise(2)`.
+    ///
+    /// The resource is mapped if it was not yet.
+    pub fn advise(&self, resource_name: &str, advice: Advice) -> io::Result<()> {
+        paging::advise(self.read_resource(resource_name)?, advice)
+    }
+
+    /// Reports how many pages of a resource are resident in memory, cf.
+    /// `mincore(2)`.
+    ///
+    /// The resource is mapped if it was not yet; mapping alone does not load
+    /// any pages.
+    #[cfg(target_os = "linux")]
+    pub fn residency(&self, resource_name: &str) -> io::Result<Residency> {
+        paging::residency(self.read_resource(resource_name)?)
+    }
+
+    /// Create a transactional memory mapped file storage at a given path.
+    ///
+    /// All resources are written to a staging directory
+    /// `.{name}.staging.{pid}.{suffix}` next to `path`, which is atomically
+    /// renamed to `path` when the storage is committed, usually by finishing
+    /// the top-level archive builder. Until then, readers cannot pick up a
+    /// partially written archive at `path`. The staging directory is unique
+    /// to the storage, therefore, builds of the same archive in other
+    /// processes are not affected; only the first commit succeeds. If the
+    /// build process crashes, only the staging directory is left behind and
+    /// needs to be removed manually.
+    ///
+    /// # Errors
+    ///
+    /// Returns an IO error of kind [`AlreadyExists`] if `path` already
+    /// exists, or if the staging directory cannot be created. Committing
+    /// fails with [`AlreadyExists`] if `path` was created in the meantime.
+    ///
+    /// [`AlreadyExists`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#AlreadyExists.v
+    pub fn create_staged<P: Into<PathBuf>>(path: P) -> io::Result<Arc<Self>> {
+        let target_path = path.into();
+        if target_path.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                target_path.to_string_lossy().into_owned(),
+            ));
+        }
+        let name = target_path
+            .file_name()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    target_path.to_string_lossy().into_owned(),
+                )
+            })?
+            .to_string_lossy()
+            .into_owned();
+        if let Some(parent) = target_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let staging_path = create_staging_dir(&target_path, &name)?;
+        Ok(Arc::new(Self {
+            storage: MemoryMappedFileStorage::default(),
+            path: PathBuf::new(),
+            staging: Some(Arc::new(Staging {
+                staging_path,
+                target_path,
+                committed: AtomicBool::new(false),
+            })),
+        }))
+    }
+
+    /// Lists files (`dirs == false`) or directories (`dirs == true`) of this
+    /// storage. A storage whose directory does not exist yet is empty.
+    fn list(&self, dirs: bool) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(self.dir()) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() == dirs {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Directory of the resources of this storage.
+    fn dir(&self) -> PathBuf {
+        match self.staging {
+            None => self.path.clone(),
+            Some(ref staging) if staging.committed.load(Ordering::SeqCst) => {
+                staging.target_path.join(&self.path)
+            }
+            Some(ref staging) => staging.staging_path.join(&self.path),
+        }
+    }
 }
 
 impl ResourceStorage for FileResourceStorage {
-    fn subdir(&self, dir: &str) -> Rc<ResourceStorage> {
-        Self::new(self.path.join(dir))
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            storage: MemoryMappedFileStorage::new(self.storage.load_mode),
+            path: self.path.join(dir),
+            staging: self.staging.clone(),
+        })
     }
 
     fn exists(&self, resource_name: &str) -> bool {
-        self.path.join(resource_name).exists()
+        self.dir().join(resource_name).exists()
     }
 
     fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
-        let resource_path = self.path.join(resource_name);
+        let resource_path = self.dir().join(resource_name);
         if !resource_path.exists() {
             return Err(io::Error::new(
                 io::ErrorKind::NotFound,
@@ -78,12 +245,254 @@ impl ResourceStorage for FileResourceStorage {
         }
     }
 
-    fn create_output_stream(&self, resource_name: &str) -> Result<Rc<RefCell<Stream>>, io::Error> {
-        if !self.path.exists() {
-            fs::create_dir_all(self.path.clone())?;
+    fn create_output_stream(
+        &self,
+        resource_name: &str,
+    ) -> Result<Arc<Mutex<dyn Stream>>, io::Error> {
+        let dir = self.dir();
+        if !dir.exists() {
+            fs::create_dir_all(dir.clone())?;
         }
-        let resource_path = self.path.join(resource_name);
+        let resource_path = dir.join(resource_name);
         let file = File::create(resource_path)?;
-        Ok(Rc::new(RefCell::new(file)))
+        Ok(Arc::new(Mutex::new(file)))
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        self.list(false)
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        self.list(true)
+    }
+
+    fn advise(&self, resource_name: &str, advice: Advice) -> io::Result<()> {
+        FileResourceStorage::advise(self, resource_name, advice)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn residency(&self, resource_name: &str) -> io::Result<Option<Residency>> {
+        FileResourceStorage::residency(self, resource_name).map(Some)
+    }
+
+    /// Renames the staging directory of a staged storage to its final path.
+    ///
+    /// Has no effect for non-staged storages, subdirectories of staged
+    /// storages and already committed storages.
+    fn commit(&self) -> io::Result<()> {
+        let staging = match self.staging {
+            Some(ref staging) if self.path.as_os_str().is_empty() => staging,
+            _ => return Ok(()),
+        };
+        if staging.committed.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        rename_no_replace(&staging.staging_path, &staging.target_path)?;
+        staging.committed.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Renames a directory, failing with `AlreadyExists` if the target exists.
+///
+/// On Linux, the check is atomic; otherwise, an empty target directory is
+/// replaced, as `rename` does.
+fn rename_no_replace(from: &Path, to: &Path) -> io::Result<()> {
+    let already_exists = |e: io::Error| match e.raw_os_error() {
+        #[cfg(unix)]
+        Some(libc::EEXIST) | Some(libc::ENOTEMPTY) => io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            to.to_string_lossy().into_owned(),
+        ),
+        _ => e,
+    };
+    #[cfg(target_os = "linux")]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let from = CString::new(from.as_os_str().as_bytes())?;
+        let to = CString::new(to.as_os_str().as_bytes())?;
+        let result = unsafe {
+            libc::renameat2(
+                libc::AT_FDCWD,
+                from.as_ptr(),
+                libc::AT_FDCWD,
+                to.as_ptr(),
+                libc::RENAME_NOREPLACE,
+            )
+        };
+        if result == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            // not supported by the kernel or the file system
+            Some(libc::ENOSYS) | Some(libc::EINVAL) => (),
+            _ => return Err(already_exists(err)),
+        }
+    }
+    fs::rename(from, to).map_err(already_exists)
+}
+
+/// Creates a new staging directory next to `target_path` with a name unique
+/// to this process and call.
+fn create_staging_dir(target_path: &Path, name: &str) -> io::Result<PathBuf> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        let suffix = format!("{:x}{}", nanos, COUNTER.fetch_add(1, Ordering::SeqCst));
+        let staging_path =
+            target_path.with_file_name(format!(".{}.staging.{}.{}", name, process::id(), suffix));
+        match fs::create_dir(&staging_path) {
+            Ok(()) => return Ok(staging_path),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+
+    /// Returns the staging directories of the archive `archive` in `dir`.
+    fn staging_dirs(dir: &Path) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                name.starts_with(".archive.staging.")
+            })
+            .collect();
+        dirs.sort();
+        dirs
+    }
+
+    #[test]
+    fn commit_staged_storage() {
+        let dir = env::temp_dir().join("flatdata_file_storage_test/commit_staged_storage");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("archive");
+
+        let storage = FileResourceStorage::create_staged(&path).unwrap();
+        let staging_path = staging_dirs(&dir).pop().unwrap();
+        storage.write("a", "schema", &[1]).unwrap();
+        let sub = storage.subdir("sub");
+        sub.write("b", "schema", &[2]).unwrap();
+        sub.commit().unwrap();
+        assert!(!path.exists());
+        assert!(staging_path.join("a").exists());
+        assert!(staging_path.join("sub/b").exists());
+
+        storage.commit().unwrap();
+        assert!(!staging_path.exists());
+        assert!(path.join("a").exists());
+        assert_eq!(storage.read("a", "schema").unwrap(), &[1]);
+        assert_eq!(sub.read("b", "schema").unwrap(), &[2]);
+        storage.commit().unwrap();
+
+        let err = FileResourceStorage::create_staged(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn stage_same_archive_concurrently() {
+        let dir = env::temp_dir().join("flatdata_file_storage_test/stage_concurrently");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("archive");
+
+        let first = FileResourceStorage::create_staged(&path).unwrap();
+        first.write("a", "schema", &[1]).unwrap();
+        let second = FileResourceStorage::create_staged(&path).unwrap();
+        second.write("a", "schema", &[2]).unwrap();
+        assert_eq!(staging_dirs(&dir).len(), 2);
+        assert_eq!(first.read("a", "schema").unwrap(), &[1]);
+
+        second.commit().unwrap();
+        assert_eq!(
+            first.commit().unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(staging_dirs(&dir).len(), 1);
+        assert_eq!(
+            FileResourceStorage::new(&path).read("a", "schema").unwrap(),
+            &[2]
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn commit_does_not_replace_empty_directory() {
+        let dir = env::temp_dir().join("flatdata_file_storage_test/commit_no_replace");
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("archive");
+
+        let storage = FileResourceStorage::create_staged(&path).unwrap();
+        storage.write("a", "schema", &[1]).unwrap();
+        fs::create_dir(&path).unwrap();
+        assert_eq!(
+            storage.commit().unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert!(!path.join("a").exists());
+    }
+
+    #[test]
+    fn list_resources_and_subdirs() {
+        let dir = env::temp_dir().join("flatdata_file_storage_test/list");
+        let _ = fs::remove_dir_all(&dir);
+        let storage = FileResourceStorage::new(&dir);
+        assert!(storage.list_resources().unwrap().is_empty());
+
+        storage.write("b", "schema", &[1]).unwrap();
+        storage.subdir("sub").write("a", "schema", &[2]).unwrap();
+        assert_eq!(
+            storage.list_resources().unwrap(),
+            vec!["b", "b.checksum", "b.schema"]
+        );
+        assert_eq!(storage.list_subdirs().unwrap(), vec!["sub"]);
+        assert!(storage.subdir("sub").list_subdirs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn populate_and_advise_resources() {
+        let dir = env::temp_dir().join("flatdata_file_storage_test/populate_and_advise");
+        let _ = fs::remove_dir_all(&dir);
+        FileResourceStorage::new(&dir)
+            .write("a", "schema", &[42; 10000])
+            .unwrap();
+
+        let storage = FileResourceStorage::with_load_mode(&dir, LoadMode::Populate);
+        assert_eq!(storage.read("a", "schema").unwrap(), &[42; 10000][..]);
+        #[cfg(target_os = "linux")]
+        {
+            let residency = storage.residency("a").unwrap();
+            assert!(residency.total_pages > 0);
+            assert_eq!(residency.resident_pages, residency.total_pages);
+        }
+        for &advice in &[
+            Advice::Sequential,
+            Advice::Random,
+            Advice::WillNeed,
+            Advice::DontNeed,
+            Advice::Normal,
+        ] {
+            storage.advise("a", advice).unwrap();
+        }
+        // pages of file mappings are read again after `DontNeed`
+        assert_eq!(storage.read("a", "schema").unwrap(), &[42; 10000][..]);
+        assert_eq!(
+            storage
+                .advise("missing", Advice::Normal)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
     }
 }
@@ -18,10 +18,30 @@
 //!
 //! * macros for generated code (prefixed with `create_`),
 //! * macros for zero-cost serialization [`read_bytes`] and deserialization
-//! [`write_bytes`], * in-memory [`MemoryResourceStorage`] and memory-mapped
-//! [`FileResourceStorage`] storages, * data structures for writing data:
-//! [`StructBuf`], [`Vector`], [`ExternalVector`],   [`MultiVector`],
-//! * data structures for reading data: [`ArrayView`], [`MultiArrayView`].
+//!   [`write_bytes`],
+//! * in-memory [`MemoryResourceStorage`], memory-mapped
+//!   [`FileResourceStorage`], single-file [`BundleResourceStorage`], and
+//!   read-only [`TarResourceStorage`] and [`BufferResourceStorage`] storages,
+//! * streaming of archives to non-seekable outputs by
+//!   [`StreamingResourceStorage`],
+//! * layering of storages by [`OverlayResourceStorage`],
+//! * transparent compression of resources by [`CompressedResourceStorage`]
+//!   (requires the `zstd` or `lz4` feature),
+//! * access pattern hints ([`Advice`]) and eager loading of memory mapped
+//!   resources ([`LoadMode`]), and page-cache residency reports
+//!   ([`ResidencyReport`], Linux only),
+//! * rate-limited background warm-up of resources by [`WarmUp`],
+//! * byte-exact copying of archives between storages by [`copy_archive`],
+//! * opening archives written with a compatible older schema by
+//!   [`Archive::open_compatible`],
+//! * parsing of the schema language by [`schema::parse`], and semantic
+//!   comparison of schemas by [`schema::diff`],
+//! * reading archives without generated code by [`DynamicArchive`],
+//! * data structures for writing data:
+//!   [`StructBuf`], [`Vector`], [`ExternalVector`], [`MultiVector`],
+//! * data structures for reading data: [`ArrayView`], [`MultiArrayView`],
+//! * parallel iteration over [`ArrayView`] and [`MultiArrayView`] with
+//!   [rayon] (requires the `rayon` feature).
 //!
 //! The generator is part of the main [heremaps/flatdata] repository.
 //!
@@ -29,6 +49,7 @@
 //! corresponding [usage].
 //!
 //! [heremaps/flatdata]: https://github.com/heremaps/flatdata
+//! [rayon]: https://docs.rs/rayon
 //! [generated code]: https://github.com/boxdot/flatdata-rs/blob/master/tests/coappearances/generated.rs
 //! [usage]: https://github.com/boxdot/flatdata-rs/blob/master/tests/coappearances_test.rs
 //! [Why flatdata?]: https://github.com/heremaps/flatdata/blob/master/docs/src/why-flatdata.rst
@@ -36,18 +57,41 @@
 //! [`write_bytes`]: macro.write_bytes.html
 //! [`MemoryResourceStorage`]: struct.MemoryResourceStorage.html
 //! [`FileResourceStorage`]: struct.FileResourceStorage.html
+//! [`BundleResourceStorage`]: struct.BundleResourceStorage.html
+//! [`TarResourceStorage`]: struct.TarResourceStorage.html
+//! [`BufferResourceStorage`]: struct.BufferResourceStorage.html
+//! [`OverlayResourceStorage`]: struct.OverlayResourceStorage.html
+//! [`CompressedResourceStorage`]: struct.CompressedResourceStorage.html
+//! [`StreamingResourceStorage`]: struct.StreamingResourceStorage.html
 //! [`StructBuf`]: struct.StructBuf.html
 //! [`Vector`]: struct.Vector.html
 //! [`ExternalVector`]: struct.ExternalVector.html
 //! [`MultiVector`]: struct.MultiVector.html
 //! [`ArrayView`]: struct.ArrayView.html
 //! [`MultiArrayView`]: struct.MultiArrayView.html
+//! [`Advice`]: enum.Advice.html
+//! [`LoadMode`]: enum.LoadMode.html
+//! [`ResidencyReport`]: struct.ResidencyReport.html
+//! [`WarmUp`]: struct.WarmUp.html
+//! [`copy_archive`]: fn.copy_archive.html
+//! [`Archive::open_compatible`]: trait.Archive.html#method.open_compatible
+//! [`schema::parse`]: schema/fn.parse.html
+//! [`schema::diff`]: schema/fn.diff.html
+//! [`DynamicArchive`]: struct.DynamicArchive.html
 
 #![deny(missing_docs, missing_debug_implementations, warnings)]
 // #![allow(intra_doc_link_resolution_failure)]
 
 extern crate diff;
+#[cfg(unix)]
+extern crate libc;
+#[cfg(feature = "lz4")]
+extern crate lz4_flex;
 extern crate memmap;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "zstd")]
+extern crate zstd;
 
 /// Number of elements in `ArrayView`, `MultiArrayView`, and `Vector` to show
 /// in Debug output.
@@ -55,6 +99,7 @@ const DEBUG_PREVIEW_LEN: usize = 10;
 
 #[macro_use]
 pub mod helper;
+pub mod schema;
 #[macro_use]
 mod bytereader;
 #[macro_use]
@@ -62,26 +107,62 @@ mod bytewriter;
 #[macro_use]
 mod archive;
 mod arrayview;
+mod bufferstorage;
+mod bundlestorage;
+mod canonical;
+mod checksum;
+mod compatibility;
+mod compressedstorage;
+mod copy;
+mod dynamic;
 mod error;
 mod filestorage;
 mod memory;
 mod memstorage;
 mod multiarrayview;
 mod multivector;
+mod overlaystorage;
+mod paging;
+#[cfg(feature = "rayon")]
+mod parallel;
 mod storage;
+mod streamingstorage;
 mod structbuf;
+mod tarstorage;
 mod vector;
+mod warmup;
 
 pub use crate::archive::*;
 pub use crate::arrayview::ArrayView;
+pub use crate::bufferstorage::BufferResourceStorage;
+pub use crate::bundlestorage::{
+    convert_bundle_to_directory, convert_directory_to_bundle, BundleResourceStorage,
+};
+pub use crate::checksum::verify_archive;
+pub use crate::compatibility::SchemaAddition;
+pub use crate::compressedstorage::{CompressedResourceStorage, Compression};
+pub use crate::copy::{copy_archive, copy_archive_verified};
+pub use crate::dynamic::{
+    DynamicArchive, DynamicArrayView, DynamicMultiArrayView, DynamicMultiArrayViewItemIter,
+    DynamicResource, DynamicStruct, FieldLayout, StructLayout, Value,
+};
 pub use crate::error::*;
-pub use crate::filestorage::FileResourceStorage;
+pub use crate::filestorage::{FileResourceStorage, LoadMode};
 pub use crate::memory::PADDING_SIZE;
 pub use crate::memstorage::MemoryResourceStorage;
 pub use crate::multiarrayview::MultiArrayView;
 pub use crate::multivector::MultiVector;
+pub use crate::overlaystorage::OverlayResourceStorage;
+pub use crate::paging::Advice;
+#[cfg(target_os = "linux")]
+pub use crate::paging::{Residency, ResidencyReport};
+#[cfg(feature = "rayon")]
+pub use crate::parallel::{ArrayViewParIter, MultiArrayViewParIter};
 pub use crate::storage::{
     create_archive, create_external_vector, create_multi_vector, MemoryDescriptor, ResourceStorage,
 };
+pub use crate::streamingstorage::StreamingResourceStorage;
 pub use crate::structbuf::StructBuf;
+pub use crate::tarstorage::TarResourceStorage;
 pub use crate::vector::*;
+pub use crate::warmup::{WarmUp, WarmUpProgress};
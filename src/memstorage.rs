@@ -1,22 +1,22 @@
-use crate::storage::{ResourceStorage, Stream};
+use crate::memory::SizeType;
+use crate::storage::{list_paths, seek_and_write_size, ResourceStorage, Stream};
 
-use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::fmt;
 use std::io::{self, Cursor};
-use std::path::PathBuf;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
 use std::slice;
+use std::sync::{Arc, Mutex};
 
-type MemoryStorageStream = Rc<RefCell<Cursor<Vec<u8>>>>;
+type MemoryStorageStream = Arc<Mutex<Cursor<Vec<u8>>>>;
 
 /// Internal storage of data in memory.
 #[derive(Default)]
 struct MemoryStorage {
     // Streams of resources that were written.
-    streams: RefCell<BTreeMap<PathBuf, MemoryStorageStream>>,
+    streams: Mutex<BTreeMap<PathBuf, MemoryStorageStream>>,
     // Data of resources that were opened for reading.
-    resources: RefCell<BTreeMap<PathBuf, Rc<Vec<u8>>>>,
+    resources: Mutex<BTreeMap<PathBuf, Arc<Vec<u8>>>>,
 }
 
 impl fmt::Debug for MemoryStorage {
@@ -24,16 +24,51 @@ impl fmt::Debug for MemoryStorage {
         write!(
             f,
             "MemoryStorage {{ num_streams: {}, num_resources: {} }}",
-            self.streams.borrow().len(),
-            self.resources.borrow().len(),
+            self.streams.lock().unwrap().len(),
+            self.resources.lock().unwrap().len(),
         )
     }
 }
 
 /// Resource storage in memory.
+///
+/// Subdirectories share the memory of the storage they were created from:
+/// resources written to a subdirectory are visible through the parent
+/// storage, e.g. as `sub/resource`, and through every other handle of the
+/// same subdirectory.
+///
+/// All written resources can be extracted with [`snapshot`], e.g. to keep
+/// them as test fixture, and a storage can be rebuilt from a snapshot with
+/// [`from_snapshot`]. To dump the storage to a directory or a bundle, copy it
+/// with [`copy_archive`] to a [`FileResourceStorage`] or a
+/// [`BundleResourceStorage`].
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{MemoryResourceStorage, ResourceStorage};
+///
+/// let storage = MemoryResourceStorage::new("/root/fixture");
+/// storage.subdir("sub").write("resource", "schema", &[42]).unwrap();
+///
+/// let snapshot = storage.snapshot();
+/// assert!(snapshot.contains_key("sub/resource.schema"));
+///
+/// let restored = MemoryResourceStorage::from_snapshot("/root/restored", snapshot);
+/// assert_eq!(restored.subdir("sub").read("resource", "schema").unwrap(), &[42]);
+/// # }
+/// ```
+///
+/// [`snapshot`]: #method.snapshot
+/// [`from_snapshot`]: #method.from_snapshot
+/// [`copy_archive`]: fn.copy_archive.html
+/// [`FileResourceStorage`]: struct.FileResourceStorage.html
+/// [`BundleResourceStorage`]: struct.BundleResourceStorage.html
 #[derive(Debug)]
 pub struct MemoryResourceStorage {
-    storage: MemoryStorage,
+    storage: Arc<MemoryStorage>,
     path: PathBuf,
 }
 
@@ -43,41 +78,125 @@ impl MemoryResourceStorage {
     /// Resources will be placed in ephemeral memory with prefix `path`. A path
     /// has to be provided to unify the interface with `FileResourceStorage`.
     #[allow(clippy::new_ret_no_self)]
-    pub fn new<P: Into<PathBuf>>(path: P) -> Rc<Self> {
-        Rc::new(Self {
-            storage: MemoryStorage::default(),
+    pub fn new<P: Into<PathBuf>>(path: P) -> Arc<Self> {
+        Arc::new(Self {
+            storage: Arc::new(MemoryStorage::default()),
             path: path.into(),
         })
     }
+
+    /// Create a memory resource storage at a given virtual path containing
+    /// the resources of a snapshot.
+    ///
+    /// Cf. [`snapshot`](#method.snapshot).
+    pub fn from_snapshot<P: Into<PathBuf>>(
+        path: P,
+        snapshot: BTreeMap<String, Vec<u8>>,
+    ) -> Arc<Self> {
+        let path = path.into();
+        let streams = snapshot
+            .into_iter()
+            .map(|(name, data)| {
+                let mut stream = Cursor::new(data);
+                // further writes append to the resource as to any written resource
+                stream.set_position(stream.get_ref().len() as u64);
+                (path.join(name), Arc::new(Mutex::new(stream)))
+            })
+            .collect();
+        Arc::new(Self {
+            storage: Arc::new(MemoryStorage {
+                streams: Mutex::new(streams),
+                resources: Mutex::new(BTreeMap::new()),
+            }),
+            path,
+        })
+    }
+
+    /// Returns the data of all resources written to this storage and its
+    /// subdirectories.
+    ///
+    /// Resources are identified by their path relative to the storage, e.g.
+    /// `statistics/vertex_degrees`. Besides flatdata resources, the snapshot
+    /// contains schemas, checksums and archive signatures, i.e. all data
+    /// needed to open an archive from it again.
+    pub fn snapshot(&self) -> BTreeMap<String, Vec<u8>> {
+        let streams = self.storage.streams.lock().unwrap();
+        streams
+            .iter()
+            .filter_map(|(path, stream)| {
+                Some((
+                    self.relative_name(path)?,
+                    stream.lock().unwrap().get_ref().clone(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Returns the name of a resource relative to the storage, e.g.
+    /// `sub/resource`, or `None` if it is not in the storage.
+    fn relative_name(&self, path: &Path) -> Option<String> {
+        let name: Vec<_> = path
+            .strip_prefix(&self.path)
+            .ok()?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        Some(name.join("/"))
+    }
+
+    fn list(&self, subdirs: bool) -> Vec<String> {
+        let streams = self.storage.streams.lock().unwrap();
+        let resources = self.storage.resources.lock().unwrap();
+        let names: Vec<String> = streams
+            .keys()
+            .chain(resources.keys())
+            .filter_map(|path| self.relative_name(path))
+            .collect();
+        list_paths(&names, "", subdirs)
+    }
 }
 
-impl Stream for Cursor<Vec<u8>> {}
+impl Stream for Cursor<Vec<u8>> {
+    fn write_size_header(&mut self, size: SizeType) -> io::Result<()> {
+        seek_and_write_size(self, size)
+    }
+}
 
 impl ResourceStorage for MemoryResourceStorage {
-    fn subdir(&self, dir: &str) -> Rc<ResourceStorage> {
-        Self::new(self.path.join(dir))
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            storage: self.storage.clone(),
+            path: self.path.join(dir),
+        })
     }
 
     fn exists(&self, resource_name: &str) -> bool {
         let resource_path = self.path.join(resource_name);
-        self.storage.resources.borrow().contains_key(&resource_path)
-            || self.storage.streams.borrow().contains_key(&resource_path)
+        self.storage
+            .resources
+            .lock()
+            .unwrap()
+            .contains_key(&resource_path)
+            || self
+                .storage
+                .streams
+                .lock()
+                .unwrap()
+                .contains_key(&resource_path)
     }
 
     fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
         let resource_path = self.path.join(resource_name);
-        if !self.storage.resources.borrow().contains_key(&resource_path) {
-            let streams = self.storage.streams.borrow();
+        let mut resources = self.storage.resources.lock().unwrap();
+        if !resources.contains_key(&resource_path) {
+            let streams = self.storage.streams.lock().unwrap();
             let stream = streams.get(&resource_path);
             match stream {
                 Some(stream) => {
                     // Resource is not yet opened, but there is a stream it was written to
                     // => copy the stream as resource data.
-                    let data = Rc::new(stream.borrow().get_ref().clone());
-                    self.storage
-                        .resources
-                        .borrow_mut()
-                        .insert(resource_path.clone(), data);
+                    let data = Arc::new(stream.lock().unwrap().get_ref().clone());
+                    resources.insert(resource_path.clone(), data);
                 }
                 None => {
                     return Err(io::Error::new(
@@ -87,22 +206,97 @@ impl ResourceStorage for MemoryResourceStorage {
                 }
             }
         }
-        let data = &self.storage.resources.borrow()[&resource_path];
+        let data = &resources[&resource_path];
         // We cannot prove to Rust that the buffer will live as long as the storage
         // (we never delete mappings), so we need to manually extend lifetime
         let extended_lifetime_data = unsafe { slice::from_raw_parts(data.as_ptr(), data.len()) };
         Ok(&extended_lifetime_data)
     }
 
-    fn create_output_stream(&self, resource_name: &str) -> Result<Rc<RefCell<Stream>>, io::Error> {
+    fn create_output_stream(
+        &self,
+        resource_name: &str,
+    ) -> Result<Arc<Mutex<dyn Stream>>, io::Error> {
         let resource_path = self.path.join(resource_name);
         let stream = self
             .storage
             .streams
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .entry(resource_path)
-            .or_insert_with(|| Rc::new(RefCell::new(Cursor::new(Vec::new()))))
+            .or_insert_with(|| Arc::new(Mutex::new(Cursor::new(Vec::new()))))
             .clone();
         Ok(stream)
     }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        Ok(self.list(false))
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        Ok(self.list(true))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn share_memory_between_subdirs() {
+        let storage = MemoryResourceStorage::new("/root/shared");
+        let sub = storage.subdir("sub");
+        sub.write("a", "schema", &[1]).unwrap();
+
+        assert!(storage.exists("sub/a"));
+        assert_eq!(storage.list_subdirs().unwrap(), vec!["sub"]);
+        let other = storage.subdir("sub");
+        assert_eq!(other.read("a", "schema").unwrap(), &[1]);
+        assert_eq!(
+            other.list_resources().unwrap(),
+            vec!["a", "a.checksum", "a.schema"]
+        );
+
+        other.subdir("nested").write("b", "schema", &[2]).unwrap();
+        assert_eq!(
+            storage.subdir("sub/nested").read("b", "schema").unwrap(),
+            &[2]
+        );
+        assert_eq!(sub.list_subdirs().unwrap(), vec!["nested"]);
+        assert!(!storage.exists("a"));
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let storage = MemoryResourceStorage::new("/root/snapshot");
+        storage.write("a", "schema", &[1, 2]).unwrap();
+        storage
+            .subdir("sub/nested")
+            .write("b", "schema", &[3])
+            .unwrap();
+        MemoryResourceStorage::new("/root/other")
+            .write("c", "schema", &[4])
+            .unwrap();
+
+        let snapshot = storage.snapshot();
+        assert_eq!(
+            snapshot.keys().collect::<Vec<_>>(),
+            vec![
+                "a",
+                "a.checksum",
+                "a.schema",
+                "sub/nested/b",
+                "sub/nested/b.checksum",
+                "sub/nested/b.schema"
+            ]
+        );
+
+        let restored = MemoryResourceStorage::from_snapshot("/root/restored", snapshot.clone());
+        assert_eq!(restored.read("a", "schema").unwrap(), &[1, 2]);
+        assert_eq!(
+            restored.subdir("sub/nested").read("b", "schema").unwrap(),
+            &[3]
+        );
+        assert_eq!(restored.snapshot(), snapshot);
+    }
 }
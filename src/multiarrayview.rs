@@ -140,7 +140,7 @@ where
     }
 }
 
-fn debug_format<'a, Idx, Ts>(
+pub(crate) fn debug_format<'a, Idx, Ts>(
     name: &str,
     iter: MultiArrayViewIter<'a, Idx, Ts>,
     f: &mut fmt::Formatter,
@@ -421,6 +421,18 @@ mod tests {
         test_fused_iterator(view.at(66), 2);
     }
 
+    #[test]
+    fn send_sync() {
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+        let storage = MemoryResourceStorage::new("/root/resources");
+        let view = create_view(&storage, 10);
+        assert_send_sync(&view);
+        assert_send_sync(&view.iter());
+        assert_send_sync(&view.at(0));
+        assert_send_sync(&view.at(0).next().unwrap());
+    }
+
     #[test]
     fn into_iter() {
         let storage = MemoryResourceStorage::new("/root/resources");
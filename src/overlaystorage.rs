@@ -0,0 +1,205 @@
+use crate::paging::Advice;
+#[cfg(target_os = "linux")]
+use crate::paging::Residency;
+use crate::storage::{ResourceStorage, Stream};
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Resource storage stacking several resource storages on top of each other.
+///
+/// Reading a resource resolves it in the top-most layer containing it. All
+/// writes go to the upper layer, which is also the top-most layer for
+/// reading. This allows to replace single resources of a large archive
+/// without copying the remaining resources, e.g. by writing a corrected
+/// vector to an upper `MemoryResourceStorage` stacked on top of a
+/// `FileResourceStorage` with the original archive.
+///
+/// Note that a resource and its schema `{resource_name}.schema` are resolved
+/// independently, therefore, a replacing layer has to contain both. The same
+/// holds for a multivector and its index `{resource_name}_index`.
+///
+/// Subdirectories are layered in the same way: a subdirectory of an overlay
+/// storage is the overlay of the corresponding subdirectories of all layers.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{MemoryResourceStorage, OverlayResourceStorage, ResourceStorage};
+///
+/// let lower = MemoryResourceStorage::new("/root/lower");
+/// lower.write("a", "schema", &[1]).unwrap();
+/// lower.write("b", "schema", &[2]).unwrap();
+///
+/// let upper = MemoryResourceStorage::new("/root/upper");
+/// let storage = OverlayResourceStorage::new(upper.clone(), vec![lower]);
+/// storage.write("b", "schema", &[3]).unwrap();
+///
+/// assert_eq!(storage.read("a", "schema").unwrap(), &[1]);
+/// assert_eq!(storage.read("b", "schema").unwrap(), &[3]);
+/// assert_eq!(upper.read("b", "schema").unwrap(), &[3]);
+/// # }
+/// ```
+pub struct OverlayResourceStorage {
+    // Layers ordered from top-most to bottom-most, the first one is the upper
+    // layer.
+    layers: Vec<Arc<dyn ResourceStorage>>,
+}
+
+impl OverlayResourceStorage {
+    /// Creates an overlay of an upper layer, which receives all writes, on
+    /// top of lower layers.
+    ///
+    /// Lower layers are ordered from top-most to bottom-most.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(upper: Arc<dyn ResourceStorage>, lower: Vec<Arc<dyn ResourceStorage>>) -> Arc<Self> {
+        let mut layers = vec![upper];
+        layers.extend(lower);
+        Arc::new(Self { layers })
+    }
+
+    /// Union of the lists of all layers.
+    fn list<F>(&self, list_layer: F) -> io::Result<Vec<String>>
+    where
+        F: Fn(&dyn ResourceStorage) -> io::Result<Vec<String>>,
+    {
+        let mut names = BTreeSet::new();
+        for layer in &self.layers {
+            names.extend(list_layer(&**layer)?);
+        }
+        Ok(names.into_iter().collect())
+    }
+
+    fn layer_of(&self, resource_name: &str) -> Option<&dyn ResourceStorage> {
+        self.layers
+            .iter()
+            .find(|layer| layer.exists(resource_name))
+            .map(|layer| &**layer)
+    }
+}
+
+impl fmt::Debug for OverlayResourceStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "OverlayResourceStorage {{ num_layers: {} }}",
+            self.layers.len()
+        )
+    }
+}
+
+impl ResourceStorage for OverlayResourceStorage {
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            layers: self.layers.iter().map(|layer| layer.subdir(dir)).collect(),
+        })
+    }
+
+    fn exists(&self, resource_name: &str) -> bool {
+        self.layer_of(resource_name).is_some()
+    }
+
+    fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
+        match self.layer_of(resource_name) {
+            Some(layer) => layer.read_resource(resource_name),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                String::from(resource_name),
+            )),
+        }
+    }
+
+    fn create_output_stream(
+        &self,
+        resource_name: &str,
+    ) -> Result<Arc<Mutex<dyn Stream>>, io::Error> {
+        self.layers[0].create_output_stream(resource_name)
+    }
+
+    fn commit(&self) -> io::Result<()> {
+        self.layers[0].commit()
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        self.list(|layer| layer.list_resources())
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        self.list(|layer| layer.list_subdirs())
+    }
+
+    fn advise(&self, resource_name: &str, advice: Advice) -> io::Result<()> {
+        match self.layer_of(resource_name) {
+            Some(layer) => layer.advise(resource_name, advice),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                String::from(resource_name),
+            )),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn residency(&self, resource_name: &str) -> io::Result<Option<Residency>> {
+        match self.layer_of(resource_name) {
+            Some(layer) => layer.residency(resource_name),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                String::from(resource_name),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filestorage::FileResourceStorage;
+    use crate::memstorage::MemoryResourceStorage;
+    use std::env;
+    use std::fs;
+
+    #[test]
+    fn resolve_from_top_most_layer() {
+        let bottom = MemoryResourceStorage::new("/root/bottom");
+        bottom.write("a", "schema", &[1]).unwrap();
+        bottom.write("b", "schema", &[1]).unwrap();
+        bottom.write("c", "schema", &[1]).unwrap();
+        let middle = MemoryResourceStorage::new("/root/middle");
+        middle.write("b", "schema", &[2]).unwrap();
+        middle.write("c", "schema", &[2]).unwrap();
+        let upper = MemoryResourceStorage::new("/root/upper");
+        upper.write("c", "schema", &[3]).unwrap();
+
+        let storage = OverlayResourceStorage::new(upper, vec![middle, bottom]);
+        assert!(storage.exists("a"));
+        assert!(!storage.exists("d"));
+        assert_eq!(storage.read("a", "schema").unwrap(), &[1]);
+        assert_eq!(storage.read("b", "schema").unwrap(), &[2]);
+        assert_eq!(storage.read("c", "schema").unwrap(), &[3]);
+        assert_eq!(
+            storage.read_resource("d").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn write_to_upper_layer() {
+        let dir = env::temp_dir().join("flatdata_overlay_test/write_to_upper_layer");
+        let _ = fs::remove_dir_all(&dir);
+        let lower = FileResourceStorage::new(dir.join("lower"));
+        lower.subdir("sub").write("a", "schema", &[1]).unwrap();
+        let upper = FileResourceStorage::new(dir.join("upper"));
+
+        let storage = OverlayResourceStorage::new(upper.clone(), vec![lower.clone()]);
+        let sub = storage.subdir("sub");
+        assert_eq!(sub.read("a", "schema").unwrap(), &[1]);
+        sub.write("b", "schema", &[2]).unwrap();
+        assert_eq!(sub.read("b", "schema").unwrap(), &[2]);
+        assert!(upper.subdir("sub").exists("b"));
+        assert!(!lower.subdir("sub").exists("b"));
+    }
+}
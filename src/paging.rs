@@ -0,0 +1,186 @@
+//! Helpers for controlling the paging of memory mapped resources.
+
+#[cfg(target_os = "linux")]
+use std::collections::BTreeMap;
+use std::io;
+
+/// Expected access pattern of a memory mapped resource, cf. `madvise(2)`.
+///
+/// Hints are passed to the operating system, which uses them to adapt
+/// readahead and caching of the pages of a resource. On platforms without
+/// `madvise`, hints are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// No special treatment (default).
+    Normal,
+    /// Pages are accessed sequentially, e.g. when iterating over a vector;
+    /// enables aggressive readahead.
+    Sequential,
+    /// Pages are accessed in random order, e.g. by lookups; disables
+    /// readahead.
+    Random,
+    /// Pages will be accessed soon; starts reading them in the background.
+    WillNeed,
+    /// Pages will not be accessed soon; allows to evict them from memory.
+    DontNeed,
+}
+
+/// Size of a memory page.
+#[cfg(unix)]
+pub(crate) fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Extends memory to the surrounding page boundaries as needed by
+/// `madvise`, `mlock` and `mincore`.
+#[cfg(unix)]
+fn page_range(data: &[u8]) -> (*mut libc::c_void, usize) {
+    let page_size = page_size();
+    let start = data.as_ptr() as usize;
+    let aligned_start = start - start % page_size;
+    let len = data.len() + (start - aligned_start);
+    (aligned_start as *mut libc::c_void, len)
+}
+
+/// Passes a hint for memory mapped data to the operating system.
+///
+/// Must only be called on memory mapped files, since e.g. `DontNeed` discards
+/// the contents of anonymous memory.
+#[cfg(unix)]
+pub(crate) fn advise(data: &[u8], advice: Advice) -> io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let flag = match advice {
+        Advice::Normal => libc::MADV_NORMAL,
+        Advice::Sequential => libc::MADV_SEQUENTIAL,
+        Advice::Random => libc::MADV_RANDOM,
+        Advice::WillNeed => libc::MADV_WILLNEED,
+        Advice::DontNeed => libc::MADV_DONTNEED,
+    };
+    let (addr, len) = page_range(data);
+    if unsafe { libc::madvise(addr, len, flag) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn advise(_data: &[u8], _advice: Advice) -> io::Result<()> {
+    Ok(())
+}
+
+/// Reads all pages of data into memory.
+pub(crate) fn populate(data: &[u8]) {
+    advise(data, Advice::WillNeed).ok();
+    #[cfg(unix)]
+    let step = page_size();
+    #[cfg(not(unix))]
+    let step = 4096;
+    let mut checksum = 0u8;
+    for offset in (0..data.len()).step_by(step) {
+        checksum ^= unsafe { std::ptr::read_volatile(data.as_ptr().add(offset)) };
+    }
+    std::hint::black_box(checksum);
+}
+
+/// Reads all pages of data into memory and locks them, cf. `mlock(2)`.
+///
+/// Pages are unlocked when data is unmapped.
+#[cfg(unix)]
+pub(crate) fn lock(data: &[u8]) -> io::Result<()> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    let (addr, len) = page_range(data);
+    if unsafe { libc::mlock(addr, len) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn lock(_data: &[u8]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "locking memory is not supported on this platform",
+    ))
+}
+
+/// Number of pages of a memory mapped resource which are resident in memory,
+/// cf. `mincore(2)`.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Residency {
+    /// Number of pages resident in memory.
+    pub resident_pages: usize,
+    /// Total number of pages of the resource.
+    pub total_pages: usize,
+}
+
+/// Page-cache residency of the memory mapped resources of an archive
+/// including its subarchives.
+///
+/// Resources are identified by their path relative to the archive, e.g.
+/// `statistics/vertex_degrees`. Resources which are not memory mapped, e.g.
+/// since they are stored in memory or compressed, are not reported.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResidencyReport {
+    /// Residency of each memory mapped resource.
+    pub resources: BTreeMap<String, Residency>,
+}
+
+#[cfg(target_os = "linux")]
+impl ResidencyReport {
+    /// Sum of the residency of all resources.
+    pub fn total(&self) -> Residency {
+        self.resources
+            .values()
+            .fold(Residency::default(), |total, residency| Residency {
+                resident_pages: total.resident_pages + residency.resident_pages,
+                total_pages: total.total_pages + residency.total_pages,
+            })
+    }
+}
+
+/// Determines which pages of memory mapped data are resident in memory.
+#[cfg(target_os = "linux")]
+pub(crate) fn residency(data: &[u8]) -> io::Result<Residency> {
+    if data.is_empty() {
+        return Ok(Residency::default());
+    }
+    let (addr, len) = page_range(data);
+    let page_size = page_size();
+    let mut pages = vec![0u8; len.div_ceil(page_size)];
+    if unsafe { libc::mincore(addr, len, pages.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Residency {
+        resident_pages: pages.iter().filter(|&&page| page & 1 != 0).count(),
+        total_pages: pages.len(),
+    })
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn page_range_is_aligned() {
+        let data = vec![0u8; 3 * page_size()];
+        let (addr, len) = page_range(&data[10..20]);
+        assert_eq!(addr as usize % page_size(), 0);
+        assert!(addr as usize + len >= data[10..20].as_ptr() as usize + 10);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn residency_of_heap_memory() {
+        let data = vec![1u8; 4 * page_size()];
+        let residency = residency(&data).unwrap();
+        assert!(residency.total_pages >= 4);
+        assert!(residency.resident_pages >= 4);
+        assert_eq!(super::residency(&[]).unwrap(), Residency::default());
+    }
+}
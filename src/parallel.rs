@@ -0,0 +1,303 @@
+//! Parallel iterators over `ArrayView` and `MultiArrayView` based on [rayon].
+//!
+//! Available only with the `rayon` feature enabled. A view is split into
+//! subviews by using its `slice` method, therefore no data is copied.
+//!
+//! [rayon]: https://docs.rs/rayon
+
+use crate::archive::{IndexStruct, Struct, VariadicStruct};
+use crate::arrayview::{self, ArrayView, ArrayViewIter};
+use crate::multiarrayview::{self, MultiArrayView, MultiArrayViewItemIter, MultiArrayViewIter};
+
+use rayon::iter::plumbing::{bridge, Consumer, Producer, ProducerCallback, UnindexedConsumer};
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+
+use std::fmt;
+
+//
+// ArrayView
+//
+
+impl<'a, T> ArrayView<'a, T>
+where
+    T: for<'b> Struct<'b> + Send + Sync,
+{
+    /// Returns a parallel iterator to the elements of the array.
+    ///
+    /// Requires the `rayon` feature.
+    pub fn par_iter(&self) -> ArrayViewParIter<'a, T> {
+        ArrayViewParIter { view: self.clone() }
+    }
+}
+
+impl<'a, T> IntoParallelIterator for ArrayView<'a, T>
+where
+    T: for<'b> Struct<'b> + Send + Sync,
+{
+    type Item = <T as Struct<'a>>::Item;
+    type Iter = ArrayViewParIter<'a, T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+impl<'a, T> Producer for ArrayView<'a, T>
+where
+    T: for<'b> Struct<'b> + Send + Sync,
+{
+    type Item = <T as Struct<'a>>::Item;
+    type IntoIter = ArrayViewIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        (self.slice(..index), self.slice(index..))
+    }
+}
+
+/// Parallel iterator through elements of `ArrayView`.
+#[derive(Clone)]
+pub struct ArrayViewParIter<'a, T>
+where
+    T: for<'b> Struct<'b>,
+{
+    view: ArrayView<'a, T>,
+}
+
+impl<'a, T> ParallelIterator for ArrayViewParIter<'a, T>
+where
+    T: for<'b> Struct<'b> + Send + Sync,
+{
+    type Item = <T as Struct<'a>>::Item;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.view.len())
+    }
+}
+
+impl<'a, T> IndexedParallelIterator for ArrayViewParIter<'a, T>
+where
+    T: for<'b> Struct<'b> + Send + Sync,
+{
+    fn len(&self) -> usize {
+        self.view.len()
+    }
+
+    fn drive<C>(self, consumer: C) -> C::Result
+    where
+        C: Consumer<Self::Item>,
+    {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: ProducerCallback<Self::Item>,
+    {
+        callback.callback(self.view)
+    }
+}
+
+impl<'a, T> fmt::Debug for ArrayViewParIter<'a, T>
+where
+    T: for<'b> Struct<'b>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        arrayview::debug_format("ArrayViewParIter", self.view.iter(), f)
+    }
+}
+
+//
+// MultiArrayView
+//
+
+impl<'a, Idx, Ts: 'a> MultiArrayView<'a, Idx, Ts>
+where
+    Idx: for<'b> IndexStruct<'b> + Send + Sync,
+    Ts: for<'b> VariadicStruct<'b> + Send + Sync,
+{
+    /// Returns a parallel iterator through the indexed items of the array.
+    ///
+    /// Requires the `rayon` feature.
+    pub fn par_iter(&self) -> MultiArrayViewParIter<'a, Idx, Ts> {
+        MultiArrayViewParIter { view: self.clone() }
+    }
+}
+
+impl<'a, Idx, Ts: 'a> IntoParallelIterator for MultiArrayView<'a, Idx, Ts>
+where
+    Idx: for<'b> IndexStruct<'b> + Send + Sync,
+    Ts: for<'b> VariadicStruct<'b> + Send + Sync,
+{
+    type Item = MultiArrayViewItemIter<'a, Ts>;
+    type Iter = MultiArrayViewParIter<'a, Idx, Ts>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+impl<'a, Idx, Ts: 'a> Producer for MultiArrayView<'a, Idx, Ts>
+where
+    Idx: for<'b> IndexStruct<'b> + Send + Sync,
+    Ts: for<'b> VariadicStruct<'b> + Send + Sync,
+{
+    type Item = MultiArrayViewItemIter<'a, Ts>;
+    type IntoIter = MultiArrayViewIter<'a, Idx, Ts>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+
+    fn split_at(self, index: usize) -> (Self, Self) {
+        (self.slice(..index), self.slice(index..))
+    }
+}
+
+/// Parallel iterator through items of a multivector.
+#[derive(Clone)]
+pub struct MultiArrayViewParIter<'a, Idx, Ts>
+where
+    Idx: for<'b> IndexStruct<'b>,
+    Ts: for<'b> VariadicStruct<'b>,
+{
+    view: MultiArrayView<'a, Idx, Ts>,
+}
+
+impl<'a, Idx, Ts: 'a> ParallelIterator for MultiArrayViewParIter<'a, Idx, Ts>
+where
+    Idx: for<'b> IndexStruct<'b> + Send + Sync,
+    Ts: for<'b> VariadicStruct<'b> + Send + Sync,
+{
+    type Item = MultiArrayViewItemIter<'a, Ts>;
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        bridge(self, consumer)
+    }
+
+    fn opt_len(&self) -> Option<usize> {
+        Some(self.view.len())
+    }
+}
+
+impl<'a, Idx, Ts: 'a> IndexedParallelIterator for MultiArrayViewParIter<'a, Idx, Ts>
+where
+    Idx: for<'b> IndexStruct<'b> + Send + Sync,
+    Ts: for<'b> VariadicStruct<'b> + Send + Sync,
+{
+    fn len(&self) -> usize {
+        self.view.len()
+    }
+
+    fn drive<C>(self, consumer: C) -> C::Result
+    where
+        C: Consumer<Self::Item>,
+    {
+        bridge(self, consumer)
+    }
+
+    fn with_producer<CB>(self, callback: CB) -> CB::Output
+    where
+        CB: ProducerCallback<Self::Item>,
+    {
+        callback.callback(self.view)
+    }
+}
+
+impl<'a, Idx, Ts> fmt::Debug for MultiArrayViewParIter<'a, Idx, Ts>
+where
+    Idx: for<'b> IndexStruct<'b>,
+    Ts: for<'b> VariadicStruct<'b>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        multiarrayview::debug_format("MultiArrayViewParIter", self.view.iter(), f)
+    }
+}
+
+#[cfg(test)]
+#[allow(dead_code)]
+mod tests {
+    use super::*;
+    use crate::memstorage::MemoryResourceStorage;
+    use crate::storage::create_multi_vector;
+    use crate::vector::Vector;
+
+    define_index!(Idx, RefIdx, RefMutIdx, "some_idx_schema", 4, 32);
+
+    define_struct!(
+        Value,
+        RefValue,
+        RefMutValue,
+        "no_schema",
+        4,
+        (value, set_value, u32, 0, 32)
+    );
+
+    define_variadic_struct!(Variant, RefVariant, BuilderVariant, Idx, 0 => (Value, add_value));
+
+    fn create_values(size: usize) -> Vector<Value> {
+        let mut v: Vector<Value> = Vector::with_len(size);
+        for i in 0..size {
+            v.at_mut(i).set_value(i as u32);
+        }
+        v
+    }
+
+    #[test]
+    fn array_view_par_iter() {
+        let v = create_values(10000);
+        let view = v.as_view();
+
+        assert_eq!(view.par_iter().len(), 10000);
+        let sum: u64 = view.par_iter().map(|x| u64::from(x.value())).sum();
+        assert_eq!(sum, (0..10000).sum());
+
+        let values: Vec<_> = view.slice(10..20).par_iter().map(|x| x.value()).collect();
+        assert_eq!(values, (10..20).collect::<Vec<_>>());
+
+        let values: Vec<_> = view.into_par_iter().rev().map(|x| x.value()).collect();
+        assert_eq!(values, (0..10000).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn multi_array_view_par_iter() {
+        let storage = MemoryResourceStorage::new("/root/resources");
+        let view: MultiArrayView<Idx, Variant> = {
+            let mut mv = create_multi_vector::<Idx, Variant>(&*storage, "multivector", "schema")
+                .expect("failed to create MultiVector");
+            for i in 0..1000 {
+                let mut item = mv.grow().expect("grow failed");
+                for _ in 0..i % 3 {
+                    item.add_value().set_value(i);
+                }
+            }
+            mv.close().expect("close failed")
+        };
+
+        assert_eq!(view.par_iter().len(), 1000);
+        let values: Vec<Vec<u32>> = view
+            .par_iter()
+            .map(|item| {
+                item.map(|x| match x {
+                    RefVariant::Value(v) => v.value(),
+                })
+                .collect()
+            })
+            .collect();
+        let expected: Vec<Vec<u32>> = (0..1000).map(|i| vec![i; i as usize % 3]).collect();
+        assert_eq!(values, expected);
+    }
+}
@@ -0,0 +1,1510 @@
+//! Parser of the flatdata schema language.
+//!
+//! Every generated archive embeds its schema, and every resource is stored
+//! together with its schema in `{resource_name}.schema`. This module parses
+//! such schemas into a typed syntax tree, which allows tools to inspect
+//! archives without generated code.
+//!
+//! # Examples
+//!
+//! ```
+//! # extern crate flatdata;
+//! # fn main() {
+//! use flatdata::schema::{self, Declaration, ResourceType};
+//!
+//! let schema = schema::parse(
+//!     r#"namespace n {
+//!     struct Point { x : u32 : 20; y : u32 : 20; }
+//!     archive Map { @optional points : vector< .n.Point >; }
+//!     }"#,
+//! )
+//! .unwrap();
+//!
+//! match schema.find(".n.Map") {
+//!     Some(Declaration::Archive(archive)) => {
+//!         let points = &archive.resources[0];
+//!         assert_eq!(points.name, "points");
+//!         assert!(points.is_optional());
+//!         assert_eq!(points.ty, ResourceType::Vector(".n.Point".into()));
+//!     }
+//!     otherwise => panic!("unexpected declaration: {:?}", otherwise),
+//! }
+//!
+//! let err = schema::parse("namespace n { struct S { x : u32 : 40; } }").unwrap_err();
+//! assert_eq!((err.position.line, err.position.column), (1, 36));
+//! # }
+//! ```
+
+use crate::canonical;
+
+use std::error;
+use std::fmt;
+
+/// Position in a schema, 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    /// Line number.
+    pub line: usize,
+    /// Column number, counted in characters.
+    pub column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// Error returned by [`parse`] for an invalid schema.
+///
+/// [`parse`]: fn.parse.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Position of the error.
+    pub position: Position,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.position, self.message)
+    }
+}
+
+impl error::Error for ParseError {}
+
+/// Parsed schema, i.e. a sequence of namespace blocks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    /// Namespace blocks in order of appearance.
+    ///
+    /// The same namespace can occur several times; generated schemas put each
+    /// declaration in its own block.
+    pub namespaces: Vec<Namespace>,
+}
+
+impl Schema {
+    /// Returns the declaration with the given qualified name, e.g.
+    /// `.coappearances.Graph` or `coappearances.Graph`.
+    pub fn find(&self, name: &str) -> Option<&Declaration> {
+        let name = name.strip_prefix('.').unwrap_or(name);
+        self.namespaces.iter().find_map(|namespace| {
+            let declaration_name = name.strip_prefix(namespace.name.as_str())?;
+            let declaration_name = declaration_name.strip_prefix('.')?;
+            namespace
+                .declarations
+                .iter()
+                .find(|declaration| declaration.name() == declaration_name)
+        })
+    }
+
+    /// Returns all declarations together with their qualified names, e.g.
+    /// `.coappearances.Graph`, in order of appearance.
+    pub fn declarations(&self) -> impl Iterator<Item = (String, &Declaration)> {
+        self.namespaces.iter().flat_map(|namespace| {
+            namespace.declarations.iter().map(move |declaration| {
+                (
+                    format!(".{}.{}", namespace.name, declaration.name()),
+                    declaration,
+                )
+            })
+        })
+    }
+}
+
+/// Namespace block, e.g. `namespace coappearances { ... }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    /// Dot-separated name of the namespace.
+    pub name: String,
+    /// Declarations in order of appearance.
+    pub declarations: Vec<Declaration>,
+    /// Position of the `namespace` keyword.
+    pub position: Position,
+}
+
+/// Declaration in a namespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Declaration {
+    /// Constant, e.g. `const u32 MAX = 10;`.
+    Const(Const),
+    /// Enumeration, e.g. `enum Kind : u8 { A, B = 3 }`.
+    Enum(Enum),
+    /// Struct with bit-packed fields.
+    Struct(Struct),
+    /// Archive of resources.
+    Archive(Archive),
+}
+
+impl Declaration {
+    /// Returns the unqualified name of the declaration.
+    pub fn name(&self) -> &str {
+        match *self {
+            Declaration::Const(ref x) => &x.name,
+            Declaration::Enum(ref x) => &x.name,
+            Declaration::Struct(ref x) => &x.name,
+            Declaration::Archive(ref x) => &x.name,
+        }
+    }
+
+    /// Returns the position of the declaration.
+    pub fn position(&self) -> Position {
+        match *self {
+            Declaration::Const(ref x) => x.position,
+            Declaration::Enum(ref x) => x.position,
+            Declaration::Struct(ref x) => x.position,
+            Declaration::Archive(ref x) => x.position,
+        }
+    }
+}
+
+/// Annotation of a declaration, field or resource, e.g. `@optional` or
+/// `@explicit_reference( .n.S.x, .n.A.y )`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Annotation {
+    /// Name without the leading `@`.
+    pub name: String,
+    /// Comma-separated arguments; the tokens of each argument are joined by
+    /// single spaces, e.g. `characters : .n.A.x`.
+    pub arguments: Vec<String>,
+    /// Position of the `@`.
+    pub position: Position,
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "@{}", self.name)?;
+        if !self.arguments.is_empty() {
+            write!(f, "( {} )", self.arguments.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Basic integer type of fields, enums and constants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BasicType {
+    /// `bool`
+    Bool,
+    /// `i8`
+    I8,
+    /// `u8`
+    U8,
+    /// `i16`
+    I16,
+    /// `u16`
+    U16,
+    /// `i32`
+    I32,
+    /// `u32`
+    U32,
+    /// `i64`
+    I64,
+    /// `u64`
+    U64,
+}
+
+impl BasicType {
+    /// Returns the type with the given name, e.g. `u32`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "bool" => BasicType::Bool,
+            "i8" => BasicType::I8,
+            "u8" => BasicType::U8,
+            "i16" => BasicType::I16,
+            "u16" => BasicType::U16,
+            "i32" => BasicType::I32,
+            "u32" => BasicType::U32,
+            "i64" => BasicType::I64,
+            "u64" => BasicType::U64,
+            _ => return None,
+        })
+    }
+
+    /// Returns the name of the type, e.g. `u32`.
+    pub fn name(self) -> &'static str {
+        match self {
+            BasicType::Bool => "bool",
+            BasicType::I8 => "i8",
+            BasicType::U8 => "u8",
+            BasicType::I16 => "i16",
+            BasicType::U16 => "u16",
+            BasicType::I32 => "i32",
+            BasicType::U32 => "u32",
+            BasicType::I64 => "i64",
+            BasicType::U64 => "u64",
+        }
+    }
+
+    /// Returns the size of the type in bits.
+    pub fn bits(self) -> u32 {
+        match self {
+            BasicType::Bool => 1,
+            BasicType::I8 | BasicType::U8 => 8,
+            BasicType::I16 | BasicType::U16 => 16,
+            BasicType::I32 | BasicType::U32 => 32,
+            BasicType::I64 | BasicType::U64 => 64,
+        }
+    }
+
+    /// Returns whether the type is signed.
+    pub fn is_signed(self) -> bool {
+        match self {
+            BasicType::I8 | BasicType::I16 | BasicType::I32 | BasicType::I64 => true,
+            BasicType::Bool | BasicType::U8 | BasicType::U16 | BasicType::U32 | BasicType::U64 => {
+                false
+            }
+        }
+    }
+}
+
+impl fmt::Display for BasicType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Constant declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Const {
+    /// Name of the constant.
+    pub name: String,
+    /// Type of the constant.
+    pub ty: BasicType,
+    /// Value of the constant.
+    pub value: i128,
+    /// Annotations of the constant.
+    pub annotations: Vec<Annotation>,
+    /// Position of the `const` keyword.
+    pub position: Position,
+}
+
+/// Enum declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enum {
+    /// Name of the enum.
+    pub name: String,
+    /// Underlying type of the enum.
+    pub ty: BasicType,
+    /// Values in order of appearance.
+    pub values: Vec<EnumValue>,
+    /// Annotations of the enum.
+    pub annotations: Vec<Annotation>,
+    /// Position of the `enum` keyword.
+    pub position: Position,
+}
+
+/// Value of an enum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnumValue {
+    /// Name of the value.
+    pub name: String,
+    /// Explicitly assigned value; otherwise, the value is the previous value
+    /// plus one, or zero for the first value.
+    pub value: Option<i128>,
+    /// Position of the name.
+    pub position: Position,
+}
+
+/// Struct declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Struct {
+    /// Name of the struct.
+    pub name: String,
+    /// Fields in order of their layout.
+    pub fields: Vec<Field>,
+    /// Annotations of the struct.
+    pub annotations: Vec<Annotation>,
+    /// Position of the `struct` keyword.
+    pub position: Position,
+}
+
+/// Type of a struct field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldType {
+    /// Basic integer type.
+    Basic(BasicType),
+    /// Enum with the given name, e.g. `.n.Kind`.
+    Enum(String),
+}
+
+impl fmt::Display for FieldType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FieldType::Basic(ty) => write!(f, "{}", ty),
+            FieldType::Enum(ref name) => f.write_str(name),
+        }
+    }
+}
+
+/// Field of a struct, e.g. `x : u32 : 20;`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Name of the field.
+    pub name: String,
+    /// Type of the field.
+    pub ty: FieldType,
+    /// Size of the field in bits; if not given, the size of its type.
+    pub width: Option<u32>,
+    /// Annotations of the field, e.g. `@range(edges)`.
+    pub annotations: Vec<Annotation>,
+    /// Position of the name.
+    pub position: Position,
+}
+
+/// Archive declaration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Archive {
+    /// Name of the archive.
+    pub name: String,
+    /// Resources in order of appearance.
+    pub resources: Vec<Resource>,
+    /// Annotations of the archive, e.g. `@bound_implicitly(...)`.
+    pub annotations: Vec<Annotation>,
+    /// Position of the `archive` keyword.
+    pub position: Position,
+}
+
+/// Type of an archive resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceType {
+    /// Single instance of the struct with the given name.
+    Instance(String),
+    /// Vector of the struct with the given name.
+    Vector(String),
+    /// Multivector of the structs with the given names.
+    Multivector {
+        /// Size of the index in bits.
+        index_width: u32,
+        /// Names of the variadic types in order of their type index.
+        types: Vec<String>,
+    },
+    /// Raw bytes.
+    RawData,
+    /// Subarchive with the given name.
+    Archive(String),
+}
+
+impl fmt::Display for ResourceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResourceType::Instance(ref name) => f.write_str(name),
+            ResourceType::Vector(ref name) => write!(f, "vector< {} >", name),
+            ResourceType::Multivector {
+                index_width,
+                ref types,
+            } => write!(f, "multivector< {}, {} >", index_width, types.join(", ")),
+            ResourceType::RawData => f.write_str("raw_data"),
+            ResourceType::Archive(ref name) => write!(f, "archive {}", name),
+        }
+    }
+}
+
+/// Resource of an archive, e.g. `vertices : vector< .n.Vertex >;`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    /// Name of the resource.
+    pub name: String,
+    /// Type of the resource.
+    pub ty: ResourceType,
+    /// Annotations of the resource, e.g. `@optional`.
+    pub annotations: Vec<Annotation>,
+    /// Position of the name.
+    pub position: Position,
+}
+
+impl Resource {
+    /// Returns whether the resource is annotated with `@optional`.
+    pub fn is_optional(&self) -> bool {
+        self.annotations
+            .iter()
+            .any(|annotation| annotation.name == "optional")
+    }
+}
+
+/// Parses a schema.
+///
+/// Type names are kept as written, e.g. `.coappearances.Character`; use
+/// [`Schema::find`] to resolve fully qualified names.
+///
+/// # Errors
+///
+/// Returns the position of the first syntax error, or of the first field
+/// whose width exceeds the size of its type.
+///
+/// [`Schema::find`]: struct.Schema.html#method.find
+pub fn parse(schema: &str) -> Result<Schema, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(schema)?,
+        pos: 0,
+        end: end_position(schema),
+    };
+    let mut namespaces = Vec::new();
+    while parser.peek().is_some() {
+        namespaces.push(parser.namespace()?);
+    }
+    Ok(Schema { namespaces })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    position: Position,
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+fn error<T>(position: Position, message: String) -> Result<T, ParseError> {
+    Err(ParseError { position, message })
+}
+
+fn end_position(schema: &str) -> Position {
+    let line = schema.split('\n').count();
+    let column = schema.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    Position { line, column }
+}
+
+/// Advances `rest` and `position` by `len` bytes.
+fn advance(rest: &mut &str, position: &mut Position, len: usize) {
+    for c in rest[..len].chars() {
+        if c == '\n' {
+            position.line += 1;
+            position.column = 1;
+        } else {
+            position.column += 1;
+        }
+    }
+    *rest = &rest[len..];
+}
+
+fn tokenize(schema: &str) -> Result<Vec<Token<'_>>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut position = Position { line: 1, column: 1 };
+    let mut rest = schema;
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_whitespace() {
+            c.len_utf8()
+        } else if rest.starts_with("//") {
+            rest.find('\n').unwrap_or(rest.len())
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            match comment.find("*/") {
+                Some(end) => end + 4,
+                None => return error(position, "unterminated comment".into()),
+            }
+        } else {
+            let len = if is_word(c) || c == '-' {
+                let start = c.len_utf8();
+                start
+                    + rest[start..]
+                        .find(|c| !is_word(c))
+                        .unwrap_or(rest.len() - start)
+            } else if "{}()<>:;,=@".contains(c) {
+                1
+            } else {
+                return error(position, format!("unexpected character `{}`", c));
+            };
+            tokens.push(Token {
+                text: &rest[..len],
+                position,
+            });
+            len
+        };
+        advance(&mut rest, &mut position, len);
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    end: Position,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|token| token.text)
+    }
+
+    fn position(&self) -> Position {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |token| token.position)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token<'a>, ParseError> {
+        match self.tokens.get(self.pos) {
+            Some(&token) => {
+                self.pos += 1;
+                Ok(token)
+            }
+            None => error(
+                self.end,
+                format!("expected {}, found end of schema", expected),
+            ),
+        }
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, ParseError> {
+        match self.peek() {
+            Some(found) => error(
+                self.position(),
+                format!("expected {}, found `{}`", expected, found),
+            ),
+            None => error(
+                self.end,
+                format!("expected {}, found end of schema", expected),
+            ),
+        }
+    }
+
+    fn accept(&mut self, text: &str) -> bool {
+        if self.peek() == Some(text) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Position, ParseError> {
+        let position = self.position();
+        if self.accept(text) {
+            Ok(position)
+        } else {
+            self.unexpected(&format!("`{}`", text))
+        }
+    }
+
+    /// Parses a name, which is qualified by dots if `qualified` is set.
+    fn name(&mut self, qualified: bool) -> Result<(String, Position), ParseError> {
+        let expected = if qualified { "qualified name" } else { "name" };
+        let is_valid = |text: &str| {
+            let text = if qualified {
+                text.strip_prefix('.').unwrap_or(text)
+            } else {
+                text
+            };
+            let is_identifier = |part: &str| {
+                part.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                    && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+            };
+            if qualified {
+                text.split('.').all(is_identifier)
+            } else {
+                is_identifier(text)
+            }
+        };
+        match self.peek() {
+            Some(text) if is_valid(text) => {
+                let token = self.next(expected)?;
+                Ok((token.text.into(), token.position))
+            }
+            _ => self.unexpected(expected),
+        }
+    }
+
+    fn number(&mut self) -> Result<i128, ParseError> {
+        let token = match self.peek() {
+            Some(text) if text.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                self.next("number")?
+            }
+            _ => return self.unexpected("number"),
+        };
+        let (negative, digits) = match token.text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token.text),
+        };
+        let value = match digits.strip_prefix("0x") {
+            Some(hex) => i128::from_str_radix(hex, 16),
+            None => digits.parse::<i128>(),
+        };
+        match value {
+            Ok(value) if negative => Ok(-value),
+            Ok(value) => Ok(value),
+            Err(_) => error(token.position, format!("invalid number `{}`", token.text)),
+        }
+    }
+
+    fn width(&mut self) -> Result<(u32, Position), ParseError> {
+        let position = self.position();
+        match self.number()? {
+            width if width > 0 && width <= 64 => Ok((width as u32, position)),
+            width => error(position, format!("invalid width {}", width)),
+        }
+    }
+
+    fn basic_type(&mut self) -> Result<BasicType, ParseError> {
+        match self.peek().and_then(BasicType::from_name) {
+            Some(ty) => {
+                self.pos += 1;
+                Ok(ty)
+            }
+            None => self.unexpected("basic type"),
+        }
+    }
+
+    fn annotations(&mut self) -> Result<Vec<Annotation>, ParseError> {
+        let mut annotations = Vec::new();
+        while self.peek() == Some("@") {
+            let position = self.expect("@")?;
+            let (name, _) = self.name(false)?;
+            let mut arguments = Vec::new();
+            if self.accept("(") {
+                let mut argument: Vec<&str> = Vec::new();
+                loop {
+                    let token = self.next("`)`")?;
+                    match token.text {
+                        ")" | "," => {
+                            if argument.is_empty() {
+                                return error(token.position, "expected argument".into());
+                            }
+                            arguments.push(argument.join(" "));
+                            argument.clear();
+                            if token.text == ")" {
+                                break;
+                            }
+                        }
+                        "(" | "{" | "}" | ";" | "@" => {
+                            return error(
+                                token.position,
+                                format!("unexpected `{}` in annotation", token.text),
+                            );
+                        }
+                        text => argument.push(text),
+                    }
+                }
+            }
+            annotations.push(Annotation {
+                name,
+                arguments,
+                position,
+            });
+        }
+        Ok(annotations)
+    }
+
+    fn namespace(&mut self) -> Result<Namespace, ParseError> {
+        let position = self.expect("namespace")?;
+        let (name, _) = self.name(true)?;
+        self.expect("{")?;
+        let mut declarations = Vec::new();
+        while !self.accept("}") {
+            declarations.push(self.declaration()?);
+        }
+        Ok(Namespace {
+            name,
+            declarations,
+            position,
+        })
+    }
+
+    fn declaration(&mut self) -> Result<Declaration, ParseError> {
+        let annotations = self.annotations()?;
+        let position = self.position();
+        let declaration = match self.peek() {
+            Some("const") => Declaration::Const(self.constant(annotations, position)?),
+            Some("enum") => Declaration::Enum(self.enumeration(annotations, position)?),
+            Some("struct") => Declaration::Struct(self.structure(annotations, position)?),
+            Some("archive") => Declaration::Archive(self.archive(annotations, position)?),
+            _ => return self.unexpected("declaration"),
+        };
+        if !matches!(declaration, Declaration::Const(_)) {
+            self.accept(";");
+        }
+        Ok(declaration)
+    }
+
+    fn constant(
+        &mut self,
+        annotations: Vec<Annotation>,
+        position: Position,
+    ) -> Result<Const, ParseError> {
+        self.expect("const")?;
+        let ty = self.basic_type()?;
+        let (name, _) = self.name(false)?;
+        self.expect("=")?;
+        let value = self.number()?;
+        self.expect(";")?;
+        Ok(Const {
+            name,
+            ty,
+            value,
+            annotations,
+            position,
+        })
+    }
+
+    fn enumeration(
+        &mut self,
+        annotations: Vec<Annotation>,
+        position: Position,
+    ) -> Result<Enum, ParseError> {
+        self.expect("enum")?;
+        let (name, _) = self.name(false)?;
+        self.expect(":")?;
+        let ty = self.basic_type()?;
+        self.expect("{")?;
+        let mut values = Vec::new();
+        while !self.accept("}") {
+            let (name, position) = self.name(false)?;
+            let value = if self.accept("=") {
+                Some(self.number()?)
+            } else {
+                None
+            };
+            values.push(EnumValue {
+                name,
+                value,
+                position,
+            });
+            if !self.accept(",") && self.peek() != Some("}") {
+                return self.unexpected("`,` or `}`");
+            }
+        }
+        Ok(Enum {
+            name,
+            ty,
+            values,
+            annotations,
+            position,
+        })
+    }
+
+    fn structure(
+        &mut self,
+        annotations: Vec<Annotation>,
+        position: Position,
+    ) -> Result<Struct, ParseError> {
+        self.expect("struct")?;
+        let (name, _) = self.name(false)?;
+        self.expect("{")?;
+        let mut fields = Vec::new();
+        while !self.accept("}") {
+            let annotations = self.annotations()?;
+            let (name, position) = self.name(false)?;
+            self.expect(":")?;
+            let ty = match self.peek().and_then(BasicType::from_name) {
+                Some(_) => FieldType::Basic(self.basic_type()?),
+                None => FieldType::Enum(self.name(true)?.0),
+            };
+            let width = if self.accept(":") {
+                let (width, width_position) = self.width()?;
+                if let FieldType::Basic(ty) = ty {
+                    if width > ty.bits() {
+                        return error(
+                            width_position,
+                            format!(
+                                "width {} of field {} exceeds the size of {}",
+                                width,
+                                name,
+                                ty.name()
+                            ),
+                        );
+                    }
+                }
+                Some(width)
+            } else {
+                None
+            };
+            self.expect(";")?;
+            fields.push(Field {
+                name,
+                ty,
+                width,
+                annotations,
+                position,
+            });
+        }
+        Ok(Struct {
+            name,
+            fields,
+            annotations,
+            position,
+        })
+    }
+
+    fn archive(
+        &mut self,
+        annotations: Vec<Annotation>,
+        position: Position,
+    ) -> Result<Archive, ParseError> {
+        self.expect("archive")?;
+        let (name, _) = self.name(false)?;
+        self.expect("{")?;
+        let mut resources = Vec::new();
+        while !self.accept("}") {
+            let annotations = self.annotations()?;
+            let (name, position) = self.name(false)?;
+            self.expect(":")?;
+            let ty = self.resource_type()?;
+            self.expect(";")?;
+            resources.push(Resource {
+                name,
+                ty,
+                annotations,
+                position,
+            });
+        }
+        Ok(Archive {
+            name,
+            resources,
+            annotations,
+            position,
+        })
+    }
+
+    fn resource_type(&mut self) -> Result<ResourceType, ParseError> {
+        if self.accept("vector") {
+            self.expect("<")?;
+            let (ty, _) = self.name(true)?;
+            self.expect(">")?;
+            Ok(ResourceType::Vector(ty))
+        } else if self.accept("multivector") {
+            self.expect("<")?;
+            let (index_width, _) = self.width()?;
+            let mut types = Vec::new();
+            while self.accept(",") {
+                types.push(self.name(true)?.0);
+            }
+            if types.is_empty() {
+                return self.unexpected("`,`");
+            }
+            self.expect(">")?;
+            Ok(ResourceType::Multivector { index_width, types })
+        } else if self.accept("raw_data") {
+            Ok(ResourceType::RawData)
+        } else if self.accept("archive") {
+            Ok(ResourceType::Archive(self.name(true)?.0))
+        } else {
+            Ok(ResourceType::Instance(self.name(true)?.0))
+        }
+    }
+}
+
+//
+// Semantic diff
+//
+
+/// Semantic difference between a stored and an expected schema.
+///
+/// Names are fully qualified, e.g. `.coappearances.Character.name_ref` for a
+/// field or `.coappearances.Graph.vertices` for a resource.
+///
+/// Cf. [`diff`].
+///
+/// [`diff`]: fn.diff.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaChange {
+    /// Declaration only in the expected schema.
+    DeclarationAdded(String),
+    /// Declaration only in the stored schema.
+    DeclarationRemoved(String),
+    /// Declaration of another kind, e.g. a struct replaced by an enum.
+    DeclarationKindChanged(String),
+    /// Type of a constant, enum, field or resource changed.
+    TypeChanged {
+        /// Name of the changed element.
+        name: String,
+        /// Type in the stored schema, as written in the schema language.
+        stored: String,
+        /// Type in the expected schema, as written in the schema language.
+        expected: String,
+    },
+    /// Value of a constant or an enum value changed.
+    ValueChanged {
+        /// Name of the constant or enum value.
+        name: String,
+        /// Value in the stored schema.
+        stored: i128,
+        /// Value in the expected schema.
+        expected: i128,
+    },
+    /// Annotations of a declaration, field or resource changed.
+    AnnotationsChanged {
+        /// Name of the annotated element.
+        name: String,
+        /// Annotations in the stored schema, e.g. `@optional`.
+        stored: Vec<String>,
+        /// Annotations in the expected schema.
+        expected: Vec<String>,
+    },
+    /// Struct field only in the expected schema.
+    FieldAdded(String),
+    /// Struct field only in the stored schema.
+    FieldRemoved(String),
+    /// Size of a struct field in bits changed.
+    FieldWidthChanged {
+        /// Name of the field.
+        name: String,
+        /// Width in the stored schema.
+        stored: u32,
+        /// Width in the expected schema.
+        expected: u32,
+    },
+    /// Fields present in both schemas are laid out in another order in the
+    /// struct with the given name.
+    FieldsReordered(String),
+    /// Enum value only in the expected schema.
+    EnumValueAdded(String),
+    /// Enum value only in the stored schema.
+    EnumValueRemoved(String),
+    /// Archive resource only in the expected schema.
+    ResourceAdded(String),
+    /// Archive resource only in the stored schema.
+    ResourceRemoved(String),
+}
+
+impl fmt::Display for SchemaChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SchemaChange::DeclarationAdded(ref name) => write!(f, "{} added", name),
+            SchemaChange::DeclarationRemoved(ref name) => write!(f, "{} removed", name),
+            SchemaChange::DeclarationKindChanged(ref name) => {
+                write!(f, "{}: kind of declaration changed", name)
+            }
+            SchemaChange::TypeChanged {
+                ref name,
+                ref stored,
+                ref expected,
+            } => write!(f, "{}: type {} -> {}", name, stored, expected),
+            SchemaChange::ValueChanged {
+                ref name,
+                stored,
+                expected,
+            } => write!(f, "{}: value {} -> {}", name, stored, expected),
+            SchemaChange::AnnotationsChanged {
+                ref name,
+                ref stored,
+                ref expected,
+            } => write!(
+                f,
+                "{}: annotations [{}] -> [{}]",
+                name,
+                stored.join(" "),
+                expected.join(" ")
+            ),
+            SchemaChange::FieldAdded(ref name) => write!(f, "field {} added", name),
+            SchemaChange::FieldRemoved(ref name) => write!(f, "field {} removed", name),
+            SchemaChange::FieldWidthChanged {
+                ref name,
+                stored,
+                expected,
+            } => write!(f, "field {}: width {} -> {}", name, stored, expected),
+            SchemaChange::FieldsReordered(ref name) => {
+                write!(f, "struct {}: fields reordered", name)
+            }
+            SchemaChange::EnumValueAdded(ref name) => write!(f, "enum value {} added", name),
+            SchemaChange::EnumValueRemoved(ref name) => write!(f, "enum value {} removed", name),
+            SchemaChange::ResourceAdded(ref name) => write!(f, "resource {} added", name),
+            SchemaChange::ResourceRemoved(ref name) => write!(f, "resource {} removed", name),
+        }
+    }
+}
+
+/// Computes the semantic differences from a stored to an expected schema.
+///
+/// Declarations are matched by their qualified names, and fields, enum
+/// values and resources by their names; formatting, comments and the order
+/// of declarations are ignored. Removed declarations are listed after all
+/// other changes.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::schema::{self, SchemaChange};
+///
+/// let stored = schema::parse("namespace n { struct S { x : u32 : 16; } }").unwrap();
+/// let expected = schema::parse("namespace n { struct S { x : u32 : 32; } }").unwrap();
+/// let changes = schema::diff(&stored, &expected);
+/// assert_eq!(
+///     changes,
+///     vec![SchemaChange::FieldWidthChanged {
+///         name: ".n.S.x".into(),
+///         stored: 16,
+///         expected: 32,
+///     }]
+/// );
+/// assert_eq!(changes[0].to_string(), "field .n.S.x: width 16 -> 32");
+/// # }
+/// ```
+pub fn diff(stored: &Schema, expected: &Schema) -> Vec<SchemaChange> {
+    let mut changes = Vec::new();
+    let stored_declarations: Vec<_> = stored.declarations().collect();
+    let find_stored = |name: &str| {
+        stored_declarations
+            .iter()
+            .find(|(stored_name, _)| stored_name == name)
+            .map(|(_, declaration)| *declaration)
+    };
+    let mut seen: Vec<String> = Vec::new();
+    for (name, declaration) in expected.declarations() {
+        if seen.contains(&name) {
+            continue;
+        }
+        match find_stored(&name) {
+            None => changes.push(SchemaChange::DeclarationAdded(name.clone())),
+            Some(stored_declaration) => diff_declarations(
+                &name,
+                (stored, stored_declaration),
+                (expected, declaration),
+                &mut changes,
+            ),
+        }
+        seen.push(name);
+    }
+    for (name, _) in &stored_declarations {
+        if !seen.contains(name) {
+            changes.push(SchemaChange::DeclarationRemoved(name.clone()));
+            seen.push(name.clone());
+        }
+    }
+    changes
+}
+
+/// Computes the semantic differences between two stored schema texts.
+///
+/// Returns no changes if one of the schemas cannot be parsed.
+pub(crate) fn diff_texts(stored: &str, expected: &str) -> Vec<SchemaChange> {
+    let strip_index = |schema| canonical::strip_index(schema).unwrap_or(schema);
+    match (parse(strip_index(stored)), parse(strip_index(expected))) {
+        (Ok(stored), Ok(expected)) => diff(&stored, &expected),
+        _ => Vec::new(),
+    }
+}
+
+fn diff_declarations(
+    name: &str,
+    (stored_schema, stored): (&Schema, &Declaration),
+    (expected_schema, expected): (&Schema, &Declaration),
+    changes: &mut Vec<SchemaChange>,
+) {
+    match (stored, expected) {
+        (Declaration::Const(stored), Declaration::Const(expected)) => {
+            diff_types(name, stored.ty, expected.ty, changes);
+            if stored.value != expected.value {
+                changes.push(SchemaChange::ValueChanged {
+                    name: name.into(),
+                    stored: stored.value,
+                    expected: expected.value,
+                });
+            }
+            diff_annotations(name, &stored.annotations, &expected.annotations, changes);
+        }
+        (Declaration::Enum(stored), Declaration::Enum(expected)) => {
+            diff_types(name, stored.ty, expected.ty, changes);
+            diff_annotations(name, &stored.annotations, &expected.annotations, changes);
+            let stored_values = enum_values(stored);
+            let expected_values = enum_values(expected);
+            for &(value_name, value) in &expected_values {
+                let qualified = format!("{}.{}", name, value_name);
+                match stored_values.iter().find(|(x, _)| *x == value_name) {
+                    None => changes.push(SchemaChange::EnumValueAdded(qualified)),
+                    Some(&(_, stored_value)) if stored_value != value => {
+                        changes.push(SchemaChange::ValueChanged {
+                            name: qualified,
+                            stored: stored_value,
+                            expected: value,
+                        })
+                    }
+                    Some(_) => (),
+                }
+            }
+            for &(value_name, _) in &stored_values {
+                if !expected_values.iter().any(|(x, _)| *x == value_name) {
+                    changes.push(SchemaChange::EnumValueRemoved(format!(
+                        "{}.{}",
+                        name, value_name
+                    )));
+                }
+            }
+        }
+        (Declaration::Struct(stored), Declaration::Struct(expected)) => {
+            diff_annotations(name, &stored.annotations, &expected.annotations, changes);
+            for field in &expected.fields {
+                let qualified = format!("{}.{}", name, field.name);
+                let stored_field = match stored.fields.iter().find(|x| x.name == field.name) {
+                    Some(stored_field) => stored_field,
+                    None => {
+                        changes.push(SchemaChange::FieldAdded(qualified));
+                        continue;
+                    }
+                };
+                if stored_field.ty != field.ty {
+                    changes.push(SchemaChange::TypeChanged {
+                        name: qualified.clone(),
+                        stored: stored_field.ty.to_string(),
+                        expected: field.ty.to_string(),
+                    });
+                }
+                match (
+                    field_width(stored_schema, stored_field),
+                    field_width(expected_schema, field),
+                ) {
+                    (Some(stored_width), Some(width)) if stored_width != width => {
+                        changes.push(SchemaChange::FieldWidthChanged {
+                            name: qualified.clone(),
+                            stored: stored_width,
+                            expected: width,
+                        })
+                    }
+                    _ => (),
+                }
+                diff_annotations(
+                    &qualified,
+                    &stored_field.annotations,
+                    &field.annotations,
+                    changes,
+                );
+            }
+            for field in &stored.fields {
+                if !expected.fields.iter().any(|x| x.name == field.name) {
+                    changes.push(SchemaChange::FieldRemoved(format!(
+                        "{}.{}",
+                        name, field.name
+                    )));
+                }
+            }
+            let common_order = |fields: &[Field], others: &[Field]| -> Vec<String> {
+                fields
+                    .iter()
+                    .filter(|field| others.iter().any(|x| x.name == field.name))
+                    .map(|field| field.name.clone())
+                    .collect()
+            };
+            if common_order(&stored.fields, &expected.fields)
+                != common_order(&expected.fields, &stored.fields)
+            {
+                changes.push(SchemaChange::FieldsReordered(name.into()));
+            }
+        }
+        (Declaration::Archive(stored), Declaration::Archive(expected)) => {
+            diff_annotations(name, &stored.annotations, &expected.annotations, changes);
+            for resource in &expected.resources {
+                let qualified = format!("{}.{}", name, resource.name);
+                let stored_resource =
+                    match stored.resources.iter().find(|x| x.name == resource.name) {
+                        Some(stored_resource) => stored_resource,
+                        None => {
+                            changes.push(SchemaChange::ResourceAdded(qualified));
+                            continue;
+                        }
+                    };
+                if stored_resource.ty != resource.ty {
+                    changes.push(SchemaChange::TypeChanged {
+                        name: qualified.clone(),
+                        stored: stored_resource.ty.to_string(),
+                        expected: resource.ty.to_string(),
+                    });
+                }
+                diff_annotations(
+                    &qualified,
+                    &stored_resource.annotations,
+                    &resource.annotations,
+                    changes,
+                );
+            }
+            for resource in &stored.resources {
+                if !expected.resources.iter().any(|x| x.name == resource.name) {
+                    changes.push(SchemaChange::ResourceRemoved(format!(
+                        "{}.{}",
+                        name, resource.name
+                    )));
+                }
+            }
+        }
+        _ => changes.push(SchemaChange::DeclarationKindChanged(name.into())),
+    }
+}
+
+fn diff_types(name: &str, stored: BasicType, expected: BasicType, changes: &mut Vec<SchemaChange>) {
+    if stored != expected {
+        changes.push(SchemaChange::TypeChanged {
+            name: name.into(),
+            stored: stored.to_string(),
+            expected: expected.to_string(),
+        });
+    }
+}
+
+fn diff_annotations(
+    name: &str,
+    stored: &[Annotation],
+    expected: &[Annotation],
+    changes: &mut Vec<SchemaChange>,
+) {
+    let render = |annotations: &[Annotation]| -> Vec<String> {
+        annotations.iter().map(ToString::to_string).collect()
+    };
+    let (stored, expected) = (render(stored), render(expected));
+    if stored != expected {
+        changes.push(SchemaChange::AnnotationsChanged {
+            name: name.into(),
+            stored,
+            expected,
+        });
+    }
+}
+
+/// Returns the names of the values of an enum together with their effective
+/// values.
+fn enum_values(enumeration: &Enum) -> Vec<(&str, i128)> {
+    let mut next = 0;
+    enumeration
+        .values
+        .iter()
+        .map(|value| {
+            let current = value.value.unwrap_or(next);
+            next = current + 1;
+            (value.name.as_str(), current)
+        })
+        .collect()
+}
+
+/// Returns the effective width of a field, or `None` if it is of an unknown
+/// enum type.
+pub(crate) fn field_width(schema: &Schema, field: &Field) -> Option<u32> {
+    if let Some(width) = field.width {
+        return Some(width);
+    }
+    match field.ty {
+        FieldType::Basic(ty) => Some(ty.bits()),
+        FieldType::Enum(ref name) => match schema.find(name) {
+            Some(Declaration::Enum(enumeration)) => Some(enumeration.ty.bits()),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GRAPH: &str =
+        include_str!("../tests/coappearances/karenina.archive/Graph.archive.schema");
+
+    fn position(line: usize, column: usize) -> Position {
+        Position { line, column }
+    }
+
+    #[test]
+    fn parse_generated_schema() {
+        let schema = parse(GRAPH).unwrap();
+        assert_eq!(schema.namespaces.len(), 12);
+        assert!(schema
+            .namespaces
+            .iter()
+            .all(|namespace| namespace.name == "coappearances"));
+
+        let chapter = match schema.find(".coappearances.Chapter") {
+            Some(Declaration::Struct(chapter)) => chapter,
+            otherwise => panic!("unexpected declaration: {:?}", otherwise),
+        };
+        assert_eq!(chapter.fields.len(), 2);
+        assert_eq!(chapter.fields[1].name, "minor");
+        assert_eq!(chapter.fields[1].ty, FieldType::Basic(BasicType::U8));
+        assert_eq!(chapter.fields[1].width, Some(7));
+
+        let graph = match schema.find("coappearances.Graph") {
+            Some(Declaration::Archive(graph)) => graph,
+            otherwise => panic!("unexpected declaration: {:?}", otherwise),
+        };
+        assert_eq!(graph.annotations[0].name, "bound_implicitly");
+        assert_eq!(
+            graph.annotations[0].arguments,
+            vec![
+                "characters : .coappearances.Graph.vertices",
+                ".coappearances.Graph.vertices_data"
+            ]
+        );
+        let types: Vec<_> = graph.resources.iter().map(|r| r.ty.clone()).collect();
+        assert_eq!(
+            types,
+            vec![
+                ResourceType::Instance(".coappearances.Meta".into()),
+                ResourceType::Vector(".coappearances.Character".into()),
+                ResourceType::Vector(".coappearances.Coappearance".into()),
+                ResourceType::Multivector {
+                    index_width: 32,
+                    types: vec![
+                        ".coappearances.Nickname".into(),
+                        ".coappearances.Description".into(),
+                        ".coappearances.UnaryRelation".into(),
+                        ".coappearances.BinaryRelation".into(),
+                    ]
+                },
+                ResourceType::Vector(".coappearances.Chapter".into()),
+                ResourceType::RawData,
+                ResourceType::Archive(".coappearances.Statistics".into()),
+            ]
+        );
+        let statistics = graph.resources.last().unwrap();
+        assert!(statistics.is_optional());
+        assert_eq!(statistics.position, position(116, 5));
+        assert_eq!(schema.declarations().count(), 12);
+    }
+
+    #[test]
+    fn parse_constants_and_enums() {
+        let schema = parse(
+            "// constants\n\
+             namespace a.b {\n\
+             /** maximum */ const i16 MIN = -0x10;\n\
+             enum Kind : u8 { A, B = 5, C, }\n\
+             struct S { @range(r) kind : .a.b.Kind : 3; flag : bool; }\n\
+             }",
+        )
+        .unwrap();
+        let namespace = &schema.namespaces[0];
+        assert_eq!(namespace.name, "a.b");
+        match namespace.declarations[0] {
+            Declaration::Const(ref c) => {
+                assert_eq!((c.ty, c.value), (BasicType::I16, -16));
+                assert_eq!(c.position, position(3, 16));
+            }
+            ref otherwise => panic!("unexpected declaration: {:?}", otherwise),
+        }
+        match schema.find(".a.b.Kind") {
+            Some(Declaration::Enum(e)) => {
+                let values: Vec<_> = e.values.iter().map(|v| (&v.name[..], v.value)).collect();
+                assert_eq!(values, vec![("A", None), ("B", Some(5)), ("C", None)]);
+            }
+            otherwise => panic!("unexpected declaration: {:?}", otherwise),
+        }
+        match schema.find(".a.b.S") {
+            Some(Declaration::Struct(s)) => {
+                assert_eq!(s.fields[0].ty, FieldType::Enum(".a.b.Kind".into()));
+                assert_eq!(s.fields[0].annotations[0].arguments, vec!["r"]);
+                assert_eq!(s.fields[1].width, None);
+            }
+            otherwise => panic!("unexpected declaration: {:?}", otherwise),
+        }
+    }
+
+    #[test]
+    fn report_error_positions() {
+        let check = |schema: &str, line, column, message: &str| {
+            let err = parse(schema).unwrap_err();
+            assert_eq!(err.position, position(line, column), "{}", err);
+            assert_eq!(err.message, message);
+        };
+        check(
+            "namespace n {\n  struct S { x : u32 }\n}",
+            2,
+            22,
+            "expected `;`, found `}`",
+        );
+        check(
+            "namespace n {\n",
+            2,
+            1,
+            "expected declaration, found end of schema",
+        );
+        check(
+            "namespace n { archive A { x : vector< > ; } }",
+            1,
+            39,
+            "expected qualified name, found `>`",
+        );
+        check(
+            "namespace n { struct S { x : u8 : 9; } }",
+            1,
+            35,
+            "width 9 of field x exceeds the size of u8",
+        );
+        check("namespace n { /* }", 1, 15, "unterminated comment");
+        check("namespace n { # }", 1, 15, "unexpected character `#`");
+    }
+
+    #[test]
+    fn diff_schemas() {
+        let stored = parse(
+            r#"namespace n {
+            const u32 C = 1;
+            enum Kind : u8 { A, B, C = 5 }
+            struct S { x : u32 : 16; y : .n.Kind; z : u8; }
+            struct Gone { x : u8; }
+            archive R { @optional s : vector< .n.S >; old : raw_data; }
+            }"#,
+        )
+        .unwrap();
+        let expected = parse(
+            r#"namespace n {
+            const u32 C = 2;
+            enum Kind : u8 { A, B = 3, D }
+            struct S { y : .n.Kind : 8; x : u64 : 32; w : u8; }
+            enum Gone : u8 { X }
+            archive R { s : multivector< 32, .n.S >; new : raw_data; }
+            struct New { x : u8; }
+            }"#,
+        )
+        .unwrap();
+
+        let changes = diff(&stored, &expected);
+        assert_eq!(
+            changes,
+            vec![
+                SchemaChange::ValueChanged {
+                    name: ".n.C".into(),
+                    stored: 1,
+                    expected: 2,
+                },
+                SchemaChange::ValueChanged {
+                    name: ".n.Kind.B".into(),
+                    stored: 1,
+                    expected: 3,
+                },
+                SchemaChange::EnumValueAdded(".n.Kind.D".into()),
+                SchemaChange::EnumValueRemoved(".n.Kind.C".into()),
+                SchemaChange::TypeChanged {
+                    name: ".n.S.x".into(),
+                    stored: "u32".into(),
+                    expected: "u64".into(),
+                },
+                SchemaChange::FieldWidthChanged {
+                    name: ".n.S.x".into(),
+                    stored: 16,
+                    expected: 32,
+                },
+                SchemaChange::FieldAdded(".n.S.w".into()),
+                SchemaChange::FieldRemoved(".n.S.z".into()),
+                SchemaChange::FieldsReordered(".n.S".into()),
+                SchemaChange::DeclarationKindChanged(".n.Gone".into()),
+                SchemaChange::TypeChanged {
+                    name: ".n.R.s".into(),
+                    stored: "vector< .n.S >".into(),
+                    expected: "multivector< 32, .n.S >".into(),
+                },
+                SchemaChange::AnnotationsChanged {
+                    name: ".n.R.s".into(),
+                    stored: vec!["@optional".into()],
+                    expected: vec![],
+                },
+                SchemaChange::ResourceAdded(".n.R.new".into()),
+                SchemaChange::ResourceRemoved(".n.R.old".into()),
+                SchemaChange::DeclarationAdded(".n.New".into()),
+            ]
+        );
+        assert_eq!(changes[8].to_string(), "struct .n.S: fields reordered");
+        assert!(diff(&expected, &expected).is_empty());
+    }
+
+    #[test]
+    fn diff_schema_texts() {
+        let stored = "index(namespace n { struct I { value : u64 : 32; } })";
+        let expected = "index(namespace n { struct I { value : u64 : 33; } })";
+        assert_eq!(
+            diff_texts(stored, expected),
+            vec![SchemaChange::FieldWidthChanged {
+                name: ".n.I.value".into(),
+                stored: 32,
+                expected: 33,
+            }]
+        );
+        assert!(diff_texts("some other schema", expected).is_empty());
+    }
+}
@@ -1,23 +1,53 @@
 use crate::archive::{ArchiveBuilder, IndexStruct, Struct, VariadicStruct};
+use crate::canonical;
+use crate::checksum::{checksum, Crc32c};
 use crate::error::ResourceStorageError;
 use crate::memory::{SizeType, PADDING_SIZE};
 use crate::multivector::MultiVector;
+use crate::paging::Advice;
+#[cfg(target_os = "linux")]
+use crate::paging::Residency;
+use crate::schema;
 use crate::vector::ExternalVector;
 
-use std::cell::RefCell;
+use std::collections::BTreeSet;
 use std::fmt;
 use std::io::{self, Seek, Write};
 use std::mem;
 use std::ops::DerefMut;
 use std::ptr;
-use std::rc::Rc;
 use std::slice;
 use std::str;
+use std::sync::{Arc, Mutex};
 use std::thread;
 
 use diff;
 
-pub trait Stream: Write + Seek {}
+/// Output stream of a resource returned by [`create_output_stream`].
+///
+/// [`create_output_stream`]: trait.ResourceStorage.html#tymethod.create_output_stream
+pub trait Stream: Write + Send {
+    /// Writes the size of a resource to its header in the beginning of the
+    /// stream after all data was written, cf. [`ResourceHandle::close`].
+    ///
+    /// Seekable streams seek back and overwrite the header reserved when the
+    /// resource was created. Non-seekable streams record the size elsewhere
+    /// instead, e.g. in the table of contents of a bundle.
+    ///
+    /// [`ResourceHandle::close`]: struct.ResourceHandle.html#method.close
+    fn write_size_header(&mut self, size: SizeType) -> io::Result<()>;
+}
+
+/// Overwrites the size header in the beginning of a seekable stream, and
+/// seeks back to its end.
+pub(crate) fn seek_and_write_size<S: Write + Seek>(
+    stream: &mut S,
+    size: SizeType,
+) -> io::Result<()> {
+    stream.seek(io::SeekFrom::Start(0u64))?;
+    write_size(size, stream)?;
+    stream.seek(io::SeekFrom::End(0)).map(|_| ())
+}
 
 /// Hierarchical Resource Storage
 ///
@@ -25,7 +55,11 @@ pub trait Stream: Write + Seek {}
 /// slash-separated('/'). Manages schema for each resource and checks it on
 /// query. Resource storage is expected to provide read-write access to
 /// resources.
-pub trait ResourceStorage {
+///
+/// A resource storage is shared between archives, subarchives and builders by
+/// an `Arc`, and is required to be `Send + Sync`, so that opened archives can
+/// be shared between threads.
+pub trait ResourceStorage: Send + Sync {
     /// Open a flatdata resource with given name and schema for reading.
     ///
     /// Also checks if the schema matches the stored schema in the storage. The
@@ -39,16 +73,22 @@ pub trait ResourceStorage {
     /// storage.
     ///
     /// The schema will be stored as another resource under the name
-    /// `{resource_name}.schema`.
+    /// `{resource_name}.schema`, and the checksum of data under the name
+    /// `{resource_name}.checksum`.
     fn write(&self, resource_name: &str, schema: &str, data: &[u8]) -> io::Result<()> {
         // write data
         let stream = self.create_output_stream(resource_name)?;
-        let mut mut_stream = stream.borrow_mut();
+        let mut mut_stream = stream.lock().unwrap();
         write_to_stream(data, mut_stream.deref_mut())?;
+        // write checksum
+        let checksum_name = format!("{}.checksum", resource_name);
+        let stream = self.create_output_stream(&checksum_name)?;
+        let mut mut_stream = stream.lock().unwrap();
+        mut_stream.write_all(checksum(data).as_bytes())?;
         // write schema
         let schema_name = format!("{}.schema", resource_name);
         let stream = self.create_output_stream(&schema_name)?;
-        let mut mut_stream = stream.borrow_mut();
+        let mut mut_stream = stream.lock().unwrap();
         write_schema(schema, mut_stream.deref_mut())
     }
 
@@ -57,7 +97,7 @@ pub trait ResourceStorage {
     //
 
     /// Creates a resource storage at a given subdirectory.
-    fn subdir(&self, dir: &str) -> Rc<ResourceStorage>;
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage>;
 
     /// Returns `true` if resource exists in the storage.
     fn exists(&self, resource_name: &str) -> bool;
@@ -73,7 +113,55 @@ pub trait ResourceStorage {
 
     /// Creates a resource with given name and returns an output stream for
     /// writing to it.
-    fn create_output_stream(&self, resource_name: &str) -> io::Result<Rc<RefCell<Stream>>>;
+    fn create_output_stream(&self, resource_name: &str) -> io::Result<Arc<Mutex<dyn Stream>>>;
+
+    /// Lists the names of all resources in the storage in lexicographic
+    /// order, not including subdirectories.
+    ///
+    /// Besides flatdata resources, this includes their schemas
+    /// `{resource_name}.schema` and checksums `{resource_name}.checksum`, and
+    /// the signatures `{archive_name}.archive` of archives. By default,
+    /// listing is not supported.
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        Err(list_unsupported_error())
+    }
+
+    /// Lists the names of all subdirectories of the storage in
+    /// lexicographic order, e.g. subarchives.
+    ///
+    /// By default, listing is not supported.
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        Err(list_unsupported_error())
+    }
+
+    /// Hints the expected access pattern of a resource.
+    ///
+    /// Storages which memory map resources pass the hint to the operating
+    /// system. By default, hints are ignored.
+    fn advise(&self, _resource_name: &str, _advice: Advice) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Reports how many pages of a resource are resident in memory.
+    ///
+    /// Returns `None` for resources which are not memory mapped, which is the
+    /// default.
+    #[cfg(target_os = "linux")]
+    fn residency(&self, _resource_name: &str) -> io::Result<Option<Residency>> {
+        Ok(None)
+    }
+
+    /// Makes all resources written to the storage visible to readers.
+    ///
+    /// Called when the top-level archive builder is finished. Transactional
+    /// storages, e.g. a staged [`FileResourceStorage`], publish the written
+    /// archive atomically here. By default, resources are visible as soon as
+    /// they are written, and this method does nothing.
+    ///
+    /// [`FileResourceStorage`]: struct.FileResourceStorage.html#method.create_staged
+    fn commit(&self) -> io::Result<()> {
+        Ok(())
+    }
 
     //
     // Implementation helper
@@ -85,7 +173,7 @@ pub trait ResourceStorage {
     /// resource and its schema. It checks the integrity of data by
     /// verifying that the size of resource matched the size specified in
     /// the header. Also checks that the stored schema matches the provided
-    /// schema.
+    /// schema up to formatting, comments and the order of declarations.
     ///
     /// [`read`]: #method.read
     /// [`read_resource`]: #tymethod.read_resource
@@ -99,25 +187,37 @@ pub trait ResourceStorage {
             .map_err(|e| ResourceStorageError::from_io_error(e, resource_name.into()))?;
 
         let schema_name = format!("{}.schema", resource_name);
-        let schema = self
-            .read_resource(&schema_name)
-            .map_err(|e| ResourceStorageError::from_io_error(e, resource_name.into()))?;
-
+        let schema = self.read_resource(&schema_name).map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                ResourceStorageError::MissingSchema(resource_name.into())
+            } else {
+                ResourceStorageError::from_io_error(e, resource_name.into())
+            }
+        })?;
+
+        let unexpected_size = |expected| ResourceStorageError::UnexpectedDataSize {
+            resource_name: resource_name.into(),
+            expected,
+            actual: data.len(),
+        };
         if data.len() < mem::size_of::<SizeType>() + PADDING_SIZE {
-            return Err(ResourceStorageError::UnexpectedDataSize);
+            return Err(unexpected_size(mem::size_of::<SizeType>() + PADDING_SIZE));
         }
 
         let size = read_bytes!(SizeType, data.as_ptr()) as usize;
         if size + mem::size_of::<SizeType>() + PADDING_SIZE != data.len() {
-            return Err(ResourceStorageError::UnexpectedDataSize);
+            return Err(unexpected_size(
+                size + mem::size_of::<SizeType>() + PADDING_SIZE,
+            ));
         }
 
         let stored_schema_slice: &[u8] = schema;
-        let stored_schema =
-            str::from_utf8(stored_schema_slice).map_err(ResourceStorageError::Utf8Error)?;
-        if stored_schema != expected_schema {
+        let stored_schema = str::from_utf8(stored_schema_slice)
+            .map_err(|e| ResourceStorageError::Utf8Error(e, resource_name.into()))?;
+        if !canonical::is_equivalent(stored_schema, expected_schema) {
             return Err(ResourceStorageError::WrongSignature {
                 resource_name: resource_name.into(),
+                changes: schema::diff_texts(stored_schema, expected_schema),
                 diff: compute_diff(stored_schema, expected_schema),
             });
         }
@@ -136,7 +236,7 @@ pub trait ResourceStorage {
 /// an [`ExternalVector`] using this resource for writing and flushing data to
 /// storage.
 pub fn create_external_vector<'a, T>(
-    storage: &'a ResourceStorage,
+    storage: &'a dyn ResourceStorage,
     resource_name: &str,
     schema: &str,
 ) -> io::Result<ExternalVector<'a, T>>
@@ -146,7 +246,7 @@ where
     // write schema
     let schema_name = format!("{}.schema", resource_name);
     let stream = storage.create_output_stream(&schema_name)?;
-    stream.borrow_mut().write_all(schema.as_bytes())?;
+    stream.lock().unwrap().write_all(schema.as_bytes())?;
 
     // create external vector
     let data_writer = storage.create_output_stream(resource_name)?;
@@ -161,7 +261,7 @@ where
 /// an [`MultiVector`] using this resource for writing and flushing data to
 /// storage.
 pub fn create_multi_vector<'a, Idx, Ts>(
-    storage: &'a ResourceStorage,
+    storage: &'a dyn ResourceStorage,
     resource_name: &str,
     schema: &str,
 ) -> io::Result<MultiVector<'a, Idx, Ts>>
@@ -177,7 +277,7 @@ where
     // write schema
     let schema_name = format!("{}.schema", resource_name);
     let stream = storage.create_output_stream(&schema_name)?;
-    stream.borrow_mut().write_all(schema.as_bytes())?;
+    stream.lock().unwrap().write_all(schema.as_bytes())?;
 
     // create multi vector
     let data_writer = storage.create_output_stream(resource_name)?;
@@ -198,7 +298,7 @@ where
 ///
 /// [`AlreadyExists`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#AlreadyExists.v
 pub fn create_archive<T: ArchiveBuilder>(
-    storage: &Rc<ResourceStorage>,
+    storage: &Arc<dyn ResourceStorage>,
 ) -> Result<(), ResourceStorageError> {
     let signature_name = format!("{}.archive", T::NAME);
     {
@@ -226,6 +326,11 @@ pub struct MemoryDescriptor {
     size: usize,
 }
 
+// A memory descriptor is only a read-only view on memory owned by a resource
+// storage, which is never mutated or unmapped while the storage is alive.
+unsafe impl Send for MemoryDescriptor {}
+unsafe impl Sync for MemoryDescriptor {}
+
 impl Default for MemoryDescriptor {
     fn default() -> MemoryDescriptor {
         MemoryDescriptor {
@@ -262,9 +367,10 @@ impl MemoryDescriptor {
 /// [`create_output_stream`]: trait.ResourceStorage.html#tycreate_output_stream
 #[derive(Clone)]
 pub struct ResourceHandle<'a> {
-    stream: Option<Rc<RefCell<Stream>>>,
+    stream: Option<Arc<Mutex<dyn Stream>>>,
     size_in_bytes: usize,
-    storage: &'a ResourceStorage,
+    checksum: Crc32c,
+    storage: &'a dyn ResourceStorage,
     name: String,
     schema: String,
 }
@@ -277,20 +383,21 @@ impl<'a> ResourceHandle<'a> {
     /// Resource storage will try to reserve space in the beginning of the
     /// stream for the size of the resource, will may result in an `io::Error`.
     pub fn try_new(
-        storage: &'a ResourceStorage,
+        storage: &'a dyn ResourceStorage,
         name: String,
         schema: String,
-        stream: Rc<RefCell<Stream>>,
+        stream: Arc<Mutex<dyn Stream>>,
     ) -> io::Result<Self> {
         // Reserve space for size in the beginning of the stream, which will be updated
         // later.
         {
-            let mut mut_stream = stream.borrow_mut();
+            let mut mut_stream = stream.lock().unwrap();
             write_size(0u64, mut_stream.deref_mut())?;
         }
         Ok(Self {
             stream: Some(stream),
             size_in_bytes: 0,
+            checksum: Crc32c::new(),
             storage,
             name,
             schema,
@@ -309,15 +416,19 @@ impl<'a> ResourceHandle<'a> {
             .as_ref()
             .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "stream closed"))?;
 
-        let res = stream.borrow_mut().write_all(data);
+        let res = stream.lock().unwrap().write_all(data);
         if res.is_ok() {
             self.size_in_bytes += data.len();
+            self.checksum.update(data);
         }
         res
     }
 
     /// Close the underlying stream and write the header containing the size in
     /// bytes of written data.
+    ///
+    /// The checksum of written data is stored as another resource under the
+    /// name `{resource_name}.checksum`.
     pub fn close(&mut self) -> Result<&'a [u8], ResourceStorageError> {
         {
             let resource_name = self.name.clone();
@@ -330,14 +441,23 @@ impl<'a> ResourceHandle<'a> {
                 ))
             })?;
 
-            let mut mut_stream = stream.borrow_mut();
+            let mut mut_stream = stream.lock().unwrap();
             write_padding(mut_stream.deref_mut()).map_err(into_storage_error)?;
 
             // Update size in the beginning of the file
             mut_stream
-                .seek(io::SeekFrom::Start(0u64))
+                .write_size_header(self.size_in_bytes as u64)
                 .map_err(into_storage_error)?;
-            write_size(self.size_in_bytes as u64, mut_stream.deref_mut())
+
+            let checksum_name = format!("{}.checksum", self.name);
+            let checksum_stream = self
+                .storage
+                .create_output_stream(&checksum_name)
+                .map_err(into_storage_error)?;
+            checksum_stream
+                .lock()
+                .unwrap()
+                .write_all(self.checksum.finish().as_bytes())
                 .map_err(into_storage_error)?;
         }
         self.stream = None;
@@ -382,28 +502,64 @@ fn compute_diff(left: &str, right: &str) -> String {
         .join("\n")
 }
 
+//
+// List helpers
+//
+
+fn list_unsupported_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "listing resources is not supported by this storage",
+    )
+}
+
+/// Lists resources (`subdirs == false`) or subdirectories (`subdirs == true`)
+/// below `prefix` of a storage whose resources are identified by their full
+/// path, e.g. `statistics/vertex_degrees`.
+pub(crate) fn list_paths<'a, I>(paths: I, prefix: &str, subdirs: bool) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let names: BTreeSet<String> = paths
+        .into_iter()
+        .filter_map(|path| {
+            if prefix.is_empty() {
+                Some(&path[..])
+            } else {
+                path.strip_prefix(prefix)?.strip_prefix('/')
+            }
+        })
+        .filter_map(|name| match (name.find('/'), subdirs) {
+            (None, false) => Some(name.to_string()),
+            (Some(pos), true) => Some(name[..pos].to_string()),
+            _ => None,
+        })
+        .collect();
+    names.into_iter().collect()
+}
+
 //
 // Write helpers
 //
 
-fn write_to_stream(data: &[u8], stream: &mut Stream) -> io::Result<()> {
+fn write_to_stream(data: &[u8], stream: &mut dyn Stream) -> io::Result<()> {
     write_size(data.len() as u64, stream)?;
     stream.write_all(data)?;
     write_padding(stream)
 }
 
-fn write_schema(schema: &str, stream: &mut Stream) -> io::Result<()> {
+fn write_schema(schema: &str, stream: &mut dyn Stream) -> io::Result<()> {
     stream.write_all(schema.as_bytes())
 }
 
-fn write_size(value: SizeType, stream: &mut Stream) -> io::Result<()> {
+fn write_size<W: Write + ?Sized>(value: SizeType, stream: &mut W) -> io::Result<()> {
     const SIZE_OF_SIZE_TYPE: usize = mem::size_of::<SizeType>();
     let mut buffer: [u8; SIZE_OF_SIZE_TYPE] = [0; SIZE_OF_SIZE_TYPE];
     write_bytes!(SizeType; value, &mut buffer, 0, SIZE_OF_SIZE_TYPE * 8);
     stream.write_all(&buffer)
 }
 
-fn write_padding(stream: &mut Stream) -> io::Result<()> {
+fn write_padding(stream: &mut dyn Stream) -> io::Result<()> {
     let zeroes: [u8; PADDING_SIZE] = [0; PADDING_SIZE];
     stream.write_all(&zeroes)
 }
@@ -412,6 +568,72 @@ fn write_padding(stream: &mut Stream) -> io::Result<()> {
 mod test {
     use super::*;
     use crate::memstorage::MemoryResourceStorage;
+    use crate::schema::SchemaChange;
+
+    #[test]
+    fn list_memory_resources() {
+        let storage = MemoryResourceStorage::new("/root/list");
+        storage.write("b", "schema", &[1]).unwrap();
+        storage.subdir("sub").write("a", "schema", &[2]).unwrap();
+        storage
+            .subdir("sub/nested")
+            .write("c", "schema", &[3])
+            .unwrap();
+
+        assert_eq!(
+            storage.list_resources().unwrap(),
+            vec!["b", "b.checksum", "b.schema"]
+        );
+        assert_eq!(storage.list_subdirs().unwrap(), vec!["sub"]);
+        let sub = storage.subdir("sub");
+        assert_eq!(sub.read("a", "schema").unwrap(), &[2]);
+        assert_eq!(
+            sub.list_resources().unwrap(),
+            vec!["a", "a.checksum", "a.schema"]
+        );
+        assert_eq!(sub.list_subdirs().unwrap(), vec!["nested"]);
+    }
+
+    #[test]
+    fn list_nested_paths() {
+        let paths: Vec<String> = vec!["a", "b/c", "b/d/e", "bb/f", "b.schema"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        assert_eq!(list_paths(&paths, "", false), vec!["a", "b.schema"]);
+        assert_eq!(list_paths(&paths, "", true), vec!["b", "bb"]);
+        assert_eq!(list_paths(&paths, "b", false), vec!["c"]);
+        assert_eq!(list_paths(&paths, "b", true), vec!["d"]);
+        assert!(list_paths(&paths, "x", false).is_empty());
+    }
+
+    #[test]
+    fn read_with_equivalent_schema() {
+        let storage = MemoryResourceStorage::new("/root/schema");
+        let schema =
+            "namespace n { struct A { x : u8 : 1; } }\nnamespace n { struct B { y : u8 : 2; } }";
+        storage.write("resource", schema, &[42]).unwrap();
+
+        let regenerated = "// regenerated\nnamespace n {\nstruct B\n{\n    y : u8 : 2;\n}\nstruct A\n{\n    x : u8 : 1;\n}\n}\n";
+        assert_eq!(storage.read("resource", regenerated).unwrap(), &[42]);
+        match storage.read("resource", "namespace n { struct A { x : u8 : 2; } }") {
+            Err(ResourceStorageError::WrongSignature { changes, diff, .. }) => {
+                assert_eq!(
+                    changes,
+                    vec![
+                        SchemaChange::FieldWidthChanged {
+                            name: ".n.A.x".into(),
+                            stored: 1,
+                            expected: 2,
+                        },
+                        SchemaChange::DeclarationRemoved(".n.B".into()),
+                    ]
+                );
+                assert!(diff.contains("+namespace n { struct A { x : u8 : 2; } }"))
+            }
+            otherwise => panic!("unexpected result: {:?}", otherwise),
+        }
+    }
 
     #[test]
     #[should_panic]
@@ -422,7 +644,8 @@ mod test {
             .create_output_stream("/root/extvec/blubb.schema")
             .unwrap();
         stream
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .write_all("myschema".as_bytes())
             .unwrap();
 
@@ -445,7 +668,8 @@ mod test {
             .create_output_stream("/root/extvec/blubb.schema")
             .unwrap();
         stream
-            .borrow_mut()
+            .lock()
+            .unwrap()
             .write_all("myschema".as_bytes())
             .unwrap();
 
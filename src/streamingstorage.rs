@@ -0,0 +1,583 @@
+use crate::bundlestorage::{BundleWriter, SIZE_IN_TOC};
+use crate::memory::SizeType;
+use crate::storage::{list_paths, seek_and_write_size, ResourceStorage, Stream};
+
+use memmap::{Mmap, MmapOptions};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Write};
+use std::process;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size up to which a resource is buffered in memory while it is written.
+const BUFFER_SIZE: usize = 1 << 20;
+
+type Output = Box<dyn Write + Send>;
+
+/// Output of a streaming storage, shared with the resource which is streamed
+/// directly to it.
+struct DirectOutput {
+    // `None` after the bundle was committed.
+    writer: Option<BundleWriter<Output>>,
+    // Whether a resource is being streamed directly to the output.
+    busy: bool,
+}
+
+/// Output stream of a resource of a streaming storage.
+struct ResourceStream {
+    output: Arc<Mutex<DirectOutput>>,
+    // Data of the resource while it is small.
+    buffer: Vec<u8>,
+    // Anonymous temporary file the data is moved to once the resource gets
+    // larger than `BUFFER_SIZE`.
+    file: Option<File>,
+    // Offset of the resource in the output if it is streamed directly to it.
+    direct: Option<u64>,
+    // Whether the size header in the output is a placeholder, since it was
+    // written after the resource was streamed to the output.
+    size_in_toc: bool,
+}
+
+impl ResourceStream {
+    fn new(output: Arc<Mutex<DirectOutput>>) -> Self {
+        Self {
+            output,
+            buffer: Vec::new(),
+            file: None,
+            direct: None,
+            size_in_toc: false,
+        }
+    }
+
+    /// Moves the buffered data to a temporary file, and streams the resource
+    /// directly to the output if no other resource is streamed to it.
+    fn spill(&mut self) -> io::Result<()> {
+        let mut file = create_spill_file()?;
+        file.write_all(&self.buffer)?;
+        let mut output = self.output.lock().unwrap();
+        if !output.busy {
+            let writer = output.writer.as_mut().ok_or_else(committed_error)?;
+            let offset = writer.offset();
+            writer.write_data(&self.buffer)?;
+            self.direct = Some(offset);
+            output.busy = true;
+        }
+        self.buffer = Vec::new();
+        self.file = Some(file);
+        Ok(())
+    }
+}
+
+impl Write for ResourceStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.file {
+            Some(ref mut file) => {
+                file.write_all(buf)?;
+                if self.direct.is_some() {
+                    let mut output = self.output.lock().unwrap();
+                    output
+                        .writer
+                        .as_mut()
+                        .ok_or_else(committed_error)?
+                        .write_data(buf)?;
+                }
+            }
+            None => {
+                self.buffer.extend_from_slice(buf);
+                if self.buffer.len() > BUFFER_SIZE {
+                    self.spill()?;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Stream for ResourceStream {
+    fn write_size_header(&mut self, size: SizeType) -> io::Result<()> {
+        match self.file {
+            Some(ref mut file) => {
+                seek_and_write_size(file, size)?;
+                // the placeholder was already streamed to the output
+                self.size_in_toc = self.direct.is_some();
+                Ok(())
+            }
+            None => seek_and_write_size(&mut Cursor::new(&mut self.buffer), size),
+        }
+    }
+}
+
+/// Internal state of a streaming storage shared by all subdirectories.
+struct StreamingBundle {
+    output: Arc<Mutex<DirectOutput>>,
+    // Streams of resources which are being written.
+    pending: BTreeMap<String, Arc<Mutex<ResourceStream>>>,
+    // Anonymous temporary file small resources are copied to for reading
+    // them back.
+    spill: File,
+    spill_size: usize,
+    // Maps of completed resources, `None` for empty resources.
+    resources: BTreeMap<String, Option<Mmap>>,
+    // Completed resources which are not yet written to the output, since
+    // another resource is streamed directly to it.
+    unwritten: Vec<String>,
+}
+
+impl StreamingBundle {
+    /// Completes all resources which are not written to anymore, i.e. whose
+    /// streams are only referenced by the storage, and writes them to the
+    /// output if possible.
+    fn write_completed(&mut self) -> io::Result<()> {
+        let completed: Vec<String> = self
+            .pending
+            .iter()
+            .filter(|(_, stream)| Arc::strong_count(stream) == 1)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in completed {
+            self.complete(&name)?;
+        }
+        self.write_unwritten()
+    }
+
+    fn complete(&mut self, name: &str) -> io::Result<()> {
+        let stream = match self.pending.remove(name) {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        let mut stream = stream.lock().unwrap();
+        let map = match stream.file.take() {
+            Some(file) => Some(unsafe { Mmap::map(&file)? }),
+            None if stream.buffer.is_empty() => None,
+            None => {
+                self.spill.write_all(&stream.buffer)?;
+                let offset = self.spill_size;
+                self.spill_size += stream.buffer.len();
+                Some(unsafe {
+                    MmapOptions::new()
+                        .offset(offset)
+                        .len(stream.buffer.len())
+                        .map(&self.spill)?
+                })
+            }
+        };
+        self.resources.insert(name.into(), map);
+        match stream.direct {
+            Some(start) => {
+                let flags = if stream.size_in_toc { SIZE_IN_TOC } else { 0 };
+                let mut output = self.output.lock().unwrap();
+                output
+                    .writer
+                    .as_mut()
+                    .ok_or_else(committed_error)?
+                    .end_resource(name, start, flags)?;
+                output.busy = false;
+            }
+            None => self.unwritten.push(name.into()),
+        }
+        Ok(())
+    }
+
+    /// Writes the completed resources to the output unless another resource
+    /// is streamed directly to it.
+    fn write_unwritten(&mut self) -> io::Result<()> {
+        let mut output = self.output.lock().unwrap();
+        if output.busy {
+            return Ok(());
+        }
+        let writer = output.writer.as_mut().ok_or_else(committed_error)?;
+        for name in self.unwritten.drain(..) {
+            let data = match self.resources[&name] {
+                Some(ref map) => &map[..],
+                None => &[],
+            };
+            writer.add(&name, &mut &data[..])?;
+        }
+        Ok(())
+    }
+}
+
+/// Write-only resource storage streaming an archive in the bundle format to
+/// any output, e.g. stdout, a socket or a compressing writer.
+///
+/// The output is never seeked. A resource is buffered in memory while it is
+/// written, and written to the output once it is complete, i.e. its output
+/// stream is dropped. Once a resource gets larger than 1 MiB, it is streamed
+/// directly to the output instead, unless another resource is already
+/// streamed to it. The size header of a directly streamed resource is only
+/// known when it is closed; the size is recorded in the table of contents
+/// instead. Further large resources written at the same time are moved to
+/// anonymous temporary files in `std::env::temp_dir()` until the output is
+/// free. Therefore, writing needs at most 1 MiB of memory per resource which
+/// is being written.
+///
+/// The table of contents is written as trailer when the storage is
+/// committed, usually by finishing the top-level archive builder. The output
+/// can be read as any bundle by [`BundleResourceStorage`], e.g. after it was
+/// stored to a file.
+///
+/// Written resources stay readable, since builders read back resources when
+/// they are closed and when they are finished. For this, all resources are
+/// also kept in memory mapped temporary files, i.e. the archive takes as
+/// much space in the temporary directory as in the output.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{BundleResourceStorage, ResourceStorage, StreamingResourceStorage};
+///
+/// let path = std::env::temp_dir().join("streaming_doc_example.bundle");
+/// let file = std::fs::File::create(&path).unwrap();
+/// let storage = StreamingResourceStorage::new(file).unwrap();
+/// storage.write("resource", "schema", &[1, 2, 3]).unwrap();
+/// storage.commit().unwrap();
+///
+/// let storage = BundleResourceStorage::open(&path).unwrap();
+/// assert_eq!(storage.read("resource", "schema").unwrap(), &[1, 2, 3]);
+/// # }
+/// ```
+///
+/// [`BundleResourceStorage`]: struct.BundleResourceStorage.html
+pub struct StreamingResourceStorage {
+    bundle: Arc<Mutex<StreamingBundle>>,
+    prefix: String,
+}
+
+impl StreamingResourceStorage {
+    /// Creates a storage streaming an archive to the given output.
+    ///
+    /// The bundle header is written immediately. Consider wrapping
+    /// unbuffered outputs in a `BufWriter`.
+    pub fn new<W: Write + Send + 'static>(output: W) -> io::Result<Arc<Self>> {
+        Ok(Arc::new(Self {
+            bundle: Arc::new(Mutex::new(StreamingBundle {
+                output: Arc::new(Mutex::new(DirectOutput {
+                    writer: Some(BundleWriter::new(Box::new(output) as Output)?),
+                    busy: false,
+                })),
+                pending: BTreeMap::new(),
+                spill: create_spill_file()?,
+                spill_size: 0,
+                resources: BTreeMap::new(),
+                unwritten: Vec::new(),
+            })),
+            prefix: String::new(),
+        }))
+    }
+
+    fn key(&self, resource_name: &str) -> String {
+        if self.prefix.is_empty() {
+            resource_name.into()
+        } else {
+            format!("{}/{}", self.prefix, resource_name)
+        }
+    }
+
+    fn list(&self, subdirs: bool) -> Vec<String> {
+        let bundle = self.bundle.lock().unwrap();
+        list_paths(
+            bundle.pending.keys().chain(bundle.resources.keys()),
+            &self.prefix,
+            subdirs,
+        )
+    }
+}
+
+/// Creates a temporary file, which is removed as soon as it is closed.
+fn create_spill_file() -> io::Result<File> {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        let path = env::temp_dir().join(format!(
+            ".flatdata.streaming.{}.{:x}{}",
+            process::id(),
+            nanos,
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => {
+                fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn committed_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "streaming storage is already committed",
+    )
+}
+
+impl fmt::Debug for StreamingResourceStorage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bundle = self.bundle.lock().unwrap();
+        write!(
+            f,
+            "StreamingResourceStorage {{ prefix: {:?}, num_pending: {}, num_written: {} }}",
+            self.prefix,
+            bundle.pending.len(),
+            bundle.resources.len(),
+        )
+    }
+}
+
+impl ResourceStorage for StreamingResourceStorage {
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            bundle: self.bundle.clone(),
+            prefix: self.key(dir),
+        })
+    }
+
+    fn exists(&self, resource_name: &str) -> bool {
+        let key = self.key(resource_name);
+        let bundle = self.bundle.lock().unwrap();
+        bundle.pending.contains_key(&key) || bundle.resources.contains_key(&key)
+    }
+
+    fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
+        let key = self.key(resource_name);
+        let mut bundle = self.bundle.lock().unwrap();
+        if bundle.pending.contains_key(&key) {
+            if Arc::strong_count(&bundle.pending[&key]) > 1 {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!("resource {} is still being written", key),
+                ));
+            }
+            bundle.complete(&key)?;
+            bundle.write_unwritten()?;
+        }
+        let data = match bundle.resources.get(&key) {
+            Some(Some(map)) => &map[..],
+            Some(None) => &[],
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, key)),
+        };
+        // We cannot prove to Rust that the map will live as long as the storage
+        // (we never delete resources), so we need to manually extend lifetime
+        let extended_lifetime_data = unsafe { slice::from_raw_parts(data.as_ptr(), data.len()) };
+        Ok(extended_lifetime_data)
+    }
+
+    fn create_output_stream(
+        &self,
+        resource_name: &str,
+    ) -> Result<Arc<Mutex<dyn Stream>>, io::Error> {
+        let key = self.key(resource_name);
+        let mut bundle = self.bundle.lock().unwrap();
+        if bundle.output.lock().unwrap().writer.is_none() {
+            return Err(committed_error());
+        }
+        bundle.write_completed()?;
+        if bundle.resources.contains_key(&key) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("resource {} is already written to the output", key),
+            ));
+        }
+        let output = bundle.output.clone();
+        let stream = bundle
+            .pending
+            .entry(key)
+            .or_insert_with(|| Arc::new(Mutex::new(ResourceStream::new(output))))
+            .clone();
+        Ok(stream)
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        Ok(self.list(false))
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        Ok(self.list(true))
+    }
+
+    /// Writes all remaining resources and the table of contents to the
+    /// output.
+    ///
+    /// Has no effect for subdirectories and already committed storages.
+    fn commit(&self) -> io::Result<()> {
+        if !self.prefix.is_empty() {
+            return Ok(());
+        }
+        let mut bundle = self.bundle.lock().unwrap();
+        if bundle.output.lock().unwrap().writer.is_none() {
+            return Ok(());
+        }
+        if let Some(name) = bundle
+            .pending
+            .iter()
+            .find(|(_, stream)| Arc::strong_count(stream) > 1)
+            .map(|(name, _)| name.clone())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("resource {} is still being written", name),
+            ));
+        }
+        let names: Vec<String> = bundle.pending.keys().cloned().collect();
+        for name in names {
+            bundle.complete(&name)?;
+        }
+        bundle.write_unwritten()?;
+        let writer = bundle.output.lock().unwrap().writer.take();
+        writer.ok_or_else(committed_error)?.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundlestorage::{convert_bundle_to_directory, BundleResourceStorage};
+    use crate::filestorage::FileResourceStorage;
+    use crate::storage::ResourceHandle;
+
+    /// Output which can be inspected while it is written to.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_completed_resources() {
+        let output = SharedOutput::default();
+        let storage = StreamingResourceStorage::new(output.clone()).unwrap();
+        let header_size = output.0.lock().unwrap().len();
+
+        let stream = storage.create_output_stream("a").unwrap();
+        stream.lock().unwrap().write_all(&[1, 2, 3]).unwrap();
+        storage.subdir("sub").write("b", "schema", &[4]).unwrap();
+        // `a` is still being written, `b` is written once the next resource is
+        // created
+        assert_eq!(output.0.lock().unwrap().len(), header_size);
+        assert!(storage.read_resource("a").is_err());
+        drop(stream);
+
+        assert_eq!(storage.read_resource("a").unwrap(), &[1, 2, 3]);
+        assert!(output.0.lock().unwrap().len() > header_size);
+        assert_eq!(storage.subdir("sub").read("b", "schema").unwrap(), &[4]);
+        assert_eq!(
+            storage.create_output_stream("a").err().map(|e| e.kind()),
+            Some(io::ErrorKind::AlreadyExists)
+        );
+        assert_eq!(storage.list_resources().unwrap(), vec!["a"]);
+        assert_eq!(storage.list_subdirs().unwrap(), vec!["sub"]);
+
+        storage.commit().unwrap();
+        assert!(output.0.lock().unwrap().ends_with(b"FDBUNDLE"));
+        assert!(storage.create_output_stream("c").is_err());
+        assert_eq!(storage.read_resource("a").unwrap(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn read_back_written_resources() {
+        let storage = StreamingResourceStorage::new(io::sink()).unwrap();
+        let data: Vec<u8> = (0..10000).map(|i| i as u8).collect();
+        storage.write("a", "schema", &data[..3]).unwrap();
+        drop(storage.create_output_stream("b").unwrap());
+        storage.write("c", "schema", &data).unwrap();
+        storage.commit().unwrap();
+
+        assert_eq!(storage.read("a", "schema").unwrap(), &data[..3]);
+        assert_eq!(storage.read_resource("b").unwrap(), &[]);
+        assert_eq!(storage.read("c", "schema").unwrap(), &data[..]);
+        let bundle = storage.bundle.lock().unwrap();
+        assert!(bundle.pending.is_empty());
+        assert!(bundle.resources["b"].is_none());
+    }
+
+    #[test]
+    fn stream_large_resources_directly() {
+        let output = SharedOutput::default();
+        let storage = StreamingResourceStorage::new(output.clone()).unwrap();
+        let data: Vec<u8> = (0..3 * BUFFER_SIZE).map(|i| (i % 251) as u8).collect();
+        storage.write("small", "schema", &data[..10]).unwrap();
+        for name in &["a.schema", "b.schema"] {
+            let stream = storage.create_output_stream(name).unwrap();
+            stream.lock().unwrap().write_all(b"schema").unwrap();
+        }
+
+        let a = storage.create_output_stream("a").unwrap();
+        let mut a = ResourceHandle::try_new(&*storage, "a".into(), "schema".into(), a).unwrap();
+        let b = storage.create_output_stream("b").unwrap();
+        let mut b = ResourceHandle::try_new(&*storage, "b".into(), "schema".into(), b).unwrap();
+        let output_size = output.0.lock().unwrap().len();
+        for chunk in data.chunks(4096) {
+            a.write(chunk).unwrap();
+            b.write(chunk).unwrap();
+        }
+        // `a` is streamed to the output, `b` is moved to a temporary file
+        assert!(output.0.lock().unwrap().len() >= output_size + 2 * BUFFER_SIZE);
+        {
+            let bundle = storage.bundle.lock().unwrap();
+            for name in &["a", "b"] {
+                let stream = bundle.pending[*name].lock().unwrap();
+                assert_eq!(stream.buffer.capacity(), 0);
+                assert!(stream.file.is_some());
+            }
+            assert!(bundle.pending["a"].lock().unwrap().direct.is_some());
+            assert!(bundle.pending["b"].lock().unwrap().direct.is_none());
+        }
+        assert_eq!(a.close().unwrap(), &data[..]);
+        assert_eq!(b.close().unwrap(), &data[..]);
+        storage.commit().unwrap();
+
+        let path = env::temp_dir().join("flatdata_streaming_test/large.bundle");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &output.0.lock().unwrap()[..]).unwrap();
+        let bundle = BundleResourceStorage::open(&path).unwrap();
+        assert_eq!(bundle.read("small", "schema").unwrap(), &data[..10]);
+        assert_eq!(bundle.read("a", "schema").unwrap(), &data[..]);
+        assert_eq!(bundle.read("b", "schema").unwrap(), &data[..]);
+
+        let dir = env::temp_dir().join("flatdata_streaming_test/large.archive");
+        let _ = fs::remove_dir_all(&dir);
+        convert_bundle_to_directory(&path, &dir).unwrap();
+        let storage = FileResourceStorage::new(&dir);
+        assert_eq!(storage.read("a", "schema").unwrap(), &data[..]);
+    }
+
+    #[test]
+    fn commit_fails_for_open_resources() {
+        let storage = StreamingResourceStorage::new(io::sink()).unwrap();
+        let stream = storage.create_output_stream("a").unwrap();
+        assert_eq!(
+            storage.commit().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        drop(stream);
+        storage.commit().unwrap();
+    }
+}
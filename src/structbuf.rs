@@ -68,12 +68,12 @@ where
     }
 
     /// Get the stored object
-    pub fn get(&self) -> <T as Struct>::Item {
+    pub fn get(&self) -> <T as Struct<'_>>::Item {
         <T as Struct>::create(&self.data)
     }
 
     /// Get the mutable version of the stored object
-    pub fn get_mut(&mut self) -> <T as Struct>::ItemMut {
+    pub fn get_mut(&mut self) -> <T as Struct<'_>>::ItemMut {
         <T as Struct>::create_mut(&mut self.data)
     }
 
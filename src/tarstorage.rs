@@ -0,0 +1,370 @@
+#[cfg(target_os = "linux")]
+use crate::paging::Residency;
+use crate::paging::{self, Advice};
+use crate::storage::{list_paths, ResourceStorage, Stream};
+
+use memmap::Mmap;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::str;
+use std::sync::{Arc, Mutex};
+
+/// Size of a tar header and of the blocks data is padded to.
+const BLOCK_SIZE: usize = 512;
+
+/// Internal index of a memory mapped tar file shared by all subdirectories.
+struct TarArchive {
+    path: PathBuf,
+    mmap: Mmap,
+    files: BTreeMap<String, Range<usize>>,
+}
+
+impl fmt::Debug for TarArchive {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TarArchive {{ path: {:?}, num_files: {} }}",
+            self.path,
+            self.files.len()
+        )
+    }
+}
+
+/// Read-only resource storage serving an archive out of an uncompressed tar
+/// file.
+///
+/// The tar file is indexed once when opened and memory mapped as a whole.
+/// Resources are returned as slices of the mapping without copying or
+/// unpacking the tar file. Subdirectories correspond to path prefixes in the
+/// tar file, e.g. an archive stored under `data/karenina.archive` in the tar
+/// file is opened by `TarResourceStorage::open(path)?.subdir("data/karenina.
+/// archive")`.
+///
+/// Supported are ustar, GNU and pax tar files.
+#[derive(Debug)]
+pub struct TarResourceStorage {
+    tar: Arc<TarArchive>,
+    prefix: String,
+}
+
+impl TarResourceStorage {
+    /// Opens and indexes a tar file for reading.
+    ///
+    /// # Errors
+    ///
+    /// Returns an IO error if the file cannot be mapped, or an error of kind
+    /// [`InvalidData`] if the file is not a valid tar file.
+    ///
+    /// [`InvalidData`]: https://doc.rust-lang.org/std/io/enum.ErrorKind.html#InvalidData.v
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Arc<Self>> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        let mmap = unsafe { Mmap::map(&file)? };
+        let files = index_tar(&mmap)?;
+        Ok(Arc::new(Self {
+            tar: Arc::new(TarArchive { path, mmap, files }),
+            prefix: String::new(),
+        }))
+    }
+
+    fn key(&self, resource_name: &str) -> String {
+        if self.prefix.is_empty() {
+            normalize(resource_name)
+        } else {
+            normalize(&format!("{}/{}", self.prefix, resource_name))
+        }
+    }
+}
+
+impl ResourceStorage for TarResourceStorage {
+    fn subdir(&self, dir: &str) -> Arc<dyn ResourceStorage> {
+        Arc::new(Self {
+            tar: self.tar.clone(),
+            prefix: self.key(dir),
+        })
+    }
+
+    fn exists(&self, resource_name: &str) -> bool {
+        self.tar.files.contains_key(&self.key(resource_name))
+    }
+
+    fn read_resource(&self, resource_name: &str) -> Result<&[u8], io::Error> {
+        let key = self.key(resource_name);
+        match self.tar.files.get(&key) {
+            Some(range) => Ok(&self.tar.mmap[range.clone()]),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, key)),
+        }
+    }
+
+    fn create_output_stream(
+        &self,
+        _resource_name: &str,
+    ) -> Result<Arc<Mutex<dyn Stream>>, io::Error> {
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("tar file {:?} is read-only", self.tar.path),
+        ))
+    }
+
+    fn list_resources(&self) -> io::Result<Vec<String>> {
+        Ok(list_paths(self.tar.files.keys(), &self.prefix, false))
+    }
+
+    fn list_subdirs(&self) -> io::Result<Vec<String>> {
+        Ok(list_paths(self.tar.files.keys(), &self.prefix, true))
+    }
+
+    fn advise(&self, resource_name: &str, advice: Advice) -> io::Result<()> {
+        paging::advise(self.read_resource(resource_name)?, advice)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn residency(&self, resource_name: &str) -> io::Result<Option<Residency>> {
+        paging::residency(self.read_resource(resource_name)?).map(Some)
+    }
+}
+
+/// Normalizes a path in a tar file: removes `.` and empty components.
+fn normalize(path: &str) -> String {
+    path.split('/')
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Builds an index from file paths to their data in the tar file.
+fn index_tar(data: &[u8]) -> io::Result<BTreeMap<String, Range<usize>>> {
+    let mut files = BTreeMap::new();
+    let mut pos = 0;
+    // Path of the next entry from a GNU long name or a pax extended header
+    let mut long_name: Option<String> = None;
+    while pos + BLOCK_SIZE <= data.len() {
+        let header = &data[pos..pos + BLOCK_SIZE];
+        if header.iter().all(|&b| b == 0) {
+            // end of archive
+            break;
+        }
+        verify_checksum(header)?;
+
+        let size = parse_size(&header[124..136])?;
+        let start = pos + BLOCK_SIZE;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= data.len())
+            .ok_or_else(|| invalid_data("tar entry out of bounds"))?;
+        let content = &data[start..end];
+
+        match header[156] {
+            // GNU long name of the next entry
+            b'L' => long_name = Some(parse_string(content)?.into()),
+            // pax extended header of the next entry
+            b'x' => {
+                if let Some(path) = parse_pax_path(content)? {
+                    long_name = Some(path);
+                }
+            }
+            // regular or contiguous file
+            b'0' | b'\0' | b'7' => {
+                let name = match long_name.take() {
+                    Some(name) => name,
+                    None => parse_name(header)?,
+                };
+                files.insert(normalize(&name), start..end);
+            }
+            // directories, links, global pax headers, etc.
+            _ => long_name = None,
+        }
+
+        // entries are padded to full blocks
+        pos = end
+            .checked_next_multiple_of(BLOCK_SIZE)
+            .ok_or_else(|| invalid_data("tar entry out of bounds"))?;
+    }
+    Ok(files)
+}
+
+fn parse_name(header: &[u8]) -> io::Result<String> {
+    let name = parse_string(&header[0..100])?;
+    // POSIX ustar stores the leading part of long paths in a separate prefix
+    if &header[257..263] == b"ustar\0" {
+        let prefix = parse_string(&header[345..500])?;
+        if !prefix.is_empty() {
+            return Ok(format!("{}/{}", prefix, name));
+        }
+    }
+    Ok(name.into())
+}
+
+fn parse_string(field: &[u8]) -> io::Result<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    str::from_utf8(&field[..len]).map_err(|_| invalid_data("path is not valid utf8"))
+}
+
+fn parse_size(field: &[u8]) -> io::Result<usize> {
+    if field[0] & 0x80 != 0 {
+        // GNU base-256 encoding
+        return field[1..]
+            .iter()
+            .try_fold(0usize, |size, &b| {
+                size.checked_mul(256).map(|size| size | b as usize)
+            })
+            .ok_or_else(|| invalid_data("tar entry size out of range"));
+    }
+    parse_octal(field)
+}
+
+fn parse_octal(field: &[u8]) -> io::Result<usize> {
+    let digits = str::from_utf8(field).map_err(|_| invalid_data("invalid octal number"))?;
+    let digits = digits.trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    usize::from_str_radix(digits, 8).map_err(|_| invalid_data("invalid octal number"))
+}
+
+fn verify_checksum(header: &[u8]) -> io::Result<()> {
+    let expected = parse_octal(&header[148..156])?;
+    // the checksum field itself is counted as spaces
+    let actual: usize = header[..148]
+        .iter()
+        .chain(header[156..].iter())
+        .map(|&b| b as usize)
+        .sum::<usize>()
+        + 8 * b' ' as usize;
+    if expected != actual {
+        return Err(invalid_data("invalid tar header checksum"));
+    }
+    Ok(())
+}
+
+/// Extracts the `path` record from pax extended header data.
+///
+/// Each record has the form `<length> <key>=<value>\n`, where length is the
+/// length of the whole record in bytes.
+fn parse_pax_path(mut data: &[u8]) -> io::Result<Option<String>> {
+    let mut path = None;
+    while !data.is_empty() {
+        let space = data
+            .iter()
+            .position(|&b| b == b' ')
+            .ok_or_else(|| invalid_data("invalid pax record"))?;
+        let len: usize = str::from_utf8(&data[..space])
+            .ok()
+            .and_then(|len| len.parse().ok())
+            .filter(|&len| len >= space + 2 && len <= data.len() && data[len - 1] == b'\n')
+            .ok_or_else(|| invalid_data("invalid pax record"))?;
+        let record = str::from_utf8(&data[space + 1..len - 1])
+            .map_err(|_| invalid_data("pax record is not valid utf8"))?;
+        if let Some(value) = record.strip_prefix("path=") {
+            path = Some(value.to_string());
+        }
+        data = &data[len..];
+    }
+    Ok(path)
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
+    fn create_tar(path: &Path, files: &[(&str, &[u8])]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut builder = tar::Builder::new(File::create(path).unwrap());
+        for (name, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, name, *data).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn read_files_from_tar() {
+        let path = env::temp_dir().join("flatdata_tar_test/read_files.tar");
+        let long_name = format!("{}/resource", "x".repeat(150));
+        create_tar(
+            &path,
+            &[
+                ("./archive/a", b"content of a"),
+                ("archive/sub/b", b"b"),
+                ("archive/empty", b""),
+                (&long_name, b"long"),
+            ],
+        );
+
+        let storage = TarResourceStorage::open(&path).unwrap();
+        assert!(storage.exists("archive/a"));
+        assert!(!storage.exists("archive/b"));
+        assert_eq!(storage.read_resource("archive/a").unwrap(), b"content of a");
+        assert_eq!(storage.read_resource("archive/empty").unwrap(), b"");
+        assert_eq!(storage.read_resource(&long_name).unwrap(), b"long");
+
+        let archive = storage.subdir("archive");
+        assert!(archive.exists("a"));
+        assert_eq!(archive.subdir("sub").read_resource("b").unwrap(), b"b");
+        assert_eq!(
+            archive.read_resource("c").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(archive.create_output_stream("c").is_err());
+    }
+
+    #[test]
+    fn open_invalid_tar() {
+        let path = env::temp_dir().join("flatdata_tar_test/invalid.tar");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, vec![1; 1024]).unwrap();
+        let err = TarResourceStorage::open(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // pax records, whose length does not cover the record
+        for record in &[&b"2 x"[..], b"3 x", b"4 x=y"] {
+            let mut builder = tar::Builder::new(File::create(&path).unwrap());
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(tar::EntryType::XHeader);
+            header.set_size(record.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, "pax", *record).unwrap();
+            builder.finish().unwrap();
+            drop(builder);
+            let err = TarResourceStorage::open(&path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn index_tar_with_huge_sizes() {
+        let path = env::temp_dir().join("flatdata_tar_test/huge_sizes.tar");
+        create_tar(&path, &[("a", b"content of a")]);
+        let tar = fs::read(&path).unwrap();
+        assert!(index_tar(&tar).is_ok());
+
+        let mut base_256 = vec![0x80];
+        base_256.extend_from_slice(&[0xff; 11]);
+        let mut max_octal = b"77777777777".to_vec();
+        max_octal.push(0);
+        for size in &[base_256, max_octal] {
+            let mut corrupt = tar.clone();
+            corrupt[124..136].copy_from_slice(size);
+            // recompute the header checksum, which counts its own field as spaces
+            corrupt[148..156].copy_from_slice(b"        ");
+            let checksum: usize = corrupt[..BLOCK_SIZE].iter().map(|&b| b as usize).sum();
+            corrupt[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+            let err = index_tar(&corrupt).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -119,7 +119,7 @@ where
 
     /// Returns an `ArrayView` to this vector.
     #[inline]
-    pub fn as_view(&self) -> ArrayView<T> {
+    pub fn as_view(&self) -> ArrayView<'_, T> {
         ArrayView::new(&self.data[..self.size_in_bytes()])
     }
 
@@ -132,7 +132,7 @@ where
     /// Appends an element to the end of this vector and returns a mutable
     /// handle to it.
     #[inline]
-    pub fn grow(&mut self) -> <T as Struct>::ItemMut {
+    pub fn grow(&mut self) -> <T as Struct<'_>>::ItemMut {
         let old_size = self.data.len();
         self.data.resize(old_size + <T as Struct>::SIZE_IN_BYTES, 0);
         let last_index = self.len() - 1;
@@ -142,14 +142,14 @@ where
     /// Return an accessor handle to the element at position `index` in the
     /// vector.
     #[inline]
-    pub fn at(&self, index: usize) -> <T as Struct>::Item {
+    pub fn at(&self, index: usize) -> <T as Struct<'_>>::Item {
         T::create(&self.data[index * <T as Struct>::SIZE_IN_BYTES..])
     }
 
     /// Return a mutable handle to the element at position `index` in the
     /// vector.
     #[inline]
-    pub fn at_mut(&mut self, index: usize) -> <T as Struct>::ItemMut {
+    pub fn at_mut(&mut self, index: usize) -> <T as Struct<'_>>::ItemMut {
         T::create_mut(&mut self.data[index * <T as Struct>::SIZE_IN_BYTES..])
     }
 
@@ -301,7 +301,7 @@ where
     /// may fail due to different IO reasons.
     ///
     /// [`flush`]: #method.flush
-    pub fn grow(&mut self) -> io::Result<<T as Struct>::ItemMut> {
+    pub fn grow(&mut self) -> io::Result<<T as Struct<'_>>::ItemMut> {
         if self.data.len() > 1024 * 1024 * 32 {
             self.flush()?;
         }
@@ -0,0 +1,242 @@
+use crate::paging;
+use crate::storage::ResourceStorage;
+
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Number of bytes prefetched at once, i.e. the granularity of progress
+/// reports and of the rate limit.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Progress of a [`WarmUp`].
+///
+/// [`WarmUp`]: struct.WarmUp.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WarmUpProgress {
+    /// Number of resources completely warmed up.
+    pub resources_done: usize,
+    /// Number of resources to warm up.
+    pub resources_total: usize,
+    /// Number of bytes warmed up.
+    pub bytes_done: u64,
+    /// Number of bytes to warm up; `0` until all resources are mapped.
+    pub bytes_total: u64,
+}
+
+#[derive(Debug, Default)]
+struct Progress {
+    resources_done: AtomicUsize,
+    resources_total: usize,
+    bytes_done: AtomicU64,
+    bytes_total: AtomicU64,
+}
+
+impl Progress {
+    fn snapshot(&self) -> WarmUpProgress {
+        WarmUpProgress {
+            resources_done: self.resources_done.load(Ordering::SeqCst),
+            resources_total: self.resources_total,
+            bytes_done: self.bytes_done.load(Ordering::SeqCst),
+            bytes_total: self.bytes_total.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Handle of resources being warmed up in a background thread.
+///
+/// Warming up reads all pages of the resources into memory, which avoids
+/// page faults, and thus, latency spikes when the resources are accessed
+/// for the first time, e.g. after deploying a new archive. Since resources
+/// are read through the storage they were opened with, a
+/// [`FileResourceStorage`] warms up exactly the mappings used by archives
+/// opened from it.
+///
+/// The background thread keeps running when the handle is dropped; use
+/// [`cancel`] to stop it early.
+///
+/// # Examples
+///
+/// ```
+/// # extern crate flatdata;
+/// # fn main() {
+/// use flatdata::{MemoryResourceStorage, ResourceStorage, WarmUp};
+///
+/// let storage = MemoryResourceStorage::new("/root/warm_up");
+/// storage.write("resource", "schema", &[42; 100]).unwrap();
+///
+/// // prefetch with at most 64 MiB/s
+/// let warm_up = WarmUp::new(vec![(storage, "resource".into())], Some(64 << 20));
+/// let progress = warm_up.wait().unwrap();
+/// assert_eq!(progress.resources_done, 1);
+/// # }
+/// ```
+///
+/// [`FileResourceStorage`]: struct.FileResourceStorage.html
+/// [`cancel`]: #method.cancel
+pub struct WarmUp {
+    progress: Arc<Progress>,
+    cancelled: Arc<AtomicBool>,
+    thread: thread::JoinHandle<io::Result<()>>,
+}
+
+impl WarmUp {
+    /// Starts warming up the given resources of storages in a background
+    /// thread.
+    ///
+    /// Resources are warmed up in the given order. If `rate_limit` is set,
+    /// at most that many bytes per second are read.
+    pub fn new(
+        resources: Vec<(Arc<dyn ResourceStorage>, String)>,
+        rate_limit: Option<u64>,
+    ) -> Self {
+        let progress = Arc::new(Progress {
+            resources_total: resources.len(),
+            ..Progress::default()
+        });
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let progress = progress.clone();
+            let cancelled = cancelled.clone();
+            thread::spawn(move || run(&resources, rate_limit, &progress, &cancelled))
+        };
+        Self {
+            progress,
+            cancelled,
+            thread,
+        }
+    }
+
+    /// Starts warming up the resources with given names of a storage in a
+    /// background thread.
+    pub fn resources(
+        storage: Arc<dyn ResourceStorage>,
+        resource_names: &[&str],
+        rate_limit: Option<u64>,
+    ) -> Self {
+        let resources = resource_names
+            .iter()
+            .map(|name| (storage.clone(), name.to_string()))
+            .collect();
+        Self::new(resources, rate_limit)
+    }
+
+    /// Returns the current progress.
+    pub fn progress(&self) -> WarmUpProgress {
+        self.progress.snapshot()
+    }
+
+    /// Returns whether the background thread has stopped.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    /// Stops warming up after the current chunk.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Waits until warming up has stopped and returns the final progress.
+    ///
+    /// # Errors
+    ///
+    /// Returns the IO error of the first resource which could not be read.
+    pub fn wait(self) -> io::Result<WarmUpProgress> {
+        self.thread
+            .join()
+            .map_err(|_| io::Error::other("warm up thread panicked"))??;
+        Ok(self.progress.snapshot())
+    }
+}
+
+impl fmt::Debug for WarmUp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WarmUp {{ progress: {:?} }}", self.progress())
+    }
+}
+
+fn run(
+    resources: &[(Arc<dyn ResourceStorage>, String)],
+    rate_limit: Option<u64>,
+    progress: &Progress,
+    cancelled: &AtomicBool,
+) -> io::Result<()> {
+    // map all resources first to know the total size
+    let data = resources
+        .iter()
+        .map(|(storage, name)| storage.read_resource(name))
+        .collect::<io::Result<Vec<_>>>()?;
+    let bytes_total = data.iter().map(|data| data.len() as u64).sum();
+    progress.bytes_total.store(bytes_total, Ordering::SeqCst);
+
+    let start = Instant::now();
+    let mut bytes_done = 0;
+    for data in data {
+        for chunk in data.chunks(CHUNK_SIZE) {
+            if cancelled.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+            paging::populate(chunk);
+            bytes_done += chunk.len() as u64;
+            progress.bytes_done.store(bytes_done, Ordering::SeqCst);
+            if let Some(rate_limit) = rate_limit {
+                throttle(start, bytes_done, rate_limit);
+            }
+        }
+        progress.resources_done.fetch_add(1, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+/// Sleeps until reading `bytes_done` since `start` does not exceed the rate
+/// limit (in bytes per second).
+fn throttle(start: Instant, bytes_done: u64, rate_limit: u64) {
+    let expected = Duration::from_secs_f64(bytes_done as f64 / cmp::max(rate_limit, 1) as f64);
+    let elapsed = start.elapsed();
+    if expected > elapsed {
+        thread::sleep(expected - elapsed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memstorage::MemoryResourceStorage;
+
+    #[test]
+    fn warm_up_resources() {
+        let storage = MemoryResourceStorage::new("/root/warm_up");
+        storage.write("a", "schema", &[1; 1000]).unwrap();
+        storage.write("b", "schema", &[2; 3000]).unwrap();
+
+        let warm_up = WarmUp::resources(storage, &["a", "b"], None);
+        let progress = warm_up.wait().unwrap();
+        assert_eq!(progress.resources_done, 2);
+        assert_eq!(progress.resources_total, 2);
+        assert_eq!(progress.bytes_done, progress.bytes_total);
+        assert!(progress.bytes_total >= 4000);
+    }
+
+    #[test]
+    fn warm_up_with_rate_limit() {
+        let storage = MemoryResourceStorage::new("/root/warm_up_rate_limit");
+        storage.write("a", "schema", &[1; 3 * CHUNK_SIZE]).unwrap();
+
+        let start = Instant::now();
+        let warm_up = WarmUp::resources(storage, &["a"], Some(20 * CHUNK_SIZE as u64));
+        let progress = warm_up.wait().unwrap();
+        assert_eq!(progress.resources_done, 1);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn warm_up_missing_resource() {
+        let storage = MemoryResourceStorage::new("/root/warm_up_missing");
+        let warm_up = WarmUp::resources(storage, &["missing"], None);
+        assert_eq!(warm_up.wait().unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
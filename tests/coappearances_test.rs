@@ -3,11 +3,13 @@ extern crate flatdata;
 
 use std::env;
 use std::fs;
-use std::io::Read;
+use std::io::{self, Read};
 use std::path;
 use std::str;
+use std::sync::Arc;
+use std::thread;
 
-use flatdata::{Archive, ArchiveBuilder};
+use flatdata::{Archive, ArchiveBuilder, ResourceStorage};
 
 pub mod coappearances;
 
@@ -379,3 +381,887 @@ fn read_and_validate_calculate_data_subarchive() {
         assert_eq!(deg.value(), expected_degrees[index]);
     }
 }
+
+#[test]
+fn read_coappearances_from_multiple_threads() {
+    let storage = flatdata::FileResourceStorage::new(path::PathBuf::from(
+        "tests/coappearances/karenina.archive",
+    ));
+    let g = Arc::new(coappearances::Graph::open(storage).expect("invalid archive"));
+
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let g = g.clone();
+            thread::spawn(move || {
+                let num_chapters: usize = g.edges().iter().map(|e| e.count() as usize).sum();
+                assert_eq!(g.chapters().len(), num_chapters);
+                g.vertices().len()
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        assert_eq!(handle.join().expect("thread panicked"), 138);
+    }
+}
+
+#[test]
+fn read_coappearances_from_bundle() {
+    let source_archive_path = path::PathBuf::from("tests/coappearances/karenina.archive");
+    let bundle_path = env::temp_dir().join("read_coappearances_from_bundle/karenina.bundle");
+    flatdata::convert_directory_to_bundle(&source_archive_path, &bundle_path)
+        .expect("could not convert archive to bundle");
+
+    let storage = flatdata::BundleResourceStorage::open(&bundle_path).expect("invalid bundle");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    assert_eq!(g.vertices().len(), 138);
+    assert_eq!(g.edges().len(), 494);
+    let stats = g.statistics().as_ref().expect("statistics failed");
+    assert_eq!(stats.vertex_degrees().len(), 138);
+
+    let archive_path = env::temp_dir().join("read_coappearances_from_bundle/karenina.archive");
+    if archive_path.exists() {
+        fs::remove_dir_all(&archive_path).expect("could not remove already existing archive");
+    }
+    flatdata::convert_bundle_to_directory(&bundle_path, &archive_path)
+        .expect("could not convert bundle to archive");
+    for resource_name in &["meta", "vertices", "edges", "vertices_data", "chapters"] {
+        assert!(compare_resource(
+            &source_archive_path,
+            &archive_path,
+            resource_name
+        ));
+    }
+    assert!(compare_resource(
+        &source_archive_path.join("statistics"),
+        &archive_path.join("statistics"),
+        "vertex_degrees"
+    ));
+}
+
+#[test]
+fn read_coappearances_from_tar() {
+    let tar_path = env::temp_dir().join("read_coappearances_from_tar/karenina.tar");
+    fs::create_dir_all(tar_path.parent().unwrap()).expect("could not create dir");
+    {
+        let mut builder = tar::Builder::new(fs::File::create(&tar_path).unwrap());
+        builder
+            .append_dir_all(
+                "data/karenina.archive",
+                "tests/coappearances/karenina.archive",
+            )
+            .expect("could not create tar file");
+        builder.finish().expect("could not create tar file");
+    }
+
+    let storage = flatdata::TarResourceStorage::open(&tar_path).expect("invalid tar file");
+    let g = coappearances::Graph::open(storage.subdir("data/karenina.archive"))
+        .expect("invalid archive");
+    assert_eq!(g.vertices().len(), 138);
+    assert_eq!(g.edges().len(), 494);
+    let stats = g.statistics().as_ref().expect("statistics failed");
+    assert_eq!(stats.invariants().max_degree(), 71);
+}
+
+macro_rules! embedded_resources {
+    ($($name:expr),*) => {
+        vec![$(
+            (
+                $name,
+                &include_bytes!(concat!("coappearances/karenina.archive/", $name))[..],
+            ),
+        )*]
+    };
+}
+
+#[test]
+fn read_coappearances_from_embedded_buffers() {
+    let storage = flatdata::BufferResourceStorage::new(embedded_resources!(
+        "Graph.archive",
+        "Graph.archive.schema",
+        "chapters",
+        "chapters.schema",
+        "edges",
+        "edges.schema",
+        "meta",
+        "meta.schema",
+        "strings",
+        "strings.schema",
+        "vertices",
+        "vertices.schema",
+        "vertices_data",
+        "vertices_data.schema",
+        "vertices_data_index",
+        "vertices_data_index.schema",
+        "statistics/Statistics.archive",
+        "statistics/Statistics.archive.schema",
+        "statistics/invariants",
+        "statistics/invariants.schema",
+        "statistics/vertex_degrees",
+        "statistics/vertex_degrees.schema"
+    ));
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    assert_eq!(g.vertices().len(), 138);
+    assert_eq!(g.edges().len(), 494);
+    let stats = g.statistics().as_ref().expect("statistics failed");
+    assert_eq!(stats.invariants().max_degree(), 71);
+}
+
+#[test]
+fn read_coappearances_with_replaced_resource() {
+    let lower = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let upper = flatdata::MemoryResourceStorage::new("/root/karenina.archive");
+    let storage = flatdata::OverlayResourceStorage::new(upper, vec![lower]);
+
+    let strings = b"Anna\0Karenina\0";
+    storage
+        .write(
+            "strings",
+            coappearances::schema::resources::graph::STRINGS,
+            strings,
+        )
+        .expect("could not replace strings");
+
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    assert_eq!(g.strings(), &strings[..]);
+    assert_eq!(g.vertices().len(), 138);
+    let stats = g.statistics().as_ref().expect("statistics failed");
+    assert_eq!(stats.invariants().max_degree(), 71);
+}
+
+#[test]
+#[cfg(feature = "zstd")]
+fn read_write_compressed_statistics() {
+    let archive_path = env::temp_dir().join("read_write_compressed_statistics/statistics");
+    if archive_path.exists() {
+        fs::remove_dir_all(&archive_path).expect("could not remove already existing archive");
+    }
+    let compression = flatdata::Compression::Zstd { level: 0 };
+
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    let orig = g.statistics().as_ref().expect("statistics failed");
+    {
+        let storage = flatdata::CompressedResourceStorage::new(
+            flatdata::FileResourceStorage::new(archive_path.clone()),
+            compression,
+        );
+        let builder = coappearances::StatisticsBuilder::new(storage.clone())
+            .expect("could not create archive");
+        builder
+            .set_invariants(orig.invariants())
+            .expect("set_invariants failed");
+        let mut vertex_degrees = builder
+            .start_vertex_degrees()
+            .expect("start_vertex_degrees failed");
+        for deg in orig.vertex_degrees().iter() {
+            vertex_degrees.grow().expect("grow failed").fill_from(&deg);
+        }
+        vertex_degrees.close().expect("close failed");
+        storage.flush().expect("flush failed");
+    }
+    assert!(archive_path.join("vertex_degrees.codec").exists());
+
+    let storage = flatdata::CompressedResourceStorage::new(
+        flatdata::FileResourceStorage::new(archive_path),
+        compression,
+    );
+    let copy = coappearances::Statistics::open(storage).expect("invalid archive");
+    assert_eq!(orig.invariants(), copy.invariants());
+    assert_eq!(orig.vertex_degrees().len(), copy.vertex_degrees().len());
+    for (a, b) in orig
+        .vertex_degrees()
+        .iter()
+        .zip(copy.vertex_degrees().iter())
+    {
+        assert_eq!(a, b);
+    }
+}
+
+#[test]
+fn verify_coappearances_checksums() {
+    let (archive_path, _) = copy_coappearances_archive(
+        "tests/coappearances/karenina.archive",
+        "verify_coappearances_checksums/karenina.archive",
+    );
+    let open_storage = || flatdata::FileResourceStorage::new(archive_path.clone());
+
+    let corrupt =
+        flatdata::verify_archive::<coappearances::Graph>(open_storage()).expect("invalid archive");
+    assert!(corrupt.is_empty());
+    coappearances::Graph::open_verified(open_storage()).expect("invalid archive");
+
+    // flip a bit in the data of edges
+    let edges_path = archive_path.join("edges");
+    let mut edges = fs::read(&edges_path).expect("could not read edges");
+    edges[8] ^= 1;
+    fs::write(&edges_path, edges).expect("could not write edges");
+
+    let corrupt =
+        flatdata::verify_archive::<coappearances::Graph>(open_storage()).expect("invalid archive");
+    assert_eq!(corrupt, vec!["edges".to_string()]);
+    match coappearances::Graph::open_verified(open_storage())
+        .as_ref()
+        .map_err(|e| e.root_cause())
+    {
+        Err(flatdata::ResourceStorageError::ChecksumMismatch(name)) if name == "edges" => (),
+        otherwise => panic!("unexpected result: {:?}", otherwise),
+    }
+    coappearances::Graph::open(open_storage()).expect("invalid archive");
+}
+
+#[test]
+fn write_statistics_to_staged_storage() {
+    let archive_path = env::temp_dir().join("write_statistics_to_staged_storage/statistics");
+    if archive_path.exists() {
+        fs::remove_dir_all(&archive_path).expect("could not remove already existing archive");
+    }
+
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    let orig = g.statistics().as_ref().expect("statistics failed");
+
+    let storage =
+        flatdata::FileResourceStorage::create_staged(&archive_path).expect("staging failed");
+    let builder = coappearances::StatisticsBuilder::new(storage).expect("could not create archive");
+    builder
+        .set_invariants(orig.invariants())
+        .expect("set_invariants failed");
+    builder
+        .set_vertex_degrees(&orig.vertex_degrees())
+        .expect("set_vertex_degrees failed");
+
+    // nothing is visible to readers before the builder is finished
+    assert!(!archive_path.exists());
+    builder.finish().expect("finish failed");
+
+    let storage = flatdata::FileResourceStorage::new(archive_path);
+    let copy = coappearances::Statistics::open(storage).expect("invalid archive");
+    assert_eq!(orig.invariants(), copy.invariants());
+    assert_eq!(orig.vertex_degrees().len(), copy.vertex_degrees().len());
+}
+
+#[test]
+fn finish_statistics_builder() {
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    let orig = g.statistics().as_ref().expect("statistics failed");
+
+    let storage = flatdata::MemoryResourceStorage::new("/root/finish_statistics_builder");
+    let builder = coappearances::StatisticsBuilder::new(storage).expect("could not create archive");
+    builder
+        .set_invariants(orig.invariants())
+        .expect("set_invariants failed");
+    match builder.clone().finish() {
+        Err(flatdata::ResourceStorageError::MissingResource(ref name))
+            if name == "vertex_degrees" => {}
+        otherwise => panic!("unexpected result: {:?}", otherwise),
+    }
+
+    let started = builder.clone();
+    let mut vertex_degrees = started
+        .start_vertex_degrees()
+        .expect("start_vertex_degrees failed");
+    for deg in orig.vertex_degrees().iter() {
+        vertex_degrees.grow().expect("grow failed").fill_from(&deg);
+    }
+    match builder.clone().finish() {
+        Err(flatdata::ResourceStorageError::ResourceNotClosed(ref name))
+            if name == "vertex_degrees" => {}
+        otherwise => panic!("unexpected result: {:?}", otherwise),
+    }
+    vertex_degrees.close().expect("close failed");
+
+    let copy = builder.finish().expect("finish failed");
+    assert_eq!(orig.invariants(), copy.invariants());
+    assert_eq!(orig.vertex_degrees().len(), copy.vertex_degrees().len());
+}
+
+#[test]
+fn finish_graph_builder_without_optional_subarchive() {
+    let (archive_path, gb) = copy_coappearances_archive(
+        "tests/coappearances/karenina.archive",
+        "finish_graph_builder/karenina.archive",
+    );
+    let g = gb.finish().expect("finish failed");
+    assert_eq!(g.vertices().len(), 138);
+    assert!(g.statistics().is_none());
+    assert!(archive_path.join("Graph.archive").exists());
+}
+
+fn karenina_with_broken_edges() -> Arc<dyn ResourceStorage> {
+    let lower = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let upper = flatdata::MemoryResourceStorage::new("/root/karenina.archive");
+    upper
+        .write("edges", "some other schema", &[])
+        .expect("could not replace edges");
+    flatdata::OverlayResourceStorage::new(upper, vec![lower])
+}
+
+#[test]
+fn open_coappearances_lazily() {
+    assert!(coappearances::Graph::open(karenina_with_broken_edges()).is_err());
+
+    let g = coappearances::Graph::open_lazy(karenina_with_broken_edges()).expect("invalid archive");
+    // formatting does not load any resources
+    assert!(format!("{:?}", g).contains("edges: <not loaded>"));
+    assert!(format!("{:?}", g).contains("strings: <not loaded>"));
+    assert_eq!(g.vertices().len(), 138);
+    let stats = g.statistics().as_ref().expect("statistics failed");
+    assert_eq!(stats.invariants().max_degree(), 71);
+    match g.validate_all().as_ref().map_err(|e| e.root_cause()) {
+        Err(flatdata::ResourceStorageError::WrongSignature { resource_name, .. })
+            if resource_name == "edges" => {}
+        otherwise => panic!("unexpected result: {:?}", otherwise),
+    }
+
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open_lazy(storage).expect("invalid archive");
+    g.validate_all().expect("invalid archive");
+    assert_eq!(g.edges().len(), 494);
+}
+
+#[test]
+fn report_schema_changes_of_coappearances() {
+    let lower = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let upper = flatdata::MemoryResourceStorage::new("/root/karenina.archive");
+    let schema = str::from_utf8(lower.read_resource("vertices.schema").unwrap())
+        .unwrap()
+        .replace("name_ref : u32 : 32;", "name_ref : u32 : 16;");
+    upper
+        .write("vertices", &schema, &[0; 16])
+        .expect("could not replace vertices");
+    let storage = flatdata::OverlayResourceStorage::new(upper, vec![lower]);
+
+    let err = coappearances::Graph::open(storage).expect_err("opened broken archive");
+    match err {
+        flatdata::ResourceStorageError::InArchive {
+            ref path,
+            ref source,
+        } if path == "Graph" => match **source {
+            flatdata::ResourceStorageError::WrongSignature {
+                ref resource_name,
+                ref changes,
+                ..
+            } if resource_name == "vertices" => assert_eq!(
+                *changes,
+                vec![flatdata::schema::SchemaChange::FieldWidthChanged {
+                    name: ".coappearances.Character.name_ref".into(),
+                    stored: 16,
+                    expected: 32,
+                }]
+            ),
+            ref otherwise => panic!("unexpected source: {:?}", otherwise),
+        },
+        ref otherwise => panic!("unexpected result: {:?}", otherwise),
+    }
+    assert_eq!(
+        err.to_string(),
+        "in archive Graph: schema of resource vertices does not match the expected schema: \
+         field .coappearances.Character.name_ref: width 16 -> 32"
+    );
+}
+
+#[test]
+fn open_coappearances_with_broken_optional_subarchive() {
+    let open_storage = || {
+        let lower = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+        let upper = flatdata::MemoryResourceStorage::new("/root/broken_statistics");
+        upper
+            .subdir("statistics")
+            .write("vertex_degrees", "some other schema", &[])
+            .expect("could not replace vertex_degrees");
+        flatdata::OverlayResourceStorage::new(upper, vec![lower])
+    };
+
+    let g = coappearances::Graph::open(open_storage()).expect("invalid archive");
+    assert!(g.statistics().is_none());
+
+    let g = coappearances::Graph::open_lazy(open_storage()).expect("invalid archive");
+    assert!(g.statistics().is_none());
+    g.validate_all().expect("invalid archive");
+
+    let g = coappearances::Graph::open_lazy(open_storage()).expect("invalid archive");
+    g.validate_all().expect("invalid archive");
+    assert!(g.statistics().is_none());
+}
+
+#[test]
+#[should_panic(expected = "failed to open resource edges")]
+fn access_broken_resource_of_lazily_opened_archive() {
+    let g = coappearances::Graph::open_lazy(karenina_with_broken_edges()).expect("invalid archive");
+    g.edges();
+}
+
+#[test]
+fn advise_coappearances_resources() {
+    let storage = flatdata::FileResourceStorage::with_load_mode(
+        "tests/coappearances/karenina.archive",
+        flatdata::LoadMode::Populate,
+    );
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    g.advise("edges", flatdata::Advice::Sequential)
+        .expect("advise failed");
+    g.advise("vertices_data", flatdata::Advice::Random)
+        .expect("advise failed");
+    g.advise("statistics", flatdata::Advice::WillNeed)
+        .expect("advise failed");
+    g.advise_all(flatdata::Advice::Normal)
+        .expect("advise failed");
+    assert_eq!(
+        g.advise("unknown", flatdata::Advice::Normal)
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+    assert_eq!(g.edges().len(), 494);
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn report_coappearances_residency() {
+    let storage = flatdata::FileResourceStorage::with_load_mode(
+        "tests/coappearances/karenina.archive",
+        flatdata::LoadMode::Populate,
+    );
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    let report = g.residency().expect("residency failed");
+    for name in &[
+        "vertices",
+        "edges",
+        "vertices_data_index",
+        "vertices_data",
+        "chapters",
+        "statistics/invariants",
+        "statistics/vertex_degrees",
+    ] {
+        let residency = report.resources[*name];
+        assert!(residency.total_pages > 0, "{}", name);
+        assert_eq!(residency.resident_pages, residency.total_pages, "{}", name);
+    }
+    let total = report.total();
+    assert_eq!(total.resident_pages, total.total_pages);
+
+    // subarchives of lazily opened archives are not opened by reporting
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open_lazy(storage).expect("invalid archive");
+    let report = g.residency().expect("residency failed");
+    assert!(report.resources.contains_key("vertices"));
+    assert!(!report.resources.contains_key("statistics/invariants"));
+    assert!(format!("{:?}", g).contains("statistics: <not loaded>"));
+    g.statistics().as_ref().expect("statistics failed");
+    let report = g.residency().expect("residency failed");
+    assert!(report.resources.contains_key("statistics/invariants"));
+}
+
+#[test]
+fn warm_up_coappearances() {
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    let progress = g.warm_up(None).wait().expect("warm up failed");
+    // meta, vertices, edges, chapters, vertices_data_index, vertices_data,
+    // strings, statistics/invariants, statistics/vertex_degrees
+    assert_eq!(progress.resources_total, 9);
+    assert_eq!(progress.resources_done, 9);
+    assert_eq!(progress.bytes_done, progress.bytes_total);
+    assert!(progress.bytes_total > 0);
+    #[cfg(target_os = "linux")]
+    {
+        let total = g.residency().expect("residency failed").total();
+        assert_eq!(total.resident_pages, total.total_pages);
+    }
+
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open_lazy(storage).expect("invalid archive");
+    let progress = g.warm_up(None).wait().expect("warm up failed");
+    assert_eq!(progress.resources_total, 7);
+    assert!(format!("{:?}", g).contains("statistics: <not loaded>"));
+}
+
+#[test]
+fn list_coappearances_resources() {
+    let source_archive_path = path::PathBuf::from("tests/coappearances/karenina.archive");
+    let bundle_path = env::temp_dir().join("list_coappearances_resources/karenina.bundle");
+    flatdata::convert_directory_to_bundle(&source_archive_path, &bundle_path)
+        .expect("could not convert archive to bundle");
+
+    let storages: Vec<Arc<dyn ResourceStorage>> = vec![
+        flatdata::FileResourceStorage::new(source_archive_path),
+        flatdata::BundleResourceStorage::open(&bundle_path).expect("invalid bundle"),
+    ];
+    for storage in storages {
+        let resources = storage.list_resources().expect("listing failed");
+        assert_eq!(resources.len(), 16);
+        assert!(resources.contains(&"Graph.archive".to_string()));
+        assert!(resources.contains(&"vertices_data_index.schema".to_string()));
+        assert_eq!(
+            storage.list_subdirs().expect("listing failed"),
+            vec!["statistics"]
+        );
+
+        let statistics = storage.subdir("statistics");
+        assert_eq!(
+            statistics.list_resources().expect("listing failed"),
+            vec![
+                "Statistics.archive",
+                "Statistics.archive.schema",
+                "invariants",
+                "invariants.schema",
+                "vertex_degrees",
+                "vertex_degrees.schema",
+            ]
+        );
+        assert!(statistics
+            .list_subdirs()
+            .expect("listing failed")
+            .is_empty());
+    }
+}
+
+#[test]
+fn copy_coappearances_between_storages() {
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+
+    let memory_storage = flatdata::MemoryResourceStorage::new("/root/karenina.archive");
+    let in_memory = g
+        .copy_to_verified(memory_storage.clone())
+        .expect("copy failed");
+    assert_eq!(in_memory.vertices().len(), 138);
+    assert_eq!(in_memory.edges().len(), 494);
+    let stats = in_memory.statistics().as_ref().expect("statistics failed");
+    assert_eq!(stats.invariants().max_degree(), 71);
+
+    let archive_path = env::temp_dir().join("copy_coappearances_between_storages/karenina.archive");
+    if archive_path.exists() {
+        fs::remove_dir_all(&archive_path).expect("could not remove already existing archive");
+    }
+    let file_storage =
+        flatdata::FileResourceStorage::create_staged(&archive_path).expect("staging failed");
+    let on_disk = in_memory.copy_to(file_storage).expect("copy failed");
+    assert_eq!(on_disk.edges().len(), 494);
+    let source_archive_path = path::PathBuf::from("tests/coappearances/karenina.archive");
+    for resource_name in &[
+        "Graph.archive",
+        "meta",
+        "vertices",
+        "edges",
+        "vertices_data",
+        "strings",
+    ] {
+        assert!(compare_resource(
+            &source_archive_path,
+            &archive_path,
+            resource_name
+        ));
+    }
+    assert!(compare_resource(
+        &source_archive_path.join("statistics"),
+        &archive_path.join("statistics"),
+        "vertex_degrees"
+    ));
+}
+
+#[test]
+fn copy_only_resources_of_coappearances() {
+    let storage = flatdata::MemoryResourceStorage::new("/root/karenina.archive");
+    flatdata::copy_archive(
+        &*flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive"),
+        &*storage,
+    )
+    .expect("copy failed");
+    storage
+        .write("unrelated", "schema", &[1, 2, 3])
+        .expect("could not write unrelated resource");
+    storage
+        .subdir("other")
+        .write("unrelated", "schema", &[4])
+        .expect("could not write unrelated resource");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+
+    let copy = flatdata::MemoryResourceStorage::new("/root/copy.archive");
+    g.copy_to_verified(copy.clone()).expect("copy failed");
+    let snapshot = copy.snapshot();
+    assert_eq!(snapshot.len(), 22);
+    assert!(snapshot.contains_key("statistics/invariants.schema"));
+    assert!(!snapshot.contains_key("unrelated"));
+    assert!(!snapshot.contains_key("other/unrelated"));
+
+    let archive_path = env::temp_dir().join("copy_only_resources_of_coappearances");
+    if archive_path.exists() {
+        fs::remove_dir_all(&archive_path).expect("could not remove already existing archive");
+    }
+    fs::create_dir_all(archive_path.join("statistics/invariants"))
+        .expect("could not create directory");
+    let err = g
+        .copy_to(flatdata::FileResourceStorage::new(&archive_path))
+        .expect_err("copied onto a directory");
+    match err {
+        flatdata::ResourceStorageError::InArchive {
+            ref path,
+            ref source,
+        } if path == "Graph/statistics" => match **source {
+            flatdata::ResourceStorageError::Io(_, ref resource_name) => {
+                assert_eq!(resource_name, "invariants")
+            }
+            _ => panic!("unexpected error: {}", err),
+        },
+        _ => panic!("unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn restore_coappearances_from_memory_snapshot() {
+    let memory_storage = flatdata::MemoryResourceStorage::new("/root/karenina.archive");
+    flatdata::copy_archive(
+        &*flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive"),
+        &*memory_storage,
+    )
+    .expect("copy failed");
+    let snapshot = memory_storage.snapshot();
+    assert_eq!(snapshot.len(), 22);
+    assert!(snapshot.contains_key("statistics/vertex_degrees"));
+
+    let restored = flatdata::MemoryResourceStorage::from_snapshot("/root/restored", snapshot);
+    let g = coappearances::Graph::open(restored.clone()).expect("invalid archive");
+    assert_eq!(g.edges().len(), 494);
+    let stats = g.statistics().as_ref().expect("statistics failed");
+    assert_eq!(stats.invariants().max_degree(), 71);
+
+    let bundle_path =
+        env::temp_dir().join("restore_coappearances_from_memory_snapshot/karenina.bundle");
+    fs::create_dir_all(bundle_path.parent().unwrap()).expect("could not create dir");
+    flatdata::copy_archive(
+        &*restored,
+        &*flatdata::BundleResourceStorage::create(&bundle_path),
+    )
+    .expect("dump failed");
+    let storage = flatdata::BundleResourceStorage::open(&bundle_path).expect("invalid bundle");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    assert_eq!(g.vertices().len(), 138);
+}
+
+#[test]
+fn stream_statistics_to_bundle() {
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open(storage).expect("invalid archive");
+    let orig = g.statistics().as_ref().expect("statistics failed");
+
+    let bundle_path = env::temp_dir().join("stream_statistics_to_bundle/statistics.bundle");
+    fs::create_dir_all(bundle_path.parent().unwrap()).expect("could not create dir");
+    let output = io::BufWriter::new(fs::File::create(&bundle_path).expect("create failed"));
+    let storage = flatdata::StreamingResourceStorage::new(output).expect("streaming failed");
+    let builder = coappearances::StatisticsBuilder::new(storage).expect("could not create archive");
+    builder
+        .set_invariants(orig.invariants())
+        .expect("set_invariants failed");
+    let mut vertex_degrees = builder
+        .start_vertex_degrees()
+        .expect("start_vertex_degrees failed");
+    for deg in orig.vertex_degrees().iter() {
+        vertex_degrees.grow().expect("grow failed").fill_from(&deg);
+    }
+    vertex_degrees.close().expect("close failed");
+    builder.finish().expect("finish failed");
+
+    let storage = flatdata::BundleResourceStorage::open(&bundle_path).expect("invalid bundle");
+    let copy = coappearances::Statistics::open(storage).expect("invalid archive");
+    assert_eq!(orig.invariants(), copy.invariants());
+    assert_eq!(orig.vertex_degrees().len(), copy.vertex_degrees().len());
+}
+
+#[test]
+fn open_coappearances_written_with_older_schema() {
+    let storage = flatdata::MemoryResourceStorage::new("/root/karenina.archive");
+    flatdata::copy_archive(
+        &*flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive"),
+        &*storage,
+    )
+    .expect("copy failed");
+
+    // an older schema without statistics and without binary relations
+    let mut snapshot = storage.snapshot();
+    snapshot.retain(|name, _| !name.starts_with("statistics/"));
+    for (name, data) in snapshot.iter_mut() {
+        if name.ends_with(".schema") {
+            let schema = str::from_utf8(data).expect("invalid schema");
+            let schema = schema
+                .replace(
+                    "    @optional\n    statistics : archive .coappearances.Statistics;\n",
+                    "",
+                )
+                .replace(", .coappearances.BinaryRelation >", " >");
+            *data = schema.into_bytes();
+        }
+    }
+    let storage = flatdata::MemoryResourceStorage::from_snapshot("/root/older", snapshot);
+
+    match coappearances::Graph::open(storage.clone())
+        .as_ref()
+        .map_err(|e| e.root_cause())
+    {
+        Err(flatdata::ResourceStorageError::WrongSignature { .. }) => (),
+        otherwise => panic!("unexpected result: {:?}", otherwise),
+    }
+    let (g, additions) =
+        coappearances::Graph::open_compatible(storage).expect("incompatible archive");
+    assert_eq!(
+        additions,
+        vec![
+            flatdata::SchemaAddition::Resource(".coappearances.Graph.statistics".into()),
+            flatdata::SchemaAddition::VariadicType {
+                resource: ".coappearances.Graph.vertices_data".into(),
+                type_name: ".coappearances.BinaryRelation".into(),
+            },
+        ]
+    );
+    assert_eq!(g.vertices().len(), 138);
+    assert_eq!(g.vertices_data().len(), 138);
+    assert!(g.statistics().is_none());
+}
+
+#[test]
+fn parse_coappearances_schemas() {
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let mut num_schemas = 0;
+    for dir in &["", "statistics"] {
+        let storage = if dir.is_empty() {
+            storage.clone()
+        } else {
+            storage.subdir(dir)
+        };
+        for name in storage.list_resources().expect("list failed") {
+            if !name.ends_with(".schema") {
+                continue;
+            }
+            let schema = str::from_utf8(storage.read_resource(&name).expect("read failed"))
+                .expect("invalid schema");
+            let schema = schema
+                .strip_prefix("index(")
+                .and_then(|schema| schema.strip_suffix(')'))
+                .unwrap_or(schema);
+            flatdata::schema::parse(schema)
+                .unwrap_or_else(|e| panic!("invalid schema {}: {}", name, e));
+            num_schemas += 1;
+        }
+    }
+    assert_eq!(num_schemas, 11);
+
+    let schema = flatdata::schema::parse(coappearances::Graph::SCHEMA).expect("invalid schema");
+    match schema.find(".coappearances.Statistics") {
+        Some(flatdata::schema::Declaration::Archive(statistics)) => {
+            assert_eq!(statistics.resources.len(), 2)
+        }
+        otherwise => panic!("unexpected declaration: {:?}", otherwise),
+    }
+}
+
+#[test]
+fn read_coappearances_dynamically() {
+    use flatdata::{DynamicArchive, DynamicResource, Value};
+
+    let storage = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let g = coappearances::Graph::open(storage.clone()).expect("invalid archive");
+    let archive = DynamicArchive::open(storage).expect("invalid archive");
+    assert_eq!(archive.name(), "Graph");
+    assert_eq!(
+        archive.resource_names().collect::<Vec<_>>(),
+        vec![
+            "meta",
+            "vertices",
+            "edges",
+            "vertices_data",
+            "chapters",
+            "strings",
+            "statistics"
+        ]
+    );
+
+    let unsigned = |value: u32| Some(Value::Unsigned(u64::from(value)));
+    match archive.resource("meta") {
+        Some(DynamicResource::Instance(meta)) => {
+            assert_eq!(meta.name(), ".coappearances.Meta");
+            assert_eq!(meta.get("title_ref"), unsigned(g.meta().title_ref()));
+            assert_eq!(meta.get("author_ref"), unsigned(g.meta().author_ref()));
+        }
+        otherwise => panic!("unexpected resource: {:?}", otherwise),
+    }
+    match archive.resource("edges") {
+        Some(DynamicResource::Vector(edges)) => {
+            assert_eq!(edges.len(), g.edges().len());
+            for (edge, expected) in edges.iter().zip(g.edges().iter()) {
+                assert_eq!(edge.get("a_ref"), unsigned(expected.a_ref()));
+                assert_eq!(edge.get("b_ref"), unsigned(expected.b_ref()));
+                assert_eq!(edge.get("count"), unsigned(expected.count()));
+                assert_eq!(
+                    edge.get("first_chapter_ref"),
+                    unsigned(expected.first_chapter_ref())
+                );
+            }
+        }
+        otherwise => panic!("unexpected resource: {:?}", otherwise),
+    }
+    match archive.resource("vertices_data") {
+        Some(DynamicResource::Multivector(vertices_data)) => {
+            assert_eq!(vertices_data.len(), g.vertices_data().len());
+            for (item, expected) in vertices_data.iter().zip(g.vertices_data().iter()) {
+                for (element, expected) in item.zip(expected) {
+                    match expected {
+                        coappearances::RefVerticesData::Nickname(x) => {
+                            assert_eq!(element.get("ref"), unsigned(x.ref_()))
+                        }
+                        coappearances::RefVerticesData::Description(x) => {
+                            assert_eq!(element.get("ref"), unsigned(x.ref_()))
+                        }
+                        coappearances::RefVerticesData::UnaryRelation(x) => {
+                            assert_eq!(element.name(), ".coappearances.UnaryRelation");
+                            assert_eq!(element.get("to_ref"), unsigned(x.to_ref()));
+                        }
+                        coappearances::RefVerticesData::BinaryRelation(x) => {
+                            assert_eq!(element.get("to_b_ref"), unsigned(x.to_b_ref()))
+                        }
+                    }
+                }
+            }
+        }
+        otherwise => panic!("unexpected resource: {:?}", otherwise),
+    }
+    match archive.resource("strings") {
+        Some(DynamicResource::RawData(strings)) => assert_eq!(strings, g.strings()),
+        otherwise => panic!("unexpected resource: {:?}", otherwise),
+    }
+    let statistics = match archive.resource("statistics") {
+        Some(DynamicResource::Archive(statistics)) => statistics,
+        otherwise => panic!("unexpected resource: {:?}", otherwise),
+    };
+    match statistics.resource("invariants") {
+        Some(DynamicResource::Instance(invariants)) => {
+            assert_eq!(invariants.get("max_degree"), unsigned(71))
+        }
+        otherwise => panic!("unexpected resource: {:?}", otherwise),
+    }
+}
+
+#[test]
+fn reject_corrupt_coappearances_index_dynamically() {
+    let lower = flatdata::FileResourceStorage::new("tests/coappearances/karenina.archive");
+    let schema = str::from_utf8(lower.read_resource("vertices_data_index.schema").unwrap())
+        .unwrap()
+        .to_string();
+    let mut index = lower.read("vertices_data_index", &schema).unwrap().to_vec();
+    index[4..8].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+    let upper = flatdata::MemoryResourceStorage::new("/root/karenina.archive");
+    upper
+        .write("vertices_data_index", &schema, &index)
+        .expect("could not replace index");
+    let storage = flatdata::OverlayResourceStorage::new(upper, vec![lower]);
+
+    let err = flatdata::DynamicArchive::open(storage).expect_err("opened corrupt archive");
+    match *err.root_cause() {
+        flatdata::ResourceStorageError::Io(ref e, ref resource_name) => {
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(resource_name, "vertices_data_index");
+        }
+        _ => panic!("unexpected error: {}", err),
+    }
+}